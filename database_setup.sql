@@ -1,33 +0,0 @@
--- Database setup for FoodAgent authentication system
--- Run this to create the required tables
-
-USE food_registry;
-
--- Users table for authentication
-CREATE TABLE IF NOT EXISTS users (
-    id INT AUTO_INCREMENT PRIMARY KEY,
-    email VARCHAR(255) UNIQUE NOT NULL,
-    username VARCHAR(100) UNIQUE NOT NULL,
-    password_hash VARCHAR(255) NOT NULL,
-    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
-);
-
--- User conversations table to track chat sessions
-CREATE TABLE IF NOT EXISTS user_conversations (
-    id INT AUTO_INCREMENT PRIMARY KEY,
-    user_id INT NOT NULL,
-    session_id VARCHAR(36) NOT NULL,
-    title VARCHAR(255) DEFAULT 'New Conversation',
-    created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-    updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
-    FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
-    INDEX idx_user_sessions (user_id, session_id),
-    UNIQUE KEY unique_user_session (user_id, session_id)
-);
-
--- Create indexes for better performance
-CREATE INDEX IF NOT EXISTS idx_users_email ON users(email);
-CREATE INDEX IF NOT EXISTS idx_users_username ON users(username);
-CREATE INDEX IF NOT EXISTS idx_conversations_user_id ON user_conversations(user_id);
-CREATE INDEX IF NOT EXISTS idx_conversations_updated_at ON user_conversations(updated_at); 
\ No newline at end of file
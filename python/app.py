@@ -4,6 +4,7 @@ from fastapi.security import HTTPBearer, HTTPAuthorizationCredentials
 from pydantic import BaseModel, EmailStr
 from passlib.context import CryptContext
 from jose import JWTError, jwt
+from cryptography.fernet import Fernet, InvalidToken
 from datetime import datetime, timedelta, timezone
 from typing import Optional
 import uuid
@@ -12,7 +13,8 @@ import json
 import mysql.connector
 import os
 from dotenv import load_dotenv
-from chat import create_new_conversation, process_message
+from chat import create_new_conversation, process_message, handler as food_handler
+from food_agent.food_agent import NotFoundError, ValidationError
 from urllib.parse import urlparse
 from prompts import conversational_orchestrator_prompt
 
@@ -29,11 +31,18 @@ app.add_middleware(
     allow_headers=["*"],
 )
 
-# Initialize database tables on startup
+# Every table, including users and conversations, is owned by the Rust migrations
 @app.on_event("startup")
 async def startup_event():
-    create_messages_table()
-    create_google_tokens_table()
+    applied = await food_handler.migrate_async()
+    print(f"DEBUG: Schema up to date, applied migrations: {applied}")
+    # Scan for expiring food in this process unless the standalone expiry_scanner runs instead
+    if os.getenv("EXPIRY_SCHEDULER", "").lower() in ("1", "true", "yes"):
+        food_handler.start_expiry_scheduler(None, os.getenv("NOTIFY_WEBHOOK_URL"), None, os.getenv("SMTP_URL"))
+
+@app.on_event("shutdown")
+async def shutdown_event():
+    food_handler.stop_expiry_scheduler()
 
 redis_client = redis.Redis(host='localhost', port=6379, decode_responses=True)
 security = HTTPBearer()
@@ -44,6 +53,11 @@ SECRET_KEY = os.getenv("SECRET_KEY")
 ALGORITHM = "HS256"
 ACCESS_TOKEN_EXPIRE_MINUTES = 30
 
+# CalDAV passwords are stored encrypted with this Fernet key (from Fernet.generate_key()).
+# Without it, CalDAV calendars cannot be connected.
+CALENDAR_SECRET_KEY = os.getenv("CALENDAR_SECRET_KEY")
+calendar_cipher = Fernet(CALENDAR_SECRET_KEY) if CALENDAR_SECRET_KEY else None
+
 # Database connection
 def get_db_connection():
     connection_string = os.getenv("DATABASE_URL", "mysql://root:@localhost:3306/food_registry")
@@ -59,47 +73,6 @@ def get_db_connection():
         database=parsed.path.lstrip('/')
     )
 
-def create_messages_table():
-    """Create the messages table if it doesn't exist"""
-    conn = get_db_connection()
-    cursor = conn.cursor()
-    
-    cursor.execute("""
-        CREATE TABLE IF NOT EXISTS conversation_messages (
-            id INT AUTO_INCREMENT PRIMARY KEY,
-            user_id INT NOT NULL,
-            session_id VARCHAR(255) NOT NULL,
-            message TEXT NOT NULL,
-            response TEXT NOT NULL,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            FOREIGN KEY (user_id) REFERENCES users(id),
-            INDEX idx_user_session (user_id, session_id),
-            INDEX idx_created_at (created_at)
-        )
-    """)
-    
-    conn.commit()
-    cursor.close()
-    conn.close()
-    print("DEBUG: Messages table created/verified")
-
-def create_google_tokens_table():
-    """Create table for storing Google tokens"""
-    conn = get_db_connection()
-    cursor = conn.cursor()
-    cursor.execute("""
-        CREATE TABLE IF NOT EXISTS user_google_tokens (
-            user_id INT PRIMARY KEY,
-            token_data JSON NOT NULL,
-            created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
-            updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
-            FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
-        )
-    """)
-    conn.commit()
-    cursor.close()
-    conn.close()
-
 # Pydantic models
 class UserCreate(BaseModel):
     email: EmailStr
@@ -127,6 +100,22 @@ class GoogleAuthResponse(BaseModel):
     success: bool
     message: str
 
+class CalDavSettings(BaseModel):
+    url: str
+    username: Optional[str] = None
+    password: Optional[str] = None
+
+class ReminderPreferences(BaseModel):
+    lead_days: Optional[int] = None
+    time: Optional[str] = None
+    timezone: Optional[str] = None
+    mode: Optional[str] = None  # "item" or "digest"
+
+class EmailDigestSettings(BaseModel):
+    frequency: Optional[str] = None  # "daily", "weekly" or "off"
+    email: Optional[EmailStr] = None  # defaults to the account's address
+    weekday: Optional[str] = None  # for weekly digests, e.g. "friday"
+
 # Password utilities
 def verify_password(plain_password, hashed_password):
     return pwd_context.verify(plain_password, hashed_password)
@@ -339,11 +328,14 @@ async def handle_google_calendar_callback(
             'token_uri': credentials.token_uri,
             'client_id': credentials.client_id,
             'client_secret': credentials.client_secret,
-            'scopes': credentials.scopes
+            'scopes': credentials.scopes,
+            'expiry': credentials.expiry.isoformat() + 'Z' if credentials.expiry else None
         }
         
         # Store token for user
         store_google_token(current_user["id"], token_data)
+        # Connecting Google makes it the user's calendar again
+        store_calendar_settings(current_user["id"], "google")
         
         return GoogleAuthResponse(
             success=True,
@@ -366,6 +358,70 @@ async def get_google_calendar_status(current_user: dict = Depends(get_current_us
         "message": "Google Calendar is connected" if token_data else "Google Calendar not connected"
     }
 
+@app.put("/calendar/caldav")
+async def connect_caldav_calendar(settings: CalDavSettings, current_user: dict = Depends(get_current_user)):
+    """Send the user's expiry reminders to a CalDAV calendar (e.g. Nextcloud) instead of Google"""
+    if settings.password and not calendar_cipher:
+        raise HTTPException(
+            status_code=status.HTTP_503_SERVICE_UNAVAILABLE,
+            detail="CalDAV passwords cannot be stored: CALENDAR_SECRET_KEY is not set",
+        )
+    store_calendar_settings(current_user["id"], "caldav", settings)
+    return {"provider": "caldav", "message": "Reminders will now be added to your CalDAV calendar"}
+
+@app.delete("/calendar/caldav")
+async def disconnect_caldav_calendar(current_user: dict = Depends(get_current_user)):
+    """Stop using CalDAV; reminders go to Google Calendar again if it is connected"""
+    store_calendar_settings(current_user["id"], "google")
+    return {"provider": "google", "message": "CalDAV calendar disconnected"}
+
+@app.get("/food/{food_id}/reminder.ics")
+async def download_food_reminder(food_id: int, current_user: dict = Depends(get_current_user)):
+    """Download a food item's expiry reminder for Apple Calendar, Thunderbird or any CalDAV client"""
+    try:
+        attachment = json.loads(await food_handler.reminder_ics_async(current_user["id"], food_id))
+    except NotFoundError as e:
+        raise HTTPException(status_code=404, detail=str(e))
+    return Response(
+        content=attachment["content"],
+        media_type=attachment["content_type"],
+        headers={"Content-Disposition": f'attachment; filename="{attachment["filename"]}"'},
+    )
+
+@app.get("/reminders/preferences")
+async def get_reminder_preferences(current_user: dict = Depends(get_current_user)):
+    """How many days ahead, at what time and in which time zone the user's reminders go off"""
+    return json.loads(await food_handler.reminder_preferences_async(current_user["id"]))
+
+@app.put("/reminders/preferences")
+async def update_reminder_preferences(preferences: ReminderPreferences, current_user: dict = Depends(get_current_user)):
+    """Change the user's reminder lead days, "HH:MM" time, IANA time zone or item/digest mode; omitted fields are kept"""
+    if preferences.lead_days is not None and preferences.lead_days < 0:
+        raise HTTPException(status_code=422, detail="lead_days cannot be negative")
+    try:
+        return json.loads(await food_handler.set_reminder_preferences_async(
+            current_user["id"], preferences.lead_days, preferences.time, preferences.timezone, preferences.mode
+        ))
+    except ValidationError as e:
+        raise HTTPException(status_code=422, detail=str(e))
+
+@app.get("/notifications/email")
+async def get_email_digest(current_user: dict = Depends(get_current_user)):
+    """The user's email digest subscription, or null when they have none"""
+    return json.loads(await food_handler.email_digest_async(current_user["id"]))
+
+@app.put("/notifications/email")
+async def update_email_digest(settings: EmailDigestSettings, current_user: dict = Depends(get_current_user)):
+    """Subscribe to daily or weekly email digests of expiring food, or unsubscribe with frequency 'off'"""
+    current = json.loads(await food_handler.email_digest_async(current_user["id"]))
+    email = settings.email or (None if current else current_user["email"])
+    try:
+        return json.loads(await food_handler.set_email_digest_async(
+            current_user["id"], settings.frequency, email, settings.weekday
+        ))
+    except ValidationError as e:
+        raise HTTPException(status_code=422, detail=str(e))
+
 # Chat endpoints (protected)
 @app.post("/chat")
 async def chat_endpoint(
@@ -389,7 +445,9 @@ async def chat_endpoint(
             user_context={
                 "user_id": user_id,
                 "has_google_calendar": get_google_token(user_id) is not None,
-                "google_token": get_google_token(user_id)
+                "calendar": get_calendar_settings(user_id),
+                # The Rust handler hands back a refreshed Google token to keep
+                "save_google_token": lambda token: store_google_token(user_id, token)
             }
         )
         
@@ -556,3 +614,60 @@ def get_google_token(user_id: int) -> dict:
     if result:
         return json.loads(result["token_data"])
     return None
+
+def encrypt_caldav_password(password: Optional[str]) -> Optional[str]:
+    """The password as stored in user_calendar_settings"""
+    if password is None:
+        return None
+    return calendar_cipher.encrypt(password.encode()).decode()
+
+def decrypt_caldav_password(user_id: int, stored: Optional[str]) -> Optional[str]:
+    """The password the CalDAV server expects, or None when it cannot be recovered"""
+    if stored is None:
+        return None
+    if not calendar_cipher:
+        print(f"DEBUG: CALENDAR_SECRET_KEY is not set; cannot read the CalDAV password of user {user_id}")
+        return None
+    try:
+        return calendar_cipher.decrypt(stored.encode()).decode()
+    except InvalidToken:
+        print(f"DEBUG: The CalDAV password of user {user_id} cannot be decrypted with CALENDAR_SECRET_KEY")
+        return None
+
+def store_calendar_settings(user_id: int, provider: str, caldav: Optional[CalDavSettings] = None):
+    """Choose which calendar provider the user's reminders go to"""
+    conn = get_db_connection()
+    cursor = conn.cursor()
+    url, username, password = (caldav.url, caldav.username, caldav.password) if caldav else (None, None, None)
+    password = encrypt_caldav_password(password)
+    cursor.execute(
+        "INSERT INTO user_calendar_settings (user_id, provider, caldav_url, caldav_username, caldav_password) "
+        "VALUES (%s, %s, %s, %s, %s) "
+        "ON DUPLICATE KEY UPDATE provider = VALUES(provider), caldav_url = VALUES(caldav_url), "
+        "caldav_username = VALUES(caldav_username), caldav_password = VALUES(caldav_password)",
+        (user_id, provider, url, username, password)
+    )
+    conn.commit()
+    cursor.close()
+    conn.close()
+
+def get_calendar_settings(user_id: int) -> Optional[dict]:
+    """The user's calendar settings in the shape PyCommandHandler.add_food expects, or None"""
+    conn = get_db_connection()
+    cursor = conn.cursor(dictionary=True)
+    cursor.execute("SELECT * FROM user_calendar_settings WHERE user_id = %s", (user_id,))
+    result = cursor.fetchone()
+    cursor.close()
+    conn.close()
+
+    if result and result["provider"] == "caldav":
+        return {
+            "provider": "caldav",
+            "url": result["caldav_url"],
+            "username": result["caldav_username"],
+            "password": decrypt_caldav_password(user_id, result["caldav_password"]),
+        }
+    token_data = get_google_token(user_id)
+    if token_data:
+        return {"provider": "google", "token": token_data}
+    return None
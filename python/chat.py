@@ -8,7 +8,13 @@ from dotenv import load_dotenv
 import json
 from react import collect_food_info
 from prompts import conversational_orchestrator_prompt
-from food_agent.food_agent import PyCommandHandler
+from food_agent.food_agent import (
+    PyCommandHandler,
+    ValidationError,
+    StorageError,
+    NotFoundError,
+    CalendarError,
+)
 from tools import tools
 
 load_dotenv()
@@ -21,8 +27,17 @@ food_collection_sessions = {}
 
 
 
-def handle_function_call(function_name, arguments, session_id=None):
-    """Handle the execution of called functions"""
+def keep_refreshed_token(result, save_google_token):
+    """Google access tokens expire hourly; keep the one the handler renewed"""
+    refreshed = json.loads(result).get("refreshed_token")
+    if refreshed and save_google_token:
+        save_google_token(refreshed)
+
+
+def handle_function_call(function_name, arguments, session_id=None, user_id=None, calendar_json=None,
+                         save_google_token=None):
+    """Handle the execution of called functions. `calendar_json` holds the user's calendar
+    settings so reminders follow items that are changed, moved, used up or deleted."""
     print(f"DEBUG: Function called: {function_name}")
     print(f"DEBUG: Arguments: {arguments}")
     print(f"DEBUG: Session ID: {session_id}")
@@ -48,25 +63,90 @@ def handle_function_call(function_name, arguments, session_id=None):
             
         elif function_name == "get_all_food":
             print("DEBUG: Executing get_all_food function")
-            result = handler.view_all_food()
+            result = json.loads(handler.view_all_food_json(user_id))
             print(f"DEBUG: get_all_food result: {result}")
-            return f" Food inventory: {result}"
+            return json.dumps({"items": result})
             
         elif function_name == "advanced_search":
             keyword = arguments.get("keyword", "")
             field = arguments.get("field", "name")
             print(f"DEBUG: Executing advanced_search with keyword='{keyword}', field='{field}'")
-            result = handler.search_storage(keyword, field)
+            result = json.loads(handler.search_storage_json(user_id, keyword, field))
             print(f"DEBUG: advanced_search result: {result}")
-            return f" Search results: {result}"
+            return json.dumps({"keyword": keyword, "field": field, "items": result})
             
+        elif function_name == "update_food":
+            food_id = arguments.get("food_id")
+            print(f"DEBUG: Executing update_food for item {food_id}")
+            result = handler.update_food_json(
+                user_id,
+                food_id,
+                name=arguments.get("name"),
+                stock_date=arguments.get("stock_date"),
+                food_type=arguments.get("food_type"),
+                storage_type=arguments.get("storage_type"),
+                quantity=arguments.get("quantity"),
+                expiry_date=arguments.get("expiry_date"),
+                calendar_json=calendar_json,
+                reminder_days=arguments.get("reminder_days"),
+            )
+            print(f"DEBUG: update_food result: {result}")
+            keep_refreshed_token(result, save_google_token)
+            return json.dumps({"updated": json.loads(result)["item"]})
+
+        elif function_name == "delete_food":
+            food_id = arguments.get("food_id")
+            print(f"DEBUG: Executing delete_food for item {food_id}")
+            result = handler.delete_food_json(user_id, food_id, calendar_json=calendar_json)
+            print(f"DEBUG: delete_food result: {result}")
+            keep_refreshed_token(result, save_google_token)
+            return result
+
+        elif function_name == "consume_food":
+            food_id = arguments.get("food_id")
+            amount = arguments.get("amount", "")
+            print(f"DEBUG: Executing consume_food for item {food_id} with amount='{amount}'")
+            result = handler.consume_food_json(user_id, food_id, amount, calendar_json=calendar_json)
+            print(f"DEBUG: consume_food result: {result}")
+            keep_refreshed_token(result, save_google_token)
+            return result
+
+        elif function_name == "move_food":
+            food_id = arguments.get("food_id")
+            storage_type = arguments.get("storage_type", "")
+            print(f"DEBUG: Executing move_food for item {food_id} to '{storage_type}'")
+            result = handler.move_food_json(user_id, food_id, storage_type, calendar_json=calendar_json)
+            print(f"DEBUG: move_food result: {result}")
+            keep_refreshed_token(result, save_google_token)
+            return json.dumps({"moved": json.loads(result)["item"]})
+
+        elif function_name == "expiring_food":
+            window = arguments.get("window", "week")
+            print(f"DEBUG: Executing expiring_food with window='{window}'")
+            result = handler.expiring_food_json(user_id, window)
+            print(f"DEBUG: expiring_food result: {result}")
+            return result
+
         else:
             print(f"DEBUG: Unknown function called: {function_name}")
             return f" Unknown function: {function_name}"
             
+    except ValidationError as e:
+        # Bad input (date, unit, food type...) the user can correct
+        print(f"DEBUG: Invalid input for {function_name}: {str(e)}")
+        return json.dumps({"error": "invalid_input", "message": str(e)})
+    except NotFoundError as e:
+        print(f"DEBUG: Item not found in {function_name}: {str(e)}")
+        return json.dumps({"error": "not_found", "message": str(e)})
+    except CalendarError as e:
+        print(f"DEBUG: Calendar error in {function_name}: {str(e)}")
+        return json.dumps({"error": "calendar", "message": str(e)})
+    except StorageError as e:
+        print(f"DEBUG: Storage error in {function_name}: {str(e)}")
+        return json.dumps({"error": "storage", "message": "The food database is unavailable right now, please try again later."})
     except Exception as e:
         print(f"DEBUG: Exception in handle_function_call: {str(e)}")
-        return f" Error executing {function_name}: {str(e)}"
+        return json.dumps({"error": "failed", "message": f"Error executing {function_name}: {str(e)}"})
 
 def process_message(user_input, conversation_history=None, session_id=None, user_context=None):
     """Process user message and return AI response"""
@@ -84,11 +164,11 @@ def process_message(user_input, conversation_history=None, session_id=None, user
             # This is a food collection response - process directly
             session_state = food_collection_sessions[session_id]
             
-            # Prepare user context with Google token if available
+            # Prepare user context with calendar settings if available
             enhanced_user_context = user_context.copy() if user_context else {}
             if user_context and user_context.get("user_id"):
-                # Get Google token from app.py context (we'll need to pass this)
-                enhanced_user_context["google_token"] = user_context.get("google_token")
+                # Calendar settings come from app.py context
+                enhanced_user_context["calendar"] = user_context.get("calendar")
             
             session_state, response, is_complete = collect_food_info(user_input, session_state, enhanced_user_context)
             
@@ -205,7 +285,12 @@ Do not provide any other response. Just the single word classification."""},
             print(f"DEBUG: Executing tool call - function: {function_name}, arguments: {arguments}")
             
             # Execute the function
-            function_result = handle_function_call(function_name, arguments, session_id)
+            user_id = user_context.get("user_id") if user_context else None
+            calendar_settings = user_context.get("calendar") if user_context else None
+            calendar_json = json.dumps(calendar_settings) if calendar_settings else None
+            save_google_token = user_context.get("save_google_token") if user_context else None
+            function_result = handle_function_call(function_name, arguments, session_id, user_id, calendar_json,
+                                                   save_google_token)
             
             print(f"DEBUG: Tool call result: {function_result}")
             
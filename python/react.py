@@ -6,7 +6,7 @@ sys.path.append(os.path.dirname(os.path.dirname(os.path.abspath(__file__))))
 from openai import OpenAI
 from dotenv import load_dotenv
 from utils import add_food
-from food_agent.food_agent import PyCommandHandler
+from food_agent.food_agent import PyCommandHandler, FoodAgentError
 
 load_dotenv()
 
@@ -54,14 +54,15 @@ def collect_food_info(user_input=None, session_state=None, user_context=None):
         **food type** is the type of food that was mentioned like fruit, vegetable, breakfast_cereal, meat, dairy, non_dairy, water and edible oils. Food type
         strictly has to be one of these.
         
-        **storage type** is how the food is being stored. Like "cold" - if it is being stored in the refrigerator or other cold storage equipment and "warm"
-        if it is not being stored in a cold storage equipment. Storage type strictly has to be either "cold" or "warm"
+        **storage type** is how the food is being stored. Like "cold" - if it is being stored in the refrigerator or other cold storage equipment, "frozen" - if it is
+        being stored in a freezer, "pantry" - if it is a dry good kept in a pantry or cupboard and "warm" if it is otherwise kept at room temperature.
+        Storage type strictly has to be one of "cold", "frozen", "pantry" or "warm"
 
         **stock date** is the day the food was bought. It can be one of three types - "today", "yesterday" or an actual date of format "DD-MM-YYYY" as in (23-05-2025).
         Stock date strictly has to be one of this three.
 
-        **quantity** is the amount of food that is being added. and there are two valid units - **grams(g)** and **litres(l)**. a valid quantity submission has to be in these
-        units - you extract "50g" as in when the user says "50grams" or "5l" as in when the user says "5litres".
+        **quantity** is the amount of food that is being added. Valid units are mass (g, kg, oz, lb), volume (ml, l, cup, tbsp, tsp) and count (pieces, dozen, pack, loaf).
+        You extract "50g" as in when the user says "50grams", "500ml" when the user says "half a litre" and "6 pieces" when the user says "6 eggs".
 
         **expiry date** is the day that the food expires if any. A valid submission is a date in the format "DD-MM-YYYY". If the user says there is no expiry date, the 
         valid submission in this case is "none". Expiry date **strictly** has to be a date in the annotated format or "none"
@@ -106,9 +107,9 @@ def collect_food_info(user_input=None, session_state=None, user_context=None):
     - Only extract ONE piece of information per response
     - For name: just the food name (e.g. "apple", "beef")
     - For food_type: must be one of: fruit, vegetable, breakfast_cereal, meat, dairy, non_dairy, beverage, grains, edible_oils
-    - For storage_type: must be "cold" or "warm"  
+    - For storage_type: must be "cold", "frozen", "pantry" or "warm"
     - For stock_date: must be "today", "yesterday", or "DD-MM-YYYY"
-    - For quantity: must include unit like "50g" or "2l"
+    - For quantity: must include unit like "50g", "2kg", "500ml", "6 pieces" or "1 loaf"
     - For expiry_date: must be "DD-MM-YYYY" or "none"
     
     Only respond with field_name=value, nothing else.
@@ -157,19 +158,28 @@ def collect_food_info(user_input=None, session_state=None, user_context=None):
         if "yes" in confirm_result:
             # Pass user context to the handler for calendar integration
             user_id = user_context.get("user_id") if user_context else None
-            google_token_data = user_context.get("google_token") if user_context else None
-            google_token_json = json.dumps(google_token_data) if google_token_data else None
+            calendar_settings = user_context.get("calendar") if user_context else None
+            calendar_json = json.dumps(calendar_settings) if calendar_settings else None
             
-            result = handler.add_food(
-                name=food_info["name"],
-                stock_date=food_info["stock_date"],
-                food_type=food_info["food_type"],
-                storage_type=food_info["storage_type"],
-                quantity=food_info["quantity"],
-                expiry_date=food_info["expiry_date"],
-                user_id=user_id,
-                google_token_json=google_token_json,
-            )
+            try:
+                added = json.loads(handler.add_food_json(
+                    name=food_info["name"],
+                    stock_date=food_info["stock_date"],
+                    food_type=food_info["food_type"],
+                    storage_type=food_info["storage_type"],
+                    quantity=food_info["quantity"],
+                    expiry_date=food_info["expiry_date"],
+                    user_id=user_id,
+                    calendar_json=calendar_json,
+                ))
+                result = "Food stock added successfully!"
+                if added["calendar_message"]:
+                    result += "\n\n" + added["calendar_message"]
+                # Google access tokens expire hourly; keep the one the handler renewed
+                if added["refreshed_token"] and user_context.get("save_google_token"):
+                    user_context["save_google_token"](added["refreshed_token"])
+            except FoodAgentError as e:
+                result = f"Error: {e}"
             session_state["completed"] = True
             
             # Store food info for calendar message generation
@@ -186,7 +196,14 @@ def collect_food_info(user_input=None, session_state=None, user_context=None):
     if count == 7:
         # Your original fallback logic
         try:
-            fallback_result = add_init.add_new_food()
+            fallback_result = add_init.add_new_food({
+                "step": "name",
+                "prompt": "What food do you want me to add?",
+                "session_data": {},
+                "collected": {},
+                "complete": False,
+                "user_id": user_context.get("user_id") if user_context else None
+            })
             session_state["completed"] = True
             return session_state, f"Having trouble collecting food details, let me guide you step by step\n{fallback_result}", True
         except Exception as e:
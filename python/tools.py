@@ -51,4 +51,156 @@ tools = [{
             "additionalProperties": False
         }
     }
+},
+{
+    "type": "function",
+    "function": {
+        "name": "update_food",
+        "description": "Corrects details of a food item already in the inventory, identified by the #id shown in inventory and search results. Pass null for fields that should stay the same.",
+        "strict": True,
+        "parameters": {
+            "type": "object",
+            "required": [
+                "food_id",
+                "name",
+                "stock_date",
+                "food_type",
+                "storage_type",
+                "quantity",
+                "expiry_date",
+                "reminder_days"
+            ],
+            "properties": {
+                "food_id": {
+                    "type": "integer",
+                    "description": "The #id of the food item to update."
+                },
+                "name": {
+                    "type": ["string", "null"],
+                    "description": "New name of the food, or null."
+                },
+                "stock_date": {
+                    "type": ["string", "null"],
+                    "description": "New stock date as today, yesterday or DD-MM-YYYY, or null."
+                },
+                "food_type": {
+                    "type": ["string", "null"],
+                    "description": "New food type: vegetable, fruit, beverage, grains, breakfast-cereal, meat, dairy, non-dairy or edible-oils, or null."
+                },
+                "storage_type": {
+                    "type": ["string", "null"],
+                    "description": "New storage type: cold, warm, frozen or pantry, or null."
+                },
+                "quantity": {
+                    "type": ["string", "null"],
+                    "description": "New quantity such as 500g, 1.5l, 6 pieces or 1 loaf, or null."
+                },
+                "expiry_date": {
+                    "type": ["string", "null"],
+                    "description": "New expiry date as DD-MM-YYYY, or null."
+                },
+                "reminder_days": {
+                    "type": ["string", "null"],
+                    "description": "Days before expiry to be reminded on, comma-separated like 3,1,0 (0 is the expiry day itself), 'default' for the food type's usual schedule, or null."
+                }
+            },
+            "additionalProperties": False
+        }
+    }
+},
+{
+    "type": "function",
+    "function": {
+        "name": "delete_food",
+        "description": "Removes a food item from the inventory, identified by the #id shown in inventory and search results. Use this when the user has thrown the item away or no longer has it.",
+        "strict": True,
+        "parameters": {
+            "type": "object",
+            "required": [
+                "food_id"
+            ],
+            "properties": {
+                "food_id": {
+                    "type": "integer",
+                    "description": "The #id of the food item to remove."
+                }
+            },
+            "additionalProperties": False
+        }
+    }
+},
+{
+    "type": "function",
+    "function": {
+        "name": "consume_food",
+        "description": "Records that the user used part or all of a food item, identified by the #id shown in inventory and search results. The stored quantity is lowered and the item is marked used up when nothing is left.",
+        "strict": True,
+        "parameters": {
+            "type": "object",
+            "required": [
+                "food_id",
+                "amount"
+            ],
+            "properties": {
+                "food_id": {
+                    "type": "integer",
+                    "description": "The #id of the food item that was used."
+                },
+                "amount": {
+                    "type": "string",
+                    "description": "How much was used, in any unit of the same kind as the stored quantity, e.g. 200g, 0.5l or 2 pieces."
+                }
+            },
+            "additionalProperties": False
+        }
+    }
+},
+{
+    "type": "function",
+    "function": {
+        "name": "expiring_food",
+        "description": "Lists the user's food items that are already expired or expire today, tomorrow or within the next 7 days, soonest expiry first.",
+        "strict": True,
+        "parameters": {
+            "type": "object",
+            "required": [
+                "window"
+            ],
+            "properties": {
+                "window": {
+                    "type": "string",
+                    "enum": ["expired", "today", "tomorrow", "week"],
+                    "description": "Which expiry window to list."
+                }
+            },
+            "additionalProperties": False
+        }
+    }
+},
+{
+    "type": "function",
+    "function": {
+        "name": "move_food",
+        "description": "Moves a food item to another storage type, e.g. into the freezer, and recalculates its expiry date from today. The item is identified by the #id shown in inventory and search results.",
+        "strict": True,
+        "parameters": {
+            "type": "object",
+            "required": [
+                "food_id",
+                "storage_type"
+            ],
+            "properties": {
+                "food_id": {
+                    "type": "integer",
+                    "description": "The #id of the food item that was moved."
+                },
+                "storage_type": {
+                    "type": "string",
+                    "enum": ["cold", "warm", "frozen", "pantry"],
+                    "description": "Where the item is kept now."
+                }
+            },
+            "additionalProperties": False
+        }
+    }
 }]
\ No newline at end of file
@@ -74,9 +74,9 @@ class add_food():
 
     def get_storage_type(self, user_input=None, session_data=None):
         """Your original get_storage_type logic, modified for web apps"""
-        storage_types = ["cold", "warm"]
+        storage_types = ["cold", "warm", "frozen", "pantry"]
         prompt = storage_type_prompt
-        init_prompt = "How is the food being stored, are you using a cold or warm storage device?"
+        init_prompt = "How is the food being stored: in the fridge (cold), freezer (frozen), pantry or at room temperature (warm)?"
         return persistent_querying(storage_types, init_prompt, prompt, user_input, session_data)
 
     def add_new_food(self, step_data=None):
@@ -149,7 +149,7 @@ class add_food():
                 if is_complete:
                     collected["expiry_date"] = result
                     step_data["step"] = "storage_type"
-                    step_data["prompt"] = "How is the food being stored, are you using a cold or warm storage device?"
+                    step_data["prompt"] = "How is the food being stored: in the fridge (cold), freezer (frozen), pantry or at room temperature (warm)?"
                     session_data["expiry_date"] = new_session
                 else:
                     step_data["prompt"] = result
@@ -161,6 +161,7 @@ class add_food():
                     collected["storage_type"] = result
                     # Your original database save logic
                     db_result = self.handler.add_food(
+                        user_id=step_data.get("user_id"),
                         name=collected["name"],
                         stock_date=collected["stock_date"],
                         food_type=collected["food_type"],
@@ -183,18 +184,18 @@ class add_food():
             step_data["prompt"] = f"Error adding food: {e}"
             return step_data
 
-    def view_all_food(self):
+    def view_all_food(self, user_id: int):
         """Your original view_all_food logic - returns result instead of printing"""
         try:
-            result = self.handler.view_all_food()
+            result = self.handler.view_all_food(user_id)
             return result
         except Exception as e:
             return f"Error viewing food: {e}"
 
-    def search_food(self, query: str, field: str):
+    def search_food(self, user_id: int, query: str, field: str):
         """Your original search_food logic - returns result instead of printing"""
         try:
-            result = self.handler.advanced_search(query, field)
+            result = self.handler.search_storage(user_id, query, field)
             return result
         except Exception as e:
             return f"Error searching food: {e}"
@@ -242,7 +243,7 @@ def get_food_name(system_prompt: str, assertion_prompt: str, user_input=None, se
 
 def get_food_quantity(system_prompt: str, user_input=None, session_data=None):
     """Your original get_food_quantity logic, modified for web apps"""
-    pattern = r"\d+(l|g)"
+    pattern = r"\d+(\.\d+)?\s*(kg|g|oz|lb|ml|l|cups?|tbsp|tsp|pcs|pieces?|dozen|packs?|loaf|loaves)\b"
     
     if session_data is None:
         session_data = {
@@ -341,6 +342,7 @@ if __name__ == "__main__":
     
     print("Note: This module is now web-compatible. Use the functions with user_input parameters.")
     print("Original terminal mode preserved for testing:")
+    cli_user_id = int(os.getenv("LEGACY_FOOD_OWNER_ID", "0"))
     
     while True:
         funnel_result, funnel_session, funnel_complete = add_new_food.get_funnel()
@@ -349,13 +351,20 @@ if __name__ == "__main__":
         if funnel_complete:
             funnel = funnel_result
             if funnel == "add":
-                print(add_new_food.add_new_food())
+                print(add_new_food.add_new_food({
+                    "step": "name",
+                    "prompt": "What food do you want me to add?",
+                    "session_data": {},
+                    "collected": {},
+                    "complete": False,
+                    "user_id": cli_user_id
+                }))
             elif funnel == "view all":
-                print(add_new_food.view_all_food())
+                print(add_new_food.view_all_food(cli_user_id))
             elif funnel == "search":
                 query = input("Enter search query: ")
                 field = input("Enter field to search: ")
-                print(add_new_food.search_food(query, field))
+                print(add_new_food.search_food(cli_user_id, query, field))
             elif funnel == "exit":
                 print("Goodbye!")
                 break
@@ -0,0 +1,16 @@
+//! Standalone expiry scanner, for deployments that run it next to the web API rather than inside it.
+//! Pass `--once` to run a single scan, e.g. from cron. Notices and scan results are logged to
+//! stderr at info level; set `RUST_LOG=debug` for more detail.
+use std::process::ExitCode;
+
+fn main() -> ExitCode {
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+    let once = std::env::args().skip(1).any(|arg| arg == "--once");
+    match food_agent::run_expiry_scanner(once) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Expiry scanner failed: {}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
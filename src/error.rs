@@ -0,0 +1,90 @@
+// pyo3 0.19's `create_exception!` checks a cfg that newer compilers don't know about
+#![allow(unexpected_cfgs)]
+use pyo3::create_exception;
+use pyo3::exceptions::PyException;
+use pyo3::prelude::*;
+use std::fmt;
+
+create_exception!(food_agent, FoodAgentError, PyException, "Base class for every error raised by food_agent.");
+create_exception!(food_agent, ValidationError, FoodAgentError, "A date, unit, food type or other input could not be understood.");
+create_exception!(food_agent, StorageError, FoodAgentError, "The food database failed or rejected the operation.");
+create_exception!(food_agent, NotFoundError, FoodAgentError, "The user has no food item with the given id.");
+create_exception!(food_agent, CalendarError, FoodAgentError, "The calendar reminder could not be set up.");
+create_exception!(food_agent, NotificationError, FoodAgentError, "An expiry notification could not be delivered.");
+
+/// Adds the exception classes to the `food_agent` module so Python can catch them by type
+pub fn register_exceptions(py: Python<'_>, m: &PyModule) -> PyResult<()> {
+    m.add("FoodAgentError", py.get_type::<FoodAgentError>())?;
+    m.add("ValidationError", py.get_type::<ValidationError>())?;
+    m.add("StorageError", py.get_type::<StorageError>())?;
+    m.add("NotFoundError", py.get_type::<NotFoundError>())?;
+    m.add("CalendarError", py.get_type::<CalendarError>())?;
+    m.add("NotificationError", py.get_type::<NotificationError>())?;
+    Ok(())
+}
+
+/// Failures surfaced by the command handlers, split by what the caller can do about them
+#[derive(Debug)]
+pub enum FoodError {
+    /// The input could not be parsed (bad date, unit, food type...)
+    Validation(String),
+    /// The database rejected or failed the operation
+    Storage(String),
+    /// The user has no such food item
+    NotFound(String),
+    /// The calendar provider or the user's calendar credentials could not be used
+    Calendar(String),
+    /// A notification channel could not deliver an expiry notice
+    Notification(String),
+}
+
+impl FoodError {
+    pub fn item_not_found(food_id: i32) -> Self {
+        FoodError::NotFound(format!("No food item #{} found in storage", food_id))
+    }
+}
+
+impl fmt::Display for FoodError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FoodError::Validation(msg) => write!(f, "{}", msg),
+            FoodError::Storage(msg) => write!(f, "{}", msg),
+            FoodError::NotFound(msg) => write!(f, "{}", msg),
+            FoodError::Calendar(msg) => write!(f, "Calendar error: {}", msg),
+            FoodError::Notification(msg) => write!(f, "Notification error: {}", msg),
+        }
+    }
+}
+
+impl std::error::Error for FoodError {}
+
+/// `InputHandler` and the unit conversions report bad input as a plain message
+impl From<String> for FoodError {
+    fn from(msg: String) -> Self {
+        FoodError::Validation(msg)
+    }
+}
+
+impl From<mysql::Error> for FoodError {
+    fn from(e: mysql::Error) -> Self {
+        FoodError::Storage(e.to_string())
+    }
+}
+
+impl From<rusqlite::Error> for FoodError {
+    fn from(e: rusqlite::Error) -> Self {
+        FoodError::Storage(e.to_string())
+    }
+}
+
+impl From<FoodError> for PyErr {
+    fn from(e: FoodError) -> Self {
+        match e {
+            FoodError::Validation(msg) => ValidationError::new_err(msg),
+            FoodError::Storage(msg) => StorageError::new_err(msg),
+            FoodError::NotFound(msg) => NotFoundError::new_err(msg),
+            FoodError::Calendar(msg) => CalendarError::new_err(msg),
+            FoodError::Notification(msg) => NotificationError::new_err(msg),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use std::str::FromStr;
+use crate::error::FoodError;
+use crate::reminder::{ics_calendar, reminder_uid, ReminderEvent, ReminderPreferences};
+use crate::storage::{SearchResult, StoredFood};
+
+/// File formats the inventory can be exported to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    Csv,
+    Json,
+    /// An iCalendar feed of every item's expiry reminder
+    Ics,
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "json" => Ok(ExportFormat::Json),
+            "ics" | "ical" | "icalendar" => Ok(ExportFormat::Ics),
+            _ => Err(format!("Unsupported export format '{}'. Use 'csv', 'json' or 'ics'", s)),
+        }
+    }
+}
+
+/// Header of the CSV export, in [`SearchResult`] field order
+const CSV_COLUMNS: [&str; 11] = [
+    "id", "name", "stock_date", "food_type", "nutrient", "storage_type",
+    "expiry_date", "quantity", "quantity_value", "quantity_unit", "reminder_days",
+];
+
+/// Renders `foods` in the given format. CSV and JSON rows have the same fields as the
+/// JSON results of the other operations; reminders in the calendar feed are timed by the user's `preferences`.
+pub fn export_foods(foods: &[StoredFood], format: ExportFormat, preferences: &ReminderPreferences) -> Result<String, FoodError> {
+    match format {
+        ExportFormat::Csv => export_csv(foods),
+        ExportFormat::Json => {
+            let items: Vec<SearchResult> = foods.iter().map(SearchResult::from).collect();
+            serde_json::to_string_pretty(&items)
+                .map_err(|e| FoodError::Storage(format!("Could not write JSON export: {}", e)))
+        }
+        ExportFormat::Ics => {
+            let events: Vec<(String, ReminderEvent)> = foods
+                .iter()
+                .map(|stored| (reminder_uid(stored.id), ReminderEvent::for_food(&stored.food, preferences)))
+                .collect();
+            Ok(ics_calendar(&events, preferences.timezone))
+        }
+    }
+}
+
+fn export_csv(foods: &[StoredFood]) -> Result<String, FoodError> {
+    let write_error = |e: csv::Error| FoodError::Storage(format!("Could not write CSV export: {}", e));
+
+    // The header is written by hand so an empty inventory still exports a valid file
+    let mut writer = csv::WriterBuilder::new().has_headers(false).from_writer(Vec::new());
+    writer.write_record(CSV_COLUMNS).map_err(write_error)?;
+    for stored in foods {
+        writer.serialize(SearchResult::from(stored)).map_err(write_error)?;
+    }
+    let bytes = writer
+        .into_inner()
+        .map_err(|e| FoodError::Storage(format!("Could not write CSV export: {}", e)))?;
+    String::from_utf8(bytes).map_err(|e| FoodError::Storage(format!("Could not write CSV export: {}", e)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::NaiveDate;
+    use chrono_tz::Tz;
+    use crate::models::{FoodStock, FoodType, StorageType, Unit};
+
+    fn foods() -> Vec<StoredFood> {
+        let date = |day| NaiveDate::from_ymd_opt(2026, 3, day).unwrap();
+        let item = |id, name: &str, food_type: FoodType, quantity| StoredFood {
+            id,
+            food: FoodStock::new(
+                name.to_string(),
+                date(1),
+                food_type.clone(),
+                food_type.major_nutrient(),
+                StorageType::Cold,
+                date(id as u32 + 4),
+                quantity,
+            ),
+        };
+        vec![item(1, "milk, whole", FoodType::Dairy, Unit::Litres(1.5)), item(2, "apples", FoodType::Fruit, Unit::Pieces(6.0))]
+    }
+
+    fn export(foods: &[StoredFood], format: ExportFormat) -> String {
+        export_foods(foods, format, &ReminderPreferences::with_timezone(Tz::UTC)).unwrap()
+    }
+
+    #[test]
+    fn parses_formats_and_aliases() {
+        assert_eq!("CSV".parse(), Ok(ExportFormat::Csv));
+        assert_eq!("json".parse(), Ok(ExportFormat::Json));
+        assert_eq!("iCal".parse(), Ok(ExportFormat::Ics));
+        assert!("pdf".parse::<ExportFormat>().is_err());
+    }
+
+    #[test]
+    fn csv_has_a_header_and_one_quoted_row_per_item() {
+        let csv = export(&foods(), ExportFormat::Csv);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], CSV_COLUMNS.join(","));
+        assert!(lines[1].starts_with("1,\"milk, whole\",2026-03-01,"), "{}", lines[1]);
+        assert!(lines[1].contains(",1.5L,1500.0,ml,"), "{}", lines[1]);
+        assert!(lines[2].starts_with("2,apples,"), "{}", lines[2]);
+    }
+
+    #[test]
+    fn empty_inventory_still_exports_a_header() {
+        assert_eq!(export(&[], ExportFormat::Csv).trim_end(), CSV_COLUMNS.join(","));
+        assert_eq!(export(&[], ExportFormat::Json), "[]");
+    }
+
+    #[test]
+    fn json_items_match_search_results() {
+        let json: serde_json::Value = serde_json::from_str(&export(&foods(), ExportFormat::Json)).unwrap();
+        let items = json.as_array().unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["id"], 1);
+        assert_eq!(items[0]["name"], "milk, whole");
+        assert_eq!(items[0]["expiry_date"], "2026-03-05");
+        assert_eq!(items[0]["quantity_unit"], "ml");
+        for column in CSV_COLUMNS {
+            assert!(items[1].get(column).is_some(), "missing {}", column);
+        }
+    }
+
+    #[test]
+    fn ics_has_one_event_per_item() {
+        let ics = export(&foods(), ExportFormat::Ics);
+        assert!(ics.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
+        assert_eq!(ics.matches("BEGIN:VEVENT").count(), 2);
+        assert!(ics.contains(&format!("UID:{}", reminder_uid(1))));
+        assert!(ics.contains(&format!("UID:{}", reminder_uid(2))));
+    }
+}
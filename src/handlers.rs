@@ -1,9 +1,23 @@
 #![allow(warnings)]
+use std::collections::BTreeSet;
 use std::io;
-use chrono::{NaiveDate, Local, Duration, Utc};
-use crate::models::{FoodType, Unit, StorageType, MajorNutrient, FoodStock};
-use crate::storage::FoodDb;
-use crate::reminder::{create_calendar_event, create_calendar_event_with_user_token};
+use chrono::{NaiveDate, NaiveTime, Local, Duration, Utc, Weekday};
+use crate::models::{ExpiryWindow, FoodType, Unit, StorageType, MajorNutrient, FoodStock, FoodUpdate};
+use crate::error::FoodError;
+use crate::export::{export_foods, ExportFormat};
+use crate::import::{parse_rows, AcceptedRow, ImportFormat, ImportReport, RejectedRow};
+use crate::notify::{DigestFrequency, EmailDigest};
+use crate::storage::{
+    lock_repository, parse_query_to_date, ConsumeOutcome, Consumption, FoodRepository, LinkedEvent, PreferenceStore, Repository, SearchResult, StoredFood,
+    SEARCHABLE_FIELDS,
+};
+use std::sync::Mutex;
+use crate::shelf_life::ShelfLifeRules;
+use crate::reminder::{
+    digest_uid, generate_calendar_links, reminder_uid, CalendarAttachment, CalendarProvider, CalendarSettings, ReminderEvent,
+    ReminderMode, ReminderPreferences, MAX_LEAD_DAYS, MAX_REMINDER_STAGES,
+};
+use chrono_tz::Tz;
 use serde_json::Value;
 
 /// Handles user input operations
@@ -25,22 +39,11 @@ impl InputHandler {
     }
     /// Gets a food type from the user
     pub fn get_food_type(&self, input: &str) -> Result<FoodType, String> {
-        match input {
-            "vegetable" => Ok(FoodType::Vegetable),
-            "fruit" => Ok(FoodType::Fruit),
-            "grains" => Ok(FoodType::Grains),
-            "breakfast-cereal" => Ok(FoodType::Breakfast_cereal),
-            "beverage" => Ok(FoodType::Beverage),
-            "meat" => Ok(FoodType::Meat),
-            "dairy" => Ok(FoodType::Dairy),
-            "non-dairy" => Ok(FoodType::Non_dairy),
-            "edible-oils" => Ok(FoodType::Edible_oils),
-            _ => Err("Invalid food type".to_string()),
-        }
+        input.parse()
     }
     pub fn get_food_name(&self, input: &str) -> Result<String, String> {
         if input.trim().is_empty() {
-            println!("Invalid input");
+            log::debug!("Rejected an empty food name");
             Err("Input was empty".to_string())
         } else {
             Ok(input.to_string())
@@ -50,187 +53,720 @@ impl InputHandler {
 
     /// Gets a storage type from the user
     pub fn get_storage_type(&self, input: &str) -> Result<StorageType, String> {
-        match input {
-            "cold" => Ok(StorageType::Cold),
-            "warm" => Ok(StorageType::RoomTemperature),
-            _ => Err("Invalid storage type".to_string()),
+        input.parse()
+    }
+
+    /// Gets an expiry window from the user
+    pub fn get_expiry_window(&self, input: &str) -> Result<ExpiryWindow, String> {
+        match input.trim().to_lowercase().as_str() {
+            "expired" => Ok(ExpiryWindow::Expired),
+            "today" => Ok(ExpiryWindow::Today),
+            "tomorrow" => Ok(ExpiryWindow::Tomorrow),
+            "week" | "this week" => Ok(ExpiryWindow::ThisWeek),
+            _ => Err("Invalid expiry window. Use 'expired', 'today', 'tomorrow' or 'week'".to_string()),
         }
     }
 
-    /// Gets a quantity from the user
-    pub fn get_quantity(&self, input: &str) -> Result<Unit, String> {
+    /// Gets an IANA time zone name such as "Europe/Berlin" from the user
+    pub fn get_timezone(&self, input: &str) -> Result<Tz, String> {
+        input.trim().parse().map_err(|_| {
+            format!("Unknown time zone '{}'. Use an IANA name such as 'Europe/Berlin'", input.trim())
+        })
+    }
+
+    /// Gets a time of day in 24-hour "HH:MM" form from the user
+    pub fn get_time_of_day(&self, input: &str) -> Result<NaiveTime, String> {
+        NaiveTime::parse_from_str(input.trim(), "%H:%M")
+            .map_err(|_| format!("Invalid time '{}'. Use 24-hour HH:MM, e.g. 08:30", input.trim()))
+    }
 
-        let last_char = input.chars().last().ok_or("Invalid quantity format")?.to_ascii_lowercase();
-        let number_part = &input[..input.len().saturating_sub(1)].trim();
-
-        match last_char {
-            'g' => number_part.parse::<f32>()
-                .map(Unit::Grams)
-                .map_err(|_| "Invalid number format".to_string()),
-            'l' => number_part.parse::<f32>()
-                .map(Unit::Litres)
-                .map_err(|_| "Invalid number format".to_string()),
-            _ => Err("Invalid unit. Use 'g' for grams or 'l' for litres".to_string()),
+    /// Gets an email address such as "ann@example.com" from the user
+    pub fn get_email_address(&self, input: &str) -> Result<String, String> {
+        input
+            .trim()
+            .parse::<lettre::Address>()
+            .map(|address| address.to_string())
+            .map_err(|_| format!("Invalid email address '{}'", input.trim()))
+    }
+
+    /// Gets a day of the week such as "friday" or "fri" from the user
+    pub fn get_weekday(&self, input: &str) -> Result<Weekday, String> {
+        input
+            .trim()
+            .parse()
+            .map_err(|_| format!("Invalid weekday '{}'. Use a day name such as 'monday'", input.trim()))
+    }
+
+    /// Gets a reminder schedule from the user: days before expiry separated by commas, e.g.
+    /// "3, 1, 0". "default" (or nothing) gives the empty schedule that follows the food type.
+    pub fn get_reminder_days(&self, input: &str) -> Result<Vec<u32>, String> {
+        let input = input.trim();
+        if input.is_empty() || input.eq_ignore_ascii_case("default") {
+            return Ok(Vec::new());
+        }
+        let mut days = input
+            .split(',')
+            .map(|day| match day.trim().parse::<u32>() {
+                Ok(day) if day <= MAX_LEAD_DAYS => Ok(day),
+                _ => Err(format!(
+                    "Invalid reminder day '{}'. Use whole days before expiry from 0 to {}",
+                    day.trim(),
+                    MAX_LEAD_DAYS
+                )),
+            })
+            .collect::<Result<Vec<u32>, String>>()?;
+        days.sort_unstable_by(|a, b| b.cmp(a));
+        days.dedup();
+        if days.len() > MAX_REMINDER_STAGES {
+            return Err(format!("An item can have at most {} reminders", MAX_REMINDER_STAGES));
         }
+        Ok(days)
+    }
+
+    /// Gets a quantity from the user
+    pub fn get_quantity(&self, input: &str) -> Result<Unit, String> {
+        input.parse()
     }
 }
 
+/// A newly stored food item and the calendar reminder made for it
+pub struct AddedFood {
+    pub stored: StoredFood,
+    /// Confirmation of the calendar event, or reminder links when no event could be created
+    pub calendar_message: Option<String>,
+    /// The reminder as an `.ics` file, for calendars other than Google
+    pub attachment: CalendarAttachment,
+    /// The user's calendar token, if it had to be refreshed; it replaces the stored one
+    pub refreshed_token: Option<Value>,
+}
+
 /// Handles command processing
 pub struct CommandHandler {
     pub input_handler: InputHandler,
+    pub shelf_life: ShelfLifeRules,
+    /// Zone the 9:00 reminders are timed in
+    pub reminder_timezone: Tz,
 }
 
 impl CommandHandler {
     pub fn new() -> Self {
+        Self::with_shelf_life(ShelfLifeRules::builtin())
+    }
+
+    /// Creates a handler that estimates expiry dates from the given shelf-life rules
+    pub fn with_shelf_life(shelf_life: ShelfLifeRules) -> Self {
         Self {
             input_handler: InputHandler::new(),
+            shelf_life,
+            reminder_timezone: Tz::UTC,
         }
     }
 
-    /// Handles the add command
+    /// Validates the raw fields of a new item through the `InputHandler` parsers. An `expiry_date`
+    /// left out (or "none") is estimated from the shelf-life rules, and without `reminder_days`
+    /// the item is reminded on its food type's schedule.
+    fn parse_food(
+        &self,
+        name: &str,
+        stock_date: &str,
+        food_type: &str,
+        storage_type: &str,
+        quantity: &str,
+        expiry_date: Option<&str>,
+        reminder_days: Option<&str>,
+    ) -> Result<FoodStock, String> {
+        let name = self.input_handler.get_food_name(name)?;
+        let stock_date = self.input_handler.get_date(stock_date)?;
+        let food_type = self.input_handler.get_food_type(food_type)?;
+        let storage_type = self.input_handler.get_storage_type(storage_type)?;
+        let quantity = self.input_handler.get_quantity(quantity)?;
+
+        let nutrient = food_type.major_nutrient();
+
+        // The expiry placeholder is replaced below, either by the user's date or an estimate
+        let mut food_stock = FoodStock::new(
+            name,
+            stock_date,
+            food_type,
+            nutrient,
+            storage_type,
+            stock_date,
+            quantity,
+        );
+        food_stock.expiry_date = match expiry_date {
+            Some(date_str) if date_str.to_lowercase() != "none" => {
+                // Use the provided date
+                self.input_handler.get_date(date_str)?
+            },
+            _ => {
+                // Estimate expiry date from the shelf-life rules
+                food_stock.estimate_expiry(&self.shelf_life)
+            }
+        };
+        if let Some(reminder_days) = reminder_days {
+            food_stock.reminder_days = self.input_handler.get_reminder_days(reminder_days)?;
+        }
+        Ok(food_stock)
+    }
+
+    /// Handles the add command. The storage lock is only held while the item is stored and
+    /// its event recorded, not while the calendar provider is called.
     pub async fn handle_add(
         &self, 
         name: String,
-        storage: &mut FoodDb,
+        storage: &Mutex<Box<dyn Repository>>,
         stock_date: String,
         food_type: String,
         storage_type: String,
         quantity: String,
         expiry_date: Option<String>,
-        user_id: Option<i32>,
-        google_token: Option<Value>
-    ) -> Result<bool, String> {
-        println!("DEBUG: handle_add called with name: {}", name);
-        println!("\nAdding new food stock...");
+        reminder_days: Option<String>,
+        user_id: i32,
+        calendar: Option<CalendarSettings>
+    ) -> Result<AddedFood, FoodError> {
+        log::debug!("handle_add called with name: {} for user {}", name, user_id);
+        log::debug!("Adding new food stock");
         
-        let name = self.input_handler.get_food_name(&name)?;
-        let stock_date = self.input_handler.get_date(&stock_date)?;
-        let food_type = self.input_handler.get_food_type(&food_type)?;
-        let storage_type = self.input_handler.get_storage_type(&storage_type)?;
-        let quantity = self.input_handler.get_quantity(&quantity)?;
-
-        let nutrient = match &food_type {
-            FoodType::Vegetable => MajorNutrient::Minerals_and_vitamins,
-            FoodType::Dairy => MajorNutrient::Balanced,
-            FoodType::Non_dairy => MajorNutrient::Carbohydrate,
-            FoodType::Fruit => MajorNutrient::Carbohydrate,
-            FoodType::Meat => MajorNutrient::Protein,
-            FoodType::Breakfast_cereal => MajorNutrient::Carbohydrate,
-            FoodType::Grains => MajorNutrient::Carbohydrate,
-            FoodType::Beverage => MajorNutrient::Sugars,
-            FoodType::Edible_oils => MajorNutrient::Fat,
+        let food_stock = self.parse_food(
+            &name,
+            &stock_date,
+            &food_type,
+            &storage_type,
+            &quantity,
+            expiry_date.as_deref(),
+            reminder_days.as_deref(),
+        )?;
+        let (stored, preferences) = {
+            let mut storage = lock_repository(storage);
+            let preferences = self.handle_reminder_preferences(storage.as_ref(), user_id)?;
+            let id = storage.add_food(user_id, food_stock.clone())?;
+            (StoredFood { id, food: food_stock }, preferences)
         };
 
-        let expiry_date = match expiry_date {
-            Some(date_str) if date_str.to_lowercase() == "none" => {
-                // Calculate expiry date based on food type and storage type
-                let days_to_add = match (&food_type, &storage_type) {
-                    (FoodType::Meat, StorageType::Cold) => 7,
-                    (FoodType::Meat, StorageType::RoomTemperature) => 2,
-                    (FoodType::Vegetable, StorageType::Cold) => 5,
-                    (FoodType::Vegetable, StorageType::RoomTemperature) => 2,
-                    (FoodType::Grains, _) => 90,
-                    (FoodType::Dairy, StorageType::Cold) => 10,
-                    (FoodType::Dairy, StorageType::RoomTemperature) => 1,
-                    (FoodType::Non_dairy, StorageType::Cold) => 10,
-                    (FoodType::Non_dairy, StorageType::RoomTemperature) => 2,
-                    (FoodType::Fruit, StorageType::Cold) => 7,
-                    (FoodType::Fruit, StorageType::RoomTemperature) => 3,
-                    (_, StorageType::Cold) => 5,
-                    (_, StorageType::RoomTemperature) => 2,
-                };
-                stock_date + Duration::days(days_to_add)
-            },
-            Some(date_str) => {
-                // Use the provided date
-                self.input_handler.get_date(&date_str)?
+        // Push the reminder to the user's calendar if they connected one, otherwise offer links
+        let provider = calendar.map(|settings| (settings.name(), settings.provider()));
+        let calendar_message = match &provider {
+            Some((provider_name, provider)) => match preferences.mode {
+                ReminderMode::Item => match self.create_item_event(storage, user_id, &stored, provider_name, provider.as_ref(), &preferences).await {
+                    Ok(()) => "📅 I've also created a calendar reminder for when this food item is approaching its expiry date!".to_string(),
+                    Err(e) => {
+                        log::warn!("Failed to create calendar event: {}", e);
+                        generate_calendar_links(&stored.food, &preferences)
+                    }
+                },
+                ReminderMode::Digest => {
+                    let expiry_date = stored.food.expiry_date;
+                    match self.apply_digest(storage, user_id, expiry_date, provider_name, provider.as_ref(), &preferences).await {
+                        Ok(()) => format!(
+                            "📅 I've added this food item to your \"use it up\" calendar reminder for everything expiring on {}!",
+                            expiry_date.format("%B %-d")
+                        ),
+                        Err(e) => {
+                            log::warn!("Failed to update digest event: {}", e);
+                            generate_calendar_links(&stored.food, &preferences)
+                        }
+                    }
+                }
             },
-            None => {
-                // Calculate expiry date based on food type and storage type
-                let days_to_add = match (&food_type, &storage_type) {
-                    (FoodType::Meat, StorageType::Cold) => 7,
-                    (FoodType::Meat, StorageType::RoomTemperature) => 2,
-                    (FoodType::Vegetable, StorageType::Cold) => 5,
-                    (FoodType::Vegetable, StorageType::RoomTemperature) => 2,
-                    (FoodType::Grains, _) => 90,
-                    (FoodType::Dairy, StorageType::Cold) => 10,
-                    (FoodType::Dairy, StorageType::RoomTemperature) => 1,
-                    (FoodType::Non_dairy, StorageType::Cold) => 10,
-                    (FoodType::Non_dairy, StorageType::RoomTemperature) => 2,
-                    (FoodType::Fruit, StorageType::Cold) => 7,
-                    (FoodType::Fruit, StorageType::RoomTemperature) => 3,
-                    (_, StorageType::Cold) => 5,
-                    (_, StorageType::RoomTemperature) => 2,
-                };
-                stock_date + Duration::days(days_to_add)
-            }
+            None => generate_calendar_links(&stored.food, &preferences),
         };
 
-        let food_stock = FoodStock::new(
-            name,
-            stock_date,
-            food_type,
-            nutrient,
-            storage_type,
-            expiry_date,
-            quantity,
+        Ok(AddedFood {
+            attachment: CalendarAttachment::for_food(&stored, &preferences),
+            stored,
+            calendar_message: Some(calendar_message),
+            refreshed_token: provider.and_then(|(_, provider)| provider.refreshed_token()),
+        })
+    }
+
+    /// Creates the item's own reminder event and links it to the item
+    async fn create_item_event(
+        &self,
+        storage: &Mutex<Box<dyn Repository>>,
+        user_id: i32,
+        stored: &StoredFood,
+        provider_name: &str,
+        provider: &dyn CalendarProvider,
+        preferences: &ReminderPreferences,
+    ) -> Result<(), FoodError> {
+        let reminder = ReminderEvent::for_food(&stored.food, preferences);
+        let event = provider.create_event(&reminder_uid(stored.id), &reminder).await?;
+        log::debug!(
+            "Created calendar event {} for user {} ({})",
+            event.id,
+            user_id,
+            event.link.as_deref().unwrap_or("no link")
         );
+        // Remembered so the event can follow the item when it changes or goes
+        let linked = LinkedEvent { provider: provider_name.to_string(), event_id: event.id };
+        if let Err(e) = lock_repository(storage).set_calendar_event(user_id, stored.id, Some(&linked)) {
+            log::warn!("Failed to record calendar event for item {}: {}", stored.id, e);
+        }
+        Ok(())
+    }
 
-        if let Err(e) = storage.add_food(food_stock.clone()) {
-            return Err(e.to_string());
+    /// Brings the user's digest for `date` in line with the food now expiring that day: it is
+    /// created for the first item, rewritten as items come and go, and removed once none are left.
+    /// A digest on a provider the user has since left is replaced by one on the current provider.
+    async fn apply_digest(
+        &self,
+        storage: &Mutex<Box<dyn Repository>>,
+        user_id: i32,
+        date: NaiveDate,
+        provider_name: &str,
+        provider: &dyn CalendarProvider,
+        preferences: &ReminderPreferences,
+    ) -> Result<(), FoodError> {
+        let (linked, foods) = {
+            let storage = lock_repository(storage);
+            let linked = storage.digest_event(user_id, date)?.filter(|linked| linked.provider == provider_name);
+            (linked, storage.search_by_expiry(user_id, Some(date), Some(date))?)
+        };
+        if foods.is_empty() {
+            if let Some(linked) = linked {
+                provider.delete_event(&linked.event_id).await?;
+                log::debug!("Deleted digest event {} for {}", linked.event_id, date);
+            }
+            return lock_repository(storage).set_digest_event(user_id, date, None);
         }
-        
-        // Try to create calendar event if user has Google Calendar connected
-        let calendar_result = if let (Some(uid), Some(token)) = (user_id, google_token) {
-            match create_calendar_event_with_user_token(&food_stock, uid, Some(token)).await {
-                Ok(result) => {
-                    // Check if this is a calendar link message (fallback) or success message
-                    if result.contains("Calendar Reminder Links") {
-                        Some(result)
-                    } else {
-                        // Successful calendar creation
-                        Some("📅 I've also created a calendar reminder for when this food item is approaching its expiry date!".to_string())
-                    }
-                },
-                Err(e) => {
-                    eprintln!("Failed to create calendar event: {}", e);
-                    // Generate calendar links as fallback
-                    Some(create_calendar_event_with_user_token(&food_stock, uid, None).await.unwrap_or_default())
+
+        let uid = digest_uid(user_id, date);
+        let reminder = ReminderEvent::digest(date, &foods, preferences);
+        let event = match &linked {
+            Some(linked) => provider.update_event(&linked.event_id, &uid, &reminder).await?,
+            None => provider.create_event(&uid, &reminder).await?,
+        };
+        log::debug!("Digest event {} for {} lists {} items", event.id, date, foods.len());
+        let linked = LinkedEvent { provider: provider_name.to_string(), event_id: event.id };
+        lock_repository(storage).set_digest_event(user_id, date, Some(&linked))
+    }
+
+    /// Brings the user's calendar in line with an item after it changed. The item's own event
+    /// (`linked`) is rewritten while the item is active and removed once it is deleted or used up,
+    /// and the digests for the day it expired on before (`expiry_before`) and the day it expires
+    /// on now are rebuilt. Both are read before the change, because deleting an item drops them.
+    /// Calendar failures are only logged, since the change itself is already stored. The storage
+    /// lock is taken for each read and write, and never held while a provider is called.
+    /// Returns the provider's renewed token, if it had to refresh one, so the caller can save it.
+    pub async fn sync_calendar(
+        &self,
+        storage: &Mutex<Box<dyn Repository>>,
+        user_id: i32,
+        food_id: i32,
+        linked: Option<LinkedEvent>,
+        expiry_before: Option<NaiveDate>,
+        calendar: Option<CalendarSettings>,
+    ) -> Option<Value> {
+        let Some(calendar) = calendar else {
+            log::debug!("No calendar settings to sync item {}", food_id);
+            return None;
+        };
+        let provider_name = calendar.name();
+        let provider = calendar.provider();
+        if let Some(linked) = linked {
+            if linked.provider != provider_name {
+                // The user switched providers since; the old calendar can no longer be reached
+                log::debug!("Item {} has a {} event but the user now uses {}", food_id, linked.provider, provider_name);
+            } else if let Err(e) = self.apply_calendar_change(storage, user_id, food_id, &linked, provider.as_ref()).await {
+                log::warn!("Failed to sync calendar event for item {}: {}", food_id, e);
+            }
+        }
+        if let Err(e) = self.sync_digests(storage, user_id, food_id, expiry_before, provider_name, provider.as_ref()).await {
+            log::warn!("Failed to sync digest events for item {}: {}", food_id, e);
+        }
+        provider.refreshed_token()
+    }
+
+    /// Rebuilds the digests the item was or is listed in. Outside digest mode only days that
+    /// already have a digest are touched, so users who pick per-item events get no new digests.
+    async fn sync_digests(
+        &self,
+        storage: &Mutex<Box<dyn Repository>>,
+        user_id: i32,
+        food_id: i32,
+        expiry_before: Option<NaiveDate>,
+        provider_name: &str,
+        provider: &dyn CalendarProvider,
+    ) -> Result<(), FoodError> {
+        let (preferences, expiry_now) = {
+            let storage = lock_repository(storage);
+            let preferences = self.handle_reminder_preferences(storage.as_ref(), user_id)?;
+            (preferences, storage.get_food(user_id, food_id)?.map(|stored| stored.food.expiry_date))
+        };
+        let mut dates: Vec<NaiveDate> = expiry_before.into_iter().chain(expiry_now).collect();
+        dates.dedup();
+        for date in dates {
+            let has_digest = lock_repository(storage).digest_event(user_id, date)?.is_some();
+            if preferences.mode == ReminderMode::Digest || has_digest {
+                self.apply_digest(storage, user_id, date, provider_name, provider, &preferences).await?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Updates `linked` to match the stored item, or deletes it once the item is no longer active
+    async fn apply_calendar_change(
+        &self,
+        storage: &Mutex<Box<dyn Repository>>,
+        user_id: i32,
+        food_id: i32,
+        linked: &LinkedEvent,
+        provider: &dyn CalendarProvider,
+    ) -> Result<(), FoodError> {
+        let stored = lock_repository(storage).get_food(user_id, food_id)?;
+        match stored {
+            Some(stored) => {
+                let preferences = self.handle_reminder_preferences(lock_repository(storage).as_ref(), user_id)?;
+                let reminder = ReminderEvent::for_food(&stored.food, &preferences);
+                let event = provider.update_event(&linked.event_id, &reminder_uid(food_id), &reminder).await?;
+                log::debug!("Updated calendar event {} for item {}", event.id, food_id);
+            }
+            None => {
+                provider.delete_event(&linked.event_id).await?;
+                log::debug!("Deleted calendar event {} for item {}", linked.event_id, food_id);
+                lock_repository(storage).set_calendar_event(user_id, food_id, None)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Handles the import command: validates every row of `data` ("csv" or "json" `format`) and
+    /// stores the valid ones in a single transaction. Invalid rows are reported rather than failing
+    /// the import. No per-item events are created for imported items, but for users in digest mode
+    /// the digest of every day an imported item expires on is rebuilt once the rows are stored;
+    /// failures there are only logged. The storage lock is not held while the calendar is called.
+    pub async fn handle_import(
+        &self,
+        storage: &Mutex<Box<dyn Repository>>,
+        user_id: i32,
+        data: &str,
+        format: &str,
+        calendar: Option<CalendarSettings>,
+    ) -> Result<ImportReport, FoodError> {
+        log::debug!("handle_import called for user {} with {} data", user_id, format);
+        let format: ImportFormat = format.parse()?;
+
+        let mut valid = Vec::new();
+        let mut rejected = Vec::new();
+        for (index, row) in parse_rows(data, format)?.into_iter().enumerate() {
+            let parsed = row.and_then(|row| {
+                self.parse_food(
+                    &row.name,
+                    row.stock_date.as_deref().unwrap_or("today"),
+                    &row.food_type,
+                    &row.storage_type,
+                    &row.quantity,
+                    row.expiry_date.as_deref(),
+                    row.reminder_days.as_deref(),
+                )
+            });
+            match parsed {
+                Ok(food) => valid.push((index + 1, food)),
+                Err(reason) => rejected.push(RejectedRow { row: index + 1, reason }),
+            }
+        }
+
+        let (ids, preferences) = {
+            let mut storage = lock_repository(storage);
+            let preferences = self.handle_reminder_preferences(storage.as_ref(), user_id)?;
+            let ids = if valid.is_empty() {
+                Vec::new()
+            } else {
+                storage.add_foods(user_id, valid.iter().map(|(_, food)| food.clone()).collect())?
+            };
+            (ids, preferences)
+        };
+
+        let mut refreshed_token = None;
+        if let Some(calendar) = calendar.filter(|_| preferences.mode == ReminderMode::Digest && !ids.is_empty()) {
+            let provider_name = calendar.name();
+            let provider = calendar.provider();
+            let dates: BTreeSet<NaiveDate> = valid.iter().map(|(_, food)| food.expiry_date).collect();
+            for date in dates {
+                if let Err(e) = self.apply_digest(storage, user_id, date, provider_name, provider.as_ref(), &preferences).await {
+                    log::warn!("Failed to update digest event for {}: {}", date, e);
                 }
             }
-        } else {
-            // No Google Calendar authentication - generate calendar links
-            Some(create_calendar_event_with_user_token(&food_stock, 0, None).await.unwrap_or_default())
+            refreshed_token = provider.refreshed_token();
+        }
+
+        let accepted = valid
+            .into_iter()
+            .zip(ids)
+            .map(|((row, food), id)| AcceptedRow { row, item: SearchResult::from(&StoredFood { id, food }) })
+            .collect();
+        Ok(ImportReport { accepted, rejected, refreshed_token })
+    }
+
+    /// Handles the update command, parsing every provided field before touching storage.
+    /// Returns the item as it is after the update.
+    pub fn handle_update(
+        &self,
+        storage: &mut dyn FoodRepository,
+        user_id: i32,
+        food_id: i32,
+        name: Option<String>,
+        stock_date: Option<String>,
+        food_type: Option<String>,
+        storage_type: Option<String>,
+        quantity: Option<String>,
+        expiry_date: Option<String>,
+        reminder_days: Option<String>,
+    ) -> Result<StoredFood, FoodError> {
+        log::debug!("handle_update called for item {} (user {})", food_id, user_id);
+
+        let update = FoodUpdate {
+            name: name.map(|n| self.input_handler.get_food_name(&n)).transpose()?,
+            stock_date: stock_date.map(|d| self.input_handler.get_date(&d)).transpose()?,
+            food_type: food_type.map(|t| self.input_handler.get_food_type(&t)).transpose()?,
+            storage_type: storage_type.map(|t| self.input_handler.get_storage_type(&t)).transpose()?,
+            expiry_date: expiry_date.map(|d| self.input_handler.get_date(&d)).transpose()?,
+            quantity: quantity.map(|q| self.input_handler.get_quantity(&q)).transpose()?,
+            reminder_days: reminder_days.map(|r| self.input_handler.get_reminder_days(&r)).transpose()?,
         };
 
-        let mut success_message = "Food stock added successfully!".to_string();
-        if let Some(calendar_msg) = calendar_result {
-            if !calendar_msg.is_empty() {
-                success_message.push_str("\n\n");
-                success_message.push_str(&calendar_msg);
+        if update.is_empty() {
+            return Err(FoodError::Validation("No fields to update were provided".to_string()));
+        }
+
+        if !storage.update_food(user_id, food_id, &update)? {
+            return Err(FoodError::item_not_found(food_id));
+        }
+        storage.get_food(user_id, food_id)?.ok_or_else(|| FoodError::item_not_found(food_id))
+    }
+
+    /// Handles the move command: changes where an item is kept and re-estimates its expiry
+    /// from the day it was moved (`moved_on`, defaulting to today) under the new storage.
+    /// Returns the moved item with its new expiry date.
+    pub fn handle_move(
+        &self,
+        storage: &mut dyn FoodRepository,
+        user_id: i32,
+        food_id: i32,
+        storage_type: String,
+        moved_on: Option<String>,
+    ) -> Result<StoredFood, FoodError> {
+        log::debug!("handle_move called for item {} (user {})", food_id, user_id);
+        let storage_type = self.input_handler.get_storage_type(&storage_type)?;
+        let moved_on = self.input_handler.get_date(moved_on.as_deref().unwrap_or("today"))?;
+
+        let mut stored = storage
+            .get_food(user_id, food_id)?
+            .ok_or_else(|| FoodError::item_not_found(food_id))?;
+        let food = &stored.food;
+
+        let days = self.shelf_life.days_for(&food.name, &food.food_type, &storage_type);
+        let expiry_date = moved_on + Duration::days(days);
+
+        let update = FoodUpdate {
+            storage_type: Some(storage_type.clone()),
+            expiry_date: Some(expiry_date),
+            ..FoodUpdate::default()
+        };
+        if !storage.update_food(user_id, food_id, &update)? {
+            return Err(FoodError::item_not_found(food_id));
+        }
+        stored.food.storage_type = storage_type;
+        stored.food.expiry_date = expiry_date;
+        Ok(stored)
+    }
+
+    /// Handles the delete command
+    pub fn handle_delete(&self, storage: &mut dyn FoodRepository, user_id: i32, food_id: i32) -> Result<(), FoodError> {
+        log::debug!("handle_delete called for item {} (user {})", food_id, user_id);
+        if storage.delete_food(user_id, food_id)? {
+            Ok(())
+        } else {
+            Err(FoodError::item_not_found(food_id))
+        }
+    }
+
+    /// Handles the consume command, recording that part of an item was used
+    pub fn handle_consume(
+        &self,
+        storage: &mut dyn FoodRepository,
+        user_id: i32,
+        food_id: i32,
+        amount: String,
+    ) -> Result<Consumption, FoodError> {
+        log::debug!("handle_consume called for item {} (user {})", food_id, user_id);
+        let amount = self.input_handler.get_quantity(&amount)?;
+        match storage.consume(user_id, food_id, &amount)? {
+            ConsumeOutcome::Consumed(consumption) => Ok(consumption),
+            ConsumeOutcome::NotFound => Err(FoodError::item_not_found(food_id)),
+            ConsumeOutcome::UnitMismatch { stored_unit } => Err(FoodError::Validation(format!(
+                "Food item #{} is measured in '{}', please give the amount in a compatible unit",
+                food_id, stored_unit
+            ))),
+        }
+    }
+
+    /// Handles the expiring command. `today` defaults to the local date and accepts
+    /// anything `parse_query_to_date` understands.
+    pub fn handle_expiring(
+        &self,
+        storage: &dyn FoodRepository,
+        user_id: i32,
+        window: &str,
+        today: Option<String>,
+    ) -> Result<(ExpiryWindow, Vec<StoredFood>), FoodError> {
+        log::debug!("handle_expiring called for user {} with window '{}'", user_id, window);
+        let window = self.input_handler.get_expiry_window(window)?;
+        let today = parse_query_to_date(today.as_deref().unwrap_or("today"))?;
+        let (from, to) = window.date_range(today);
+        let foods = storage.search_by_expiry(user_id, from, to)?;
+        Ok((window, foods))
+    }
+
+    /// Handles the total command: sums the quantities of the user's items whose name
+    /// contains `name`, one total per kind of unit (e.g. "500ml" and "1L" of milk make 1.5L)
+    pub fn handle_total(&self, storage: &dyn FoodRepository, user_id: i32, name: &str) -> Result<Vec<Unit>, FoodError> {
+        log::debug!("handle_total called for user {} with name '{}'", user_id, name);
+        let foods = storage.advanced_search(user_id, name, "name")?;
+
+        let mut totals: Vec<Unit> = Vec::new();
+        for stored in foods {
+            let quantity = stored.food.quantity;
+            match totals.iter_mut().find(|total| total.is_compatible(&quantity)) {
+                Some(total) => *total = total.checked_add(&quantity)?,
+                None => totals.push(quantity.to_base()),
             }
         }
+        Ok(totals.iter().map(Unit::humanize).collect())
+    }
 
-        println!("{}", success_message);
-        Ok(true)
+    /// Handles the export command: the user's whole inventory as "csv", "json" or
+    /// "ics" (a calendar feed of every item's expiry reminder)
+    pub fn handle_export(&self, storage: &dyn Repository, user_id: i32, format: &str) -> Result<String, FoodError> {
+        log::debug!("handle_export called for user {} as {}", user_id, format);
+        let format: ExportFormat = format.parse()?;
+        let foods = storage.get_all_food(user_id)?;
+        export_foods(&foods, format, &self.handle_reminder_preferences(storage, user_id)?)
     }
 
-    /// Handles the view all command
-    pub fn handle_view_all(&self, storage: &FoodDb) -> Result<bool, String> {
-        println!("DEBUG: handle_view_all called");
-        println!("\nCurrent food stocks:");
-        match storage.get_all_food() {
-            Ok(foods) => {
-                for food in foods {
-                    println!("{:?}", food);
-                }
-                Ok(true)
+    /// Handles the reminder command: one item's expiry reminder as a downloadable `.ics` file
+    pub fn handle_reminder_ics(&self, storage: &dyn Repository, user_id: i32, food_id: i32) -> Result<CalendarAttachment, FoodError> {
+        log::debug!("handle_reminder_ics called for item {} (user {})", food_id, user_id);
+        let stored = storage
+            .get_food(user_id, food_id)?
+            .ok_or_else(|| FoodError::item_not_found(food_id))?;
+        Ok(CalendarAttachment::for_food(&stored, &self.handle_reminder_preferences(storage, user_id)?))
+    }
+
+    /// The user's reminder preferences, or the defaults in the handler's time zone if they set none
+    pub fn handle_reminder_preferences(&self, storage: &dyn PreferenceStore, user_id: i32) -> Result<ReminderPreferences, FoodError> {
+        Ok(storage
+            .reminder_preferences(user_id)?
+            .unwrap_or_else(|| ReminderPreferences::with_timezone(self.reminder_timezone)))
+    }
+
+    /// Handles changing the user's reminder preferences: days ahead of expiry, local "HH:MM"
+    /// time, IANA time zone and "item" or "digest" mode. Omitted settings keep their current
+    /// values. Reminders already in a calendar move the next time their item changes.
+    pub fn handle_set_reminder_preferences(
+        &self,
+        storage: &mut dyn PreferenceStore,
+        user_id: i32,
+        lead_days: Option<u32>,
+        time: Option<String>,
+        timezone: Option<String>,
+        mode: Option<String>,
+    ) -> Result<ReminderPreferences, FoodError> {
+        log::debug!("handle_set_reminder_preferences called for user {}", user_id);
+        let mut preferences = self.handle_reminder_preferences(storage, user_id)?;
+        if let Some(lead_days) = lead_days {
+            if lead_days > MAX_LEAD_DAYS {
+                return Err(FoodError::Validation(format!(
+                    "Reminders can be at most {} days before expiry",
+                    MAX_LEAD_DAYS
+                )));
             }
-            Err(e) => {
-                eprintln!("Error retrieving food: {}", e);
-                return Err(e.to_string());
+            preferences.lead_days = lead_days;
+        }
+        if let Some(time) = time {
+            preferences.time = self.input_handler.get_time_of_day(&time)?;
+        }
+        if let Some(timezone) = timezone {
+            preferences.timezone = self.input_handler.get_timezone(&timezone)?;
+        }
+        if let Some(mode) = mode {
+            preferences.mode = mode.parse().map_err(FoodError::Validation)?;
+        }
+        storage.set_reminder_preferences(user_id, &preferences)?;
+        Ok(preferences)
+    }
+
+    /// The user's email digest subscription, or `None` when they have none
+    pub fn handle_email_digest(&self, storage: &dyn PreferenceStore, user_id: i32) -> Result<Option<EmailDigest>, FoodError> {
+        storage.email_digest(user_id)
+    }
+
+    /// Handles subscribing to email digests: `frequency` "daily" or "weekly" (on `weekday`,
+    /// Monday unless chosen), or "off" to unsubscribe. A first subscription needs an `address`
+    /// and starts daily unless told otherwise; later changes keep what they omit, and a
+    /// `weekday` alone makes the digest weekly. Returns the subscription now in effect.
+    pub fn handle_set_email_digest(
+        &self,
+        storage: &mut dyn PreferenceStore,
+        user_id: i32,
+        frequency: Option<String>,
+        address: Option<String>,
+        weekday: Option<String>,
+    ) -> Result<Option<EmailDigest>, FoodError> {
+        log::debug!("handle_set_email_digest called for user {}", user_id);
+        if frequency.as_deref().map(|f| f.trim().eq_ignore_ascii_case("off")).unwrap_or(false) {
+            storage.set_email_digest(user_id, None)?;
+            return Ok(None);
+        }
+
+        let current = storage.email_digest(user_id)?;
+        let address = match (address, &current) {
+            (Some(address), _) => self.input_handler.get_email_address(&address)?,
+            (None, Some(current)) => current.address.clone(),
+            (None, None) => {
+                return Err(FoodError::Validation("An email address is needed to subscribe to digests".to_string()));
             }
+        };
+        let mut frequency = match (frequency, &current) {
+            (Some(frequency), current) => match (frequency.parse().map_err(FoodError::Validation)?, current) {
+                // A weekly digest keeps its day unless a new one is given
+                (DigestFrequency::Weekly(_), Some(EmailDigest { frequency: DigestFrequency::Weekly(day), .. })) => {
+                    DigestFrequency::Weekly(*day)
+                }
+                (frequency, _) => frequency,
+            },
+            (None, Some(current)) => current.frequency,
+            (None, None) => DigestFrequency::Daily,
+        };
+        if let Some(weekday) = weekday {
+            frequency = DigestFrequency::Weekly(self.input_handler.get_weekday(&weekday)?);
+        }
+
+        let digest = EmailDigest { address, frequency };
+        storage.set_email_digest(user_id, Some(&digest))?;
+        Ok(Some(digest))
+    }
+
+    /// Handles the view all command
+    pub fn handle_view_all(&self, storage: &dyn FoodRepository, user_id: i32) -> Result<Vec<StoredFood>, FoodError> {
+        log::debug!("handle_view_all called for user {}", user_id);
+        storage.get_all_food(user_id).map_err(|e| {
+            log::error!("Error retrieving food: {}", e);
+            FoodError::from(e)
+        })
+    }
+
+    /// Handles the search command for one of the searchable fields
+    /// ("name", "food_type", "nutrient" or "storage_type")
+    pub fn handle_search(&self, storage: &dyn FoodRepository, user_id: i32, keyword: &str, field: &str) -> Result<Vec<StoredFood>, FoodError> {
+        log::debug!("handle_search called for user {} with keyword '{}' in '{}'", user_id, keyword, field);
+        if !SEARCHABLE_FIELDS.contains(&field) {
+            return Err(FoodError::Validation(format!(
+                "Cannot search by '{}'. Use 'name', 'food_type', 'nutrient' or 'storage_type'",
+                field
+            )));
         }
+        Ok(storage.advanced_search(user_id, keyword, field)?)
     }
 
     /// Handles the search command
@@ -272,13 +808,13 @@ impl CommandHandler {
     /// Displays search results
     fn display_search_results(&self, results: &Vec<&FoodStock>) {
         if results.is_empty() {
-            println!("No matching food stocks found.");
+            log::info!("No matching food stocks found");
             return;
         }
 
-        println!("\nSearch Results:");
+        log::info!("Search results:");
         for stock in results {
-            println!("{}", stock);
+            log::info!("{}", stock);
         }
     }
 }
\ No newline at end of file
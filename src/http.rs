@@ -0,0 +1,16 @@
+use hyper::client::HttpConnector;
+use hyper::Client;
+use hyper_rustls::{HttpsConnector, HttpsConnectorBuilder};
+
+/// The client every outgoing HTTP call goes through: calendar providers and webhooks.
+/// Trusts the system's root certificates and also speaks plain `http://`, e.g. for a
+/// local CalDAV server or webhook receiver.
+pub fn https_client() -> Client<HttpsConnector<HttpConnector>> {
+    Client::builder().build(
+        HttpsConnectorBuilder::new()
+            .with_native_roots()
+            .https_or_http()
+            .enable_http1()
+            .build(),
+    )
+}
@@ -0,0 +1,237 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use serde::Serialize;
+use serde_json::Value;
+use crate::error::FoodError;
+use crate::storage::SearchResult;
+
+/// File formats accepted by the bulk import
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImportFormat {
+    Csv,
+    Json,
+}
+
+impl FromStr for ImportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "csv" => Ok(ImportFormat::Csv),
+            "json" => Ok(ImportFormat::Json),
+            _ => Err(format!("Unsupported import format '{}'. Use 'csv' or 'json'", s)),
+        }
+    }
+}
+
+/// One imported row as raw text, before it is validated by the `InputHandler` parsers.
+/// Dates use the same DD-MM-YYYY format as the chat flow.
+pub struct ImportRow {
+    pub name: String,
+    pub food_type: String,
+    pub storage_type: String,
+    pub quantity: String,
+    /// Defaults to today
+    pub stock_date: Option<String>,
+    /// Estimated from the shelf-life rules when left out
+    pub expiry_date: Option<String>,
+    /// Comma-separated days before expiry; the food type's schedule when left out
+    pub reminder_days: Option<String>,
+}
+
+impl ImportRow {
+    /// Picks the row's columns out of `fields`, keyed by lower-cased column name.
+    /// `type` and `storage` are accepted as short names for `food_type` and `storage_type`.
+    fn from_fields(mut fields: HashMap<String, String>) -> Result<Self, String> {
+        let mut take = |names: &[&str]| {
+            names
+                .iter()
+                .filter_map(|name| fields.remove(*name))
+                .find(|value| !value.trim().is_empty())
+        };
+        let name = take(&["name"]);
+        let food_type = take(&["food_type", "type"]);
+        let storage_type = take(&["storage_type", "storage"]);
+        let quantity = take(&["quantity"]);
+        let stock_date = take(&["stock_date"]);
+        let expiry_date = take(&["expiry_date"]);
+        let reminder_days = take(&["reminder_days"]);
+
+        let required = |value: Option<String>, column: &str| value.ok_or_else(|| format!("Missing '{}'", column));
+        Ok(ImportRow {
+            name: required(name, "name")?,
+            food_type: required(food_type, "food_type")?,
+            storage_type: required(storage_type, "storage_type")?,
+            quantity: required(quantity, "quantity")?,
+            stock_date,
+            expiry_date,
+            reminder_days,
+        })
+    }
+}
+
+/// Splits `data` into rows. A file that can't be read at all is an error; a single
+/// malformed row comes back as an `Err` with the reason, so the other rows can still be imported.
+pub fn parse_rows(data: &str, format: ImportFormat) -> Result<Vec<Result<ImportRow, String>>, FoodError> {
+    match format {
+        ImportFormat::Csv => parse_csv(data),
+        ImportFormat::Json => parse_json(data),
+    }
+}
+
+fn parse_csv(data: &str) -> Result<Vec<Result<ImportRow, String>>, FoodError> {
+    let mut reader = csv::ReaderBuilder::new().trim(csv::Trim::All).flexible(true).from_reader(data.as_bytes());
+    let headers: Vec<String> = reader
+        .headers()
+        .map_err(|e| FoodError::Validation(format!("Unreadable CSV header: {}", e)))?
+        .iter()
+        .map(str::to_lowercase)
+        .collect();
+
+    Ok(reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| format!("Unreadable CSV row: {}", e))?;
+            let fields = headers.iter().cloned().zip(record.iter().map(str::to_string)).collect();
+            ImportRow::from_fields(fields)
+        })
+        .collect())
+}
+
+fn parse_json(data: &str) -> Result<Vec<Result<ImportRow, String>>, FoodError> {
+    let rows: Vec<Value> = serde_json::from_str(data)
+        .map_err(|e| FoodError::Validation(format!("Import data is not a JSON list of rows: {}", e)))?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| {
+            let Value::Object(object) = row else {
+                return Err("Row is not a JSON object".to_string());
+            };
+            let mut fields = HashMap::new();
+            for (key, value) in object {
+                let text = match value {
+                    Value::Null => continue,
+                    Value::String(text) => text,
+                    Value::Number(number) => number.to_string(),
+                    _ => return Err(format!("'{}' must be text or a number", key)),
+                };
+                fields.insert(key.to_lowercase(), text);
+            }
+            ImportRow::from_fields(fields)
+        })
+        .collect())
+}
+
+/// Outcome of a bulk import. Rows are numbered from 1 in file order, not counting a CSV header.
+#[derive(Debug, Serialize)]
+pub struct ImportReport {
+    pub accepted: Vec<AcceptedRow>,
+    pub rejected: Vec<RejectedRow>,
+    /// The user's calendar token, if it had to be refreshed while their digests were updated
+    pub refreshed_token: Option<Value>,
+}
+
+/// A row that was stored, with the item it became
+#[derive(Debug, Serialize)]
+pub struct AcceptedRow {
+    pub row: usize,
+    pub item: SearchResult,
+}
+
+/// A row that was left out, and why
+#[derive(Debug, Serialize)]
+pub struct RejectedRow {
+    pub row: usize,
+    pub reason: String,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handlers::CommandHandler;
+    use crate::runtime;
+    use crate::storage::{lock_repository, InMemoryFoodDb, Repository};
+    use std::sync::Mutex;
+
+    fn rows(data: &str, format: ImportFormat) -> Vec<Result<ImportRow, String>> {
+        parse_rows(data, format).unwrap()
+    }
+
+    #[test]
+    fn parses_formats_case_insensitively() {
+        assert_eq!("CSV".parse(), Ok(ImportFormat::Csv));
+        assert_eq!(" json ".parse(), Ok(ImportFormat::Json));
+        assert!("xml".parse::<ImportFormat>().is_err());
+    }
+
+    #[test]
+    fn reads_csv_with_short_column_names() {
+        let data = "Name,Type,Quantity,Storage,Expiry_Date\nmilk,dairy,1L,cold,\nrice, grains ,2kg,pantry,01-01-2027\n";
+        let rows = rows(data, ImportFormat::Csv);
+        assert_eq!(rows.len(), 2);
+
+        let milk = rows[0].as_ref().unwrap();
+        assert_eq!(milk.name, "milk");
+        assert_eq!(milk.food_type, "dairy");
+        assert_eq!(milk.storage_type, "cold");
+        assert_eq!(milk.quantity, "1L");
+        assert_eq!(milk.expiry_date, None);
+        assert_eq!(milk.stock_date, None);
+
+        let rice = rows[1].as_ref().unwrap();
+        assert_eq!(rice.food_type, "grains");
+        assert_eq!(rice.expiry_date.as_deref(), Some("01-01-2027"));
+    }
+
+    #[test]
+    fn rejects_only_the_incomplete_csv_row() {
+        let data = "name,food_type,storage_type,quantity\nmilk,dairy,cold,1L\nbread,grains,,1 loaf\n";
+        let rows = rows(data, ImportFormat::Csv);
+        assert!(rows[0].is_ok());
+        assert_eq!(rows[1].as_ref().err().map(String::as_str), Some("Missing 'storage_type'"));
+    }
+
+    #[test]
+    fn reads_json_objects_with_numbers_and_nulls() {
+        let data = r#"[
+            {"name": "eggs", "type": "meat", "storage": "cold", "quantity": 12, "expiry_date": null},
+            {"name": "tea", "food_type": "beverage", "storage_type": "pantry", "quantity": "100g", "reminder_days": "3,1"},
+            "not a row",
+            {"name": ["milk"], "food_type": "dairy", "storage_type": "cold", "quantity": "1L"}
+        ]"#;
+        let rows = rows(data, ImportFormat::Json);
+        assert_eq!(rows.len(), 4);
+
+        let eggs = rows[0].as_ref().unwrap();
+        assert_eq!(eggs.quantity, "12");
+        assert_eq!(eggs.expiry_date, None);
+        assert_eq!(rows[1].as_ref().unwrap().reminder_days.as_deref(), Some("3,1"));
+        assert_eq!(rows[2].as_ref().err().map(String::as_str), Some("Row is not a JSON object"));
+        assert_eq!(rows[3].as_ref().err().map(String::as_str), Some("'name' must be text or a number"));
+    }
+
+    #[test]
+    fn fails_on_data_that_is_not_a_list() {
+        assert!(matches!(parse_rows(r#"{"name": "milk"}"#, ImportFormat::Json), Err(FoodError::Validation(_))));
+    }
+
+    #[test]
+    fn stores_valid_rows_and_reports_the_rest() {
+        let storage: Mutex<Box<dyn Repository>> = Mutex::new(Box::new(InMemoryFoodDb::new()));
+        let data = "name,type,quantity,storage,expiry_date\n\
+                    milk,dairy,1L,cold,05-03-2027\n\
+                    bread,grains,2 kgg,pantry,\n\
+                    rice,grains,2kg,pantry,01-01-2027\n\
+                    soup,stew,1L,cold,\n";
+        let report = runtime().block_on(CommandHandler::new().handle_import(&storage, 1, data, "csv", None)).unwrap();
+
+        let accepted: Vec<(usize, &str)> = report.accepted.iter().map(|a| (a.row, a.item.name.as_str())).collect();
+        assert_eq!(accepted, vec![(1, "milk"), (3, "rice")]);
+        let rejected: Vec<usize> = report.rejected.iter().map(|r| r.row).collect();
+        assert_eq!(rejected, vec![2, 4]);
+        assert_eq!(report.rejected[1].reason, "Invalid food type");
+        assert_eq!(lock_repository(&storage).get_all_food(1).unwrap().len(), 2);
+        assert!(report.refreshed_token.is_none());
+    }
+}
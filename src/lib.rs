@@ -1,130 +1,875 @@
+// pyo3 0.19's `#[pymethods]` expands to impls that newer compilers flag as non-local
+#![allow(non_local_definitions)]
+use pyo3::exceptions::PyRuntimeError;
 use pyo3::prelude::*;
-use serde_json;
+use pyo3::sync::GILOnceCell;
+use serde::Serialize;
+use serde_json::{json, Value};
 use std::env;
+use std::sync::mpsc::{self, Sender};
+use std::sync::{Arc, Mutex, MutexGuard, OnceLock, PoisonError};
+use std::thread;
+use tokio::runtime::Runtime;
 
+mod error;
+mod export;
 mod handlers;
+mod http;
+mod import;
 mod models;
+mod notify;
 mod storage;
 mod reminder;
+mod scheduler;
+mod shelf_life;
 
 
 
-use crate::handlers::{CommandHandler};
-use crate::storage::{DbConfig, FoodDb};
+use crate::error::FoodError;
+use crate::handlers::{AddedFood, CommandHandler};
+use crate::notify::{EmailChannel, ExpiryNotice, LogChannel, NotificationChannel, NotifyFuture, WebhookChannel};
+use crate::reminder::CalendarSettings;
+use crate::scheduler::{ExpiryScheduler, DEFAULT_SCAN_INTERVAL};
+use crate::shelf_life::ShelfLifeRules;
+use crate::storage::{lock_repository, open_repository, timeout_from_secs, Consumption, DbConfig, FoodRepository, Repository, SearchResult, StoredFood};
 
 #[pymodule]
-fn food_agent(_py: Python<'_>, m: &PyModule) -> PyResult<()> {
+fn food_agent(py: Python<'_>, m: &PyModule) -> PyResult<()> {
     m.add_class::<PyCommandHandler>()?;
+    error::register_exceptions(py, m)?;
     Ok(())
 }
 
+fn to_json(value: &impl Serialize) -> PyResult<String> {
+    serde_json::to_string(value).map_err(|e| PyRuntimeError::new_err(e.to_string()))
+}
+
+fn items_json(foods: &[StoredFood]) -> Vec<SearchResult> {
+    foods.iter().map(SearchResult::from).collect()
+}
+
+/// The Tokio runtime every handler in the process runs its async work on, started on first use
+fn runtime() -> &'static Runtime {
+    static RUNTIME: OnceLock<Runtime> = OnceLock::new();
+    RUNTIME.get_or_init(|| Runtime::new().expect("failed to start the Tokio runtime"))
+}
+
+/// Python helper that settles an asyncio future unless its awaiter already cancelled it
+fn resolve_future(py: Python<'_>) -> PyResult<&PyAny> {
+    static RESOLVE: GILOnceCell<PyObject> = GILOnceCell::new();
+    let resolve = RESOLVE.get_or_try_init(py, || -> PyResult<PyObject> {
+        let module = PyModule::from_code(
+            py,
+            "def resolve(future, method, value):\n    if not future.cancelled():\n        getattr(future, method)(value)\n",
+            "food_agent_async.py",
+            "food_agent_async",
+        )?;
+        Ok(module.getattr("resolve")?.into())
+    })?;
+    Ok(resolve.as_ref(py))
+}
+
+/// Run `work` on the shared runtime's blocking pool, without the GIL, and return an asyncio
+/// future on the running event loop that settles with its result
+fn awaitable<T, F>(py: Python<'_>, work: F) -> PyResult<PyObject>
+where
+    T: IntoPy<PyObject>,
+    F: FnOnce() -> PyResult<T> + Send + 'static,
+{
+    let event_loop: PyObject = py.import("asyncio")?.call_method0("get_running_loop")?.into();
+    let future: PyObject = event_loop.call_method0(py, "create_future")?;
+    let pending = future.clone_ref(py);
+    runtime().spawn_blocking(move || {
+        let result = work();
+        let settled = Python::with_gil(|py| {
+            let (method, value) = match result {
+                Ok(value) => ("set_result", value.into_py(py)),
+                Err(e) => ("set_exception", e.into_value(py).into_py(py)),
+            };
+            event_loop.call_method1(py, "call_soon_threadsafe", (resolve_future(py)?, pending, method, value))
+        });
+        if let Err(e) = settled {
+            // The event loop was closed, so nobody is left waiting for the result
+            log::debug!("Could not deliver async result: {}", e);
+        }
+    });
+    Ok(future)
+}
+
+/// Handler state shared between the Python object and the work it runs off the GIL
+struct Shared {
+    handler: CommandHandler,
+    storage: Mutex<Box<dyn Repository>>,
+}
+
 #[pyclass]
 struct PyCommandHandler {
-    handler: CommandHandler,
-    storage: FoodDb,
+    shared: Arc<Shared>,
+    /// Stops the background expiry scheduler, when one is running; dropping it stops it too
+    scheduler: Mutex<Option<Sender<()>>>,
+}
+
+/// Hands expiry notices to a Python callable as JSON, for channels written in Python
+struct PyCallbackChannel {
+    callback: PyObject,
 }
 
+impl NotificationChannel for PyCallbackChannel {
+    fn name(&self) -> &str {
+        "callback"
+    }
+
+    fn send<'a>(&'a self, notice: &'a ExpiryNotice) -> NotifyFuture<'a> {
+        Box::pin(async move {
+            let json = serde_json::to_string(notice)
+                .map_err(|e| FoodError::Notification(format!("Could not encode the notice: {}", e)))?;
+            Python::with_gil(|py| self.callback.call1(py, (json,)).map(|_| ()))
+                .map_err(|e| FoodError::Notification(format!("The notification callback failed: {}", e)))
+        })
+    }
+}
+
+/// The channels a scheduler sends through: the Python `callback`, `webhook_url` and `smtp_url`
+/// given, or logging them when there are none
+fn notification_channels(
+    callback: Option<PyObject>,
+    webhook_url: Option<String>,
+    smtp_url: Option<String>,
+) -> Result<Vec<Box<dyn NotificationChannel>>, FoodError> {
+    let mut channels: Vec<Box<dyn NotificationChannel>> = Vec::new();
+    if let Some(callback) = callback {
+        channels.push(Box::new(PyCallbackChannel { callback }));
+    }
+    with_url_channels(channels, webhook_url, smtp_url)
+}
+
+/// `channels` plus a webhook channel for `webhook_url` and an email digest channel for `smtp_url`,
+/// or logging them when that leaves none. Kept apart from the Python callback because the
+/// `extension-module` feature leaves Python's symbols for the interpreter to provide: the
+/// standalone scanner still links this crate, so nothing it reaches may touch a Python object.
+fn with_url_channels(
+    mut channels: Vec<Box<dyn NotificationChannel>>,
+    webhook_url: Option<String>,
+    smtp_url: Option<String>,
+) -> Result<Vec<Box<dyn NotificationChannel>>, FoodError> {
+    if let Some(url) = webhook_url {
+        channels.push(Box::new(WebhookChannel::new(url)));
+    }
+    if let Some(url) = smtp_url {
+        channels.push(Box::new(EmailChannel::from_env(&url)?));
+    }
+    if channels.is_empty() {
+        channels.push(Box::new(LogChannel));
+    }
+    Ok(channels)
+}
+
+/// Entry point of the `expiry_scanner` binary. The database and reminder time zone come from the
+/// environment as for `PyCommandHandler()`; `NOTIFY_WEBHOOK_URL` sends notices to a webhook and
+/// `SMTP_URL` emails digests to subscribed users (otherwise notices are logged).
+/// `SCAN_INTERVAL_SECS` sets how often to scan, hourly by default. With `once` a single scan
+/// runs; otherwise it scans until the process is stopped.
+pub fn run_expiry_scanner(once: bool) -> Result<(), String> {
+    let shared = Shared::open(&DbConfig::from_env().map_err(|e| e.to_string())?, None).map_err(|e| e.to_string())?;
+    let channels = with_url_channels(Vec::new(), env::var("NOTIFY_WEBHOOK_URL").ok(), env::var("SMTP_URL").ok())
+        .map_err(|e| e.to_string())?;
+    let scheduler = ExpiryScheduler::new(channels);
+    if once {
+        let report = runtime()
+            .block_on(scheduler.scan(&shared.handler, &shared.storage, chrono::Utc::now()))
+            .map_err(|e| e.to_string())?;
+        log::info!("Sent {} notices, {} failed", report.sent.len(), report.failed.len());
+        return Ok(());
+    }
+
+    let interval = match env::var("SCAN_INTERVAL_SECS") {
+        Ok(seconds) => {
+            let seconds = seconds.parse().map_err(|_| format!("SCAN_INTERVAL_SECS must be a number, got '{}'", seconds))?;
+            timeout_from_secs(seconds).map_err(|e| e.to_string())?
+        }
+        Err(_) => DEFAULT_SCAN_INTERVAL,
+    };
+    // Never signalled; the scanner runs until the process is stopped
+    let (_stop, stop_receiver) = mpsc::channel();
+    scheduler.run(&shared.handler, &shared.storage, interval, &stop_receiver);
+    Ok(())
+}
+
+
 #[pymethods]
 impl PyCommandHandler {
+    /// Connect to the food database. Arguments left out fall back to the environment
+    /// (`DATABASE_URL`, `DATABASE_NAME`, `DATABASE_POOL_SIZE`, `DATABASE_CONNECT_TIMEOUT`,
+    /// `DATABASE_QUERY_TIMEOUT`); timeouts are in seconds. Raises instead of aborting when the
+    /// config is invalid or the database can't be reached. `timezone` (or `REMINDER_TIMEZONE`)
+    /// is the IANA zone reminders are timed in for users without their own preference, UTC by default.
     #[new]
-    fn new() -> Self {
-        let db_url = env::var("DATABASE_URL").expect("DATABASE_URL not set");
-        let confg = DbConfig::new(&db_url, "food_registry");
-        
-        Self {
-            handler: CommandHandler::new(),
-            storage: FoodDb::new(confg).expect("Failed to initialize Db"),
+    #[pyo3(signature = (database_url=None, database=None, pool_size=None, connect_timeout=None, query_timeout=None, timezone=None))]
+    fn new(
+        py: Python<'_>,
+        database_url: Option<String>,
+        database: Option<String>,
+        pool_size: Option<usize>,
+        connect_timeout: Option<f64>,
+        query_timeout: Option<f64>,
+        timezone: Option<String>,
+    ) -> PyResult<Self> {
+        let mut config = DbConfig::from_env()?;
+        if let Some(url) = database_url {
+            config = config.with_url(&url);
+        }
+        if let Some(database) = database {
+            config = config.with_database(&database);
+        }
+        if let Some(pool_size) = pool_size {
+            config = config.with_pool_size(pool_size);
+        }
+        if let Some(seconds) = connect_timeout {
+            config = config.with_connect_timeout(timeout_from_secs(seconds)?);
+        }
+        if let Some(seconds) = query_timeout {
+            config = config.with_query_timeout(timeout_from_secs(seconds)?);
+        }
+        Ok(Self {
+            shared: Arc::new(py.allow_threads(|| Shared::open(&config, timezone))?),
+            scheduler: Mutex::new(None),
+        })
+    }
+
+    /// Start scanning for food entering its expiry window in a background thread, every
+    /// `interval_secs` (hourly by default). Due items are passed to `callback` as notice JSON
+    /// (`{"user_id", "date", "items": [...]}`), POSTed to `webhook_url` and/or emailed as digests
+    /// to users subscribed with `set_email_digest_json`, through the SMTP server at `smtp_url`;
+    /// with none of these they are logged. Each channel sends an item's reminder stage once,
+    /// also across restarts. Raises if the scheduler is already running.
+    fn start_expiry_scheduler(
+        &self,
+        callback: Option<PyObject>,
+        webhook_url: Option<String>,
+        interval_secs: Option<f64>,
+        smtp_url: Option<String>,
+    ) -> PyResult<()> {
+        let interval = interval_secs.map(timeout_from_secs).transpose()?.unwrap_or(DEFAULT_SCAN_INTERVAL);
+        let mut running = self.scheduler.lock().unwrap_or_else(PoisonError::into_inner);
+        if running.is_some() {
+            return Err(FoodError::Validation("The expiry scheduler is already running".to_string()).into());
         }
+
+        let scheduler = ExpiryScheduler::new(notification_channels(callback, webhook_url, smtp_url)?);
+        let shared = Arc::clone(&self.shared);
+        let (stop, stop_receiver) = mpsc::channel();
+        thread::spawn(move || scheduler.run(&shared.handler, &shared.storage, interval, &stop_receiver));
+        *running = Some(stop);
+        Ok(())
+    }
+
+    /// Stop the background expiry scheduler after its current scan. Returns whether one was running.
+    fn stop_expiry_scheduler(&self) -> bool {
+        let stop = self.scheduler.lock().unwrap_or_else(PoisonError::into_inner).take();
+        // A scheduler whose thread already ended has nobody listening, which is fine
+        stop.map(|stop| stop.send(()).ok()).is_some()
+    }
+
+    /// Run one expiry scan now through the given channels (as for `start_expiry_scheduler`) and
+    /// return `{"sent": [{"channel", "notice"}], "failed": [{"channel", "user_id", "error"}]}` as JSON
+    fn scan_expiring_json(
+        &self,
+        py: Python<'_>,
+        callback: Option<PyObject>,
+        webhook_url: Option<String>,
+        smtp_url: Option<String>,
+    ) -> PyResult<String> {
+        py.allow_threads(|| self.shared.scan_json(callback, webhook_url, smtp_url))
+    }
+
+    /// Awaitable `scan_expiring_json`
+    fn scan_expiring_async(
+        &self,
+        py: Python<'_>,
+        callback: Option<PyObject>,
+        webhook_url: Option<String>,
+        smtp_url: Option<String>,
+    ) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.scan_json(callback, webhook_url, smtp_url))
     }
 
-    /// Add food to storage
+    /// The user's email digest subscription as `{"address", "frequency", "weekday"}` JSON,
+    /// or `null` when they have none
+    fn email_digest_json(&self, py: Python<'_>, user_id: i32) -> PyResult<String> {
+        py.allow_threads(|| self.shared.email_digest_json(user_id))
+    }
+
+    /// Awaitable `email_digest_json`
+    fn email_digest_async(&self, py: Python<'_>, user_id: i32) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.email_digest_json(user_id))
+    }
+
+    /// Subscribe the user to email digests of their expiring and expired food: `frequency` is
+    /// "daily" or "weekly" (sent on `weekday`, Monday by default), or "off" to unsubscribe.
+    /// The first subscription needs an `address`; later calls keep what they omit. Digests go
+    /// out at the user's reminder time once a scheduler runs with an `smtp_url`. Returns the
+    /// subscription now in effect (or `null`) as JSON and raises on invalid values.
+    fn set_email_digest_json(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        frequency: Option<String>,
+        address: Option<String>,
+        weekday: Option<String>,
+    ) -> PyResult<String> {
+        py.allow_threads(|| self.shared.set_email_digest_json(user_id, frequency, address, weekday))
+    }
+
+    /// Awaitable `set_email_digest_json`
+    fn set_email_digest_async(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        frequency: Option<String>,
+        address: Option<String>,
+        weekday: Option<String>,
+    ) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.set_email_digest_json(user_id, frequency, address, weekday))
+    }
+
+    /// Apply any pending schema migrations, returning the names of those applied
+    fn migrate(&self, py: Python<'_>) -> PyResult<Vec<String>> {
+        py.allow_threads(|| self.shared.migrate())
+    }
+
+    /// Awaitable `migrate`
+    fn migrate_async(&self, py: Python<'_>) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.migrate())
+    }
+
+    /// Add food to the given user's storage. `calendar_json` holds the user's calendar settings
+    /// (`{"provider": "google", "token": {...}}` or `{"provider": "caldav", "url", "username",
+    /// "password"}`; a bare Google token also works) for pushing the expiry reminder.
+    /// `reminder_days` sets the days before expiry to be reminded on, e.g. "3,1,0"; without it
+    /// the item follows its food type's schedule. Returns a confirmation message and raises a
+    /// `FoodAgentError` subclass on failure, like every method here.
+    #[allow(clippy::too_many_arguments)]
     pub fn add_food(
-        &mut self,
+        &self,
+        py: Python<'_>,
+        user_id: i32,
         name: String,
         stock_date: String,
         food_type: String,
         storage_type: String,
         quantity: String,
         expiry_date: Option<String>,
-        user_id: Option<i32>,
-        google_token_json: Option<String>,
-    ) -> PyResult<String> {
-        let rt = tokio::runtime::Runtime::new().unwrap();
-        
-        // Parse Google token JSON if provided
-        let google_token = if let Some(token_str) = google_token_json {
-            match serde_json::from_str(&token_str) {
-                Ok(token) => Some(token),
-                Err(_) => {
-                    println!("DEBUG: Failed to parse Google token JSON");
-                    None
+        calendar_json: Option<String>,
+        reminder_days: Option<String>,
+    ) -> PyResult<String> {
+        let added = py.allow_threads(|| {
+            self.shared
+                .add(user_id, name, stock_date, food_type, storage_type, quantity, expiry_date, calendar_json, reminder_days)
+        });
+        match added {
+            Ok(added) => {
+                let mut message = "Food stock added successfully!".to_string();
+                if let Some(calendar_msg) = added.calendar_message {
+                    message.push_str("\n\n");
+                    message.push_str(&calendar_msg);
                 }
+                Ok(message)
             }
-        } else {
-            None
-        };
-        
-        match rt.block_on(self.handler.handle_add(
-            name,
-            &mut self.storage,
-            stock_date,
-            food_type,
-            storage_type,
-            quantity,
-            expiry_date,
-            user_id,
-            google_token,
-        )) {
-            Ok(_) => Ok("Food stock added successfully!".to_string()),
-            Err(e) => Ok(format!("Error: {}", e)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Like `add_food`, but returns `{"item": {...}, "calendar_message": ..., "attachment": {...}}`
+    /// as JSON. The attachment is the reminder as an `.ics` file
+    /// (`filename`, `content_type`, `content`). `refreshed_token` is set when the user's
+    /// Google token had to be renewed and should be stored in place of the old one.
+    #[allow(clippy::too_many_arguments)]
+    fn add_food_json(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        name: String,
+        stock_date: String,
+        food_type: String,
+        storage_type: String,
+        quantity: String,
+        expiry_date: Option<String>,
+        calendar_json: Option<String>,
+        reminder_days: Option<String>,
+    ) -> PyResult<String> {
+        py.allow_threads(|| {
+            self.shared
+                .add_json(user_id, name, stock_date, food_type, storage_type, quantity, expiry_date, calendar_json, reminder_days)
+        })
+    }
+
+    /// Awaitable `add_food_json`
+    #[allow(clippy::too_many_arguments)]
+    fn add_food_async(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        name: String,
+        stock_date: String,
+        food_type: String,
+        storage_type: String,
+        quantity: String,
+        expiry_date: Option<String>,
+        calendar_json: Option<String>,
+        reminder_days: Option<String>,
+    ) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || {
+            shared.add_json(user_id, name, stock_date, food_type, storage_type, quantity, expiry_date, calendar_json, reminder_days)
+        })
+    }
+
+    /// Import many items at once from CSV (with a header row) or a JSON list of objects.
+    /// Each row needs `name`, `food_type`, `storage_type` and `quantity`, and may give
+    /// `stock_date`, `expiry_date` and `reminder_days`. Valid rows are stored together; returns
+    /// `{"accepted": [{"row", "item"}], "rejected": [{"row", "reason"}], "refreshed_token": ...}`
+    /// as JSON. With the user's `calendar_json` (as for `add_food`), the digests of the days the
+    /// imported items expire on are updated when the user is in digest mode.
+    fn import_food_json(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        data: &str,
+        format: &str,
+        calendar_json: Option<String>,
+    ) -> PyResult<String> {
+        py.allow_threads(|| self.shared.import_json(user_id, data, format, calendar_json))
+    }
+
+    /// Awaitable `import_food_json`
+    fn import_food_async(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        data: String,
+        format: String,
+        calendar_json: Option<String>,
+    ) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.import_json(user_id, &data, &format, calendar_json))
+    }
+
+    /// Update fields of one of the user's food items; omitted fields are kept. With the user's
+    /// `calendar_json` (as for `add_food`), the item's calendar reminder is updated to match.
+    /// `reminder_days` of "default" puts the item back on its food type's reminder schedule.
+    #[allow(clippy::too_many_arguments)]
+    fn update_food(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        food_id: i32,
+        name: Option<String>,
+        stock_date: Option<String>,
+        food_type: Option<String>,
+        storage_type: Option<String>,
+        quantity: Option<String>,
+        expiry_date: Option<String>,
+        calendar_json: Option<String>,
+        reminder_days: Option<String>,
+    ) -> PyResult<String> {
+        let updated = py.allow_threads(|| {
+            self.shared.update(
+                user_id,
+                food_id,
+                name,
+                stock_date,
+                food_type,
+                storage_type,
+                quantity,
+                expiry_date,
+                calendar_json,
+                reminder_days,
+            )
+        });
+        match updated {
+            Ok(_) => Ok(format!("Food item #{} updated successfully!", food_id)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Like `update_food`, but returns `{"item": {...}, "refreshed_token": ...}` as JSON, with
+    /// `refreshed_token` set as for `add_food_json`
+    #[allow(clippy::too_many_arguments)]
+    fn update_food_json(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        food_id: i32,
+        name: Option<String>,
+        stock_date: Option<String>,
+        food_type: Option<String>,
+        storage_type: Option<String>,
+        quantity: Option<String>,
+        expiry_date: Option<String>,
+        calendar_json: Option<String>,
+        reminder_days: Option<String>,
+    ) -> PyResult<String> {
+        py.allow_threads(|| {
+            self.shared.update_json(
+                user_id,
+                food_id,
+                name,
+                stock_date,
+                food_type,
+                storage_type,
+                quantity,
+                expiry_date,
+                calendar_json,
+                reminder_days,
+            )
+        })
+    }
+
+    /// Awaitable `update_food_json`
+    #[allow(clippy::too_many_arguments)]
+    fn update_food_async(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        food_id: i32,
+        name: Option<String>,
+        stock_date: Option<String>,
+        food_type: Option<String>,
+        storage_type: Option<String>,
+        quantity: Option<String>,
+        expiry_date: Option<String>,
+        calendar_json: Option<String>,
+        reminder_days: Option<String>,
+    ) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || {
+            shared.update_json(
+                user_id,
+                food_id,
+                name,
+                stock_date,
+                food_type,
+                storage_type,
+                quantity,
+                expiry_date,
+                calendar_json,
+                reminder_days,
+            )
+        })
+    }
+
+    /// Move one of the user's food items to another storage type ("cold", "warm", "frozen" or "pantry")
+    /// and recalculate its expiry from the day it was moved, which defaults to today.
+    /// The calendar reminder moves with the expiry when `calendar_json` is given.
+    fn move_food(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        food_id: i32,
+        storage_type: String,
+        moved_on: Option<String>,
+        calendar_json: Option<String>,
+    ) -> PyResult<String> {
+        match py.allow_threads(|| self.shared.move_item(user_id, food_id, storage_type, moved_on, calendar_json)) {
+            Ok((stored, _)) => Ok(format!(
+                "Food item #{} moved. It now expires on {}.",
+                food_id, stored.food.expiry_date
+            )),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Like `move_food`, but returns `{"item": {...}, "refreshed_token": ...}` as JSON, with
+    /// `refreshed_token` set as for `add_food_json`
+    fn move_food_json(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        food_id: i32,
+        storage_type: String,
+        moved_on: Option<String>,
+        calendar_json: Option<String>,
+    ) -> PyResult<String> {
+        py.allow_threads(|| self.shared.move_json(user_id, food_id, storage_type, moved_on, calendar_json))
+    }
+
+    /// Awaitable `move_food_json`
+    fn move_food_async(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        food_id: i32,
+        storage_type: String,
+        moved_on: Option<String>,
+        calendar_json: Option<String>,
+    ) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.move_json(user_id, food_id, storage_type, moved_on, calendar_json))
+    }
+
+    /// Move one of the user's food items into the freezer, extending its expiry
+    fn move_to_freezer(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        food_id: i32,
+        moved_on: Option<String>,
+        calendar_json: Option<String>,
+    ) -> PyResult<String> {
+        self.move_food(py, user_id, food_id, "frozen".to_string(), moved_on, calendar_json)
+    }
+
+    /// Delete one of the user's food items, and its calendar reminder when `calendar_json` is given
+    fn delete_food(&self, py: Python<'_>, user_id: i32, food_id: i32, calendar_json: Option<String>) -> PyResult<String> {
+        match py.allow_threads(|| self.shared.delete(user_id, food_id, calendar_json)) {
+            Ok(_) => Ok(format!("Food item #{} removed from storage", food_id)),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Like `delete_food`, but returns `{"deleted": id, "refreshed_token": ...}` as JSON, with
+    /// `refreshed_token` set as for `add_food_json`
+    fn delete_food_json(&self, py: Python<'_>, user_id: i32, food_id: i32, calendar_json: Option<String>) -> PyResult<String> {
+        py.allow_threads(|| self.shared.delete_json(user_id, food_id, calendar_json))
+    }
+
+    /// Awaitable `delete_food_json`
+    fn delete_food_async(&self, py: Python<'_>, user_id: i32, food_id: i32, calendar_json: Option<String>) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.delete_json(user_id, food_id, calendar_json))
+    }
+
+    /// Record that part of one of the user's food items was used, e.g. "200g".
+    /// Once it is used up, its calendar reminder is removed when `calendar_json` is given.
+    fn consume_food(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        food_id: i32,
+        amount: String,
+        calendar_json: Option<String>,
+    ) -> PyResult<String> {
+        match py.allow_threads(|| self.shared.consume(user_id, food_id, amount, calendar_json)) {
+            Ok((consumption, _)) if consumption.used_up => Ok(format!(
+                "Used {} of food item #{}. It is now used up.",
+                consumption.consumed, food_id
+            )),
+            Ok((consumption, _)) => Ok(format!(
+                "Used {} of food item #{}, {} left.",
+                consumption.consumed, food_id, consumption.remaining
+            )),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Like `consume_food`, but returns `{"consumed", "remaining", "used_up", "refreshed_token"}` as
+    /// JSON, with `refreshed_token` set as for `add_food_json`
+    fn consume_food_json(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        food_id: i32,
+        amount: String,
+        calendar_json: Option<String>,
+    ) -> PyResult<String> {
+        py.allow_threads(|| self.shared.consume_json(user_id, food_id, amount, calendar_json))
+    }
+
+    /// Awaitable `consume_food_json`
+    fn consume_food_async(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        food_id: i32,
+        amount: String,
+        calendar_json: Option<String>,
+    ) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.consume_json(user_id, food_id, amount, calendar_json))
+    }
+
+    /// List the user's food in an expiry window ("expired", "today", "tomorrow" or "week"),
+    /// soonest expiry first. `today` defaults to the local date.
+    fn expiring_food(&self, py: Python<'_>, user_id: i32, window: &str, today: Option<String>) -> PyResult<String> {
+        log::debug!("expiring_food called for user {}", user_id);
+        let expiring = py.allow_threads(|| self.shared.handler.handle_expiring(self.shared.storage().as_ref(), user_id, window, today));
+        match expiring {
+            Ok((window, foods)) => {
+                if foods.is_empty() {
+                    Ok(format!("There is no food {} in storage", window))
+                } else {
+                    let mut result = format!("These items are {}:\n", window);
+                    for food in &foods {
+                        result.push_str(&format!("- {}\n", SearchResult::from(food)));
+                    }
+                    Ok(result)
+                }
+            }
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// Like `expiring_food`, but returns `{"window": ..., "items": [...]}` as JSON and raises on failure
+    fn expiring_food_json(&self, py: Python<'_>, user_id: i32, window: &str, today: Option<String>) -> PyResult<String> {
+        py.allow_threads(|| self.shared.expiring_json(user_id, window, today))
+    }
+
+    /// Awaitable `expiring_food_json`
+    fn expiring_food_async(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        window: String,
+        today: Option<String>,
+    ) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.expiring_json(user_id, &window, today))
+    }
+
+    /// Total amount of a food across all of the user's matching items, converting
+    /// between units of the same kind (e.g. "500ml" plus "1L" of milk)
+    fn total_quantity(&self, py: Python<'_>, user_id: i32, name: &str) -> PyResult<String> {
+        match py.allow_threads(|| self.shared.handler.handle_total(self.shared.storage().as_ref(), user_id, name)) {
+            Ok(totals) if totals.is_empty() => Ok(format!("There is no {} available in storage", name)),
+            Ok(totals) => {
+                let totals: Vec<String> = totals.iter().map(|total| total.to_string()).collect();
+                Ok(format!("You have {} of {} in total", totals.join(" and "), name))
+            }
+            Err(e) => Err(e.into()),
         }
     }
 
-    /// View all food in storage
-    fn view_all_food(&self) -> PyResult<String> {
-        println!("DEBUG: view_all_food called");
-        match self.storage.get_all_food() {
+    /// View all food in the given user's storage
+    fn view_all_food(&self, py: Python<'_>, user_id: i32) -> PyResult<String> {
+        log::debug!("view_all_food called for user {}", user_id);
+        match py.allow_threads(|| self.shared.handler.handle_view_all(self.shared.storage().as_ref(), user_id)) {
             Ok(foods) => {
                 if foods.is_empty() {
                     Ok("No food items found in storage.".to_string())
                 } else {
                     let mut result = String::new();
-                    for food in foods {
-                        result.push_str(&format!("{}\n", food));
+                    for food in &foods {
+                        result.push_str(&format!("{}\n", SearchResult::from(food)));
                     }
                     Ok(result.trim().to_string())
                 }
             }
-            Err(e) => Ok(format!("Error retrieving food: {}", e)),
+            Err(e) => Err(e.into()),
         }
     }
 
-    /// Search storage by keyword and field
-    fn search_storage(&self, keyword: &str, field: &str) -> PyResult<String> {
-        println!("DEBUG: search_storage called");
-        match self.storage.advanced_search(keyword, field) {
+    /// The user's food items as a JSON list
+    fn view_all_food_json(&self, py: Python<'_>, user_id: i32) -> PyResult<String> {
+        py.allow_threads(|| self.shared.view_all_json(user_id))
+    }
+
+    /// Awaitable `view_all_food_json`
+    fn view_all_food_async(&self, py: Python<'_>, user_id: i32) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.view_all_json(user_id))
+    }
+
+    /// The user's whole inventory as a "csv" or "json" file, or as an "ics" calendar feed of
+    /// every item's expiry reminder. Raises on failure.
+    fn export_food(&self, py: Python<'_>, user_id: i32, format: &str) -> PyResult<String> {
+        py.allow_threads(|| self.shared.export(user_id, format))
+    }
+
+    /// Awaitable `export_food`
+    fn export_food_async(&self, py: Python<'_>, user_id: i32, format: String) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.export(user_id, &format))
+    }
+
+    /// One of the user's items' expiry reminder as an `.ics` file, returned as
+    /// `{"filename", "content_type", "content"}` JSON. Raises on failure.
+    fn reminder_ics_json(&self, py: Python<'_>, user_id: i32, food_id: i32) -> PyResult<String> {
+        py.allow_threads(|| self.shared.reminder_ics_json(user_id, food_id))
+    }
+
+    /// Awaitable `reminder_ics_json`
+    fn reminder_ics_async(&self, py: Python<'_>, user_id: i32, food_id: i32) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.reminder_ics_json(user_id, food_id))
+    }
+
+    /// The user's reminder preferences as `{"lead_days", "time", "timezone"}` JSON.
+    /// Users who never set any get 9:00 the day before expiry in the handler's time zone.
+    fn reminder_preferences_json(&self, py: Python<'_>, user_id: i32) -> PyResult<String> {
+        py.allow_threads(|| self.shared.reminder_preferences_json(user_id))
+    }
+
+    /// Awaitable `reminder_preferences_json`
+    fn reminder_preferences_async(&self, py: Python<'_>, user_id: i32) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.reminder_preferences_json(user_id))
+    }
+
+    /// Change when the user's reminders go off: `lead_days` before expiry (0 for the day itself),
+    /// at `time` ("HH:MM") in `timezone` (an IANA name). `mode` is "item" for one calendar event
+    /// per item or "digest" for one "use it up" event per day listing everything expiring then.
+    /// Omitted settings are kept; returns the preferences now in effect as JSON and raises on
+    /// invalid values.
+    fn set_reminder_preferences_json(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        lead_days: Option<u32>,
+        time: Option<String>,
+        timezone: Option<String>,
+        mode: Option<String>,
+    ) -> PyResult<String> {
+        py.allow_threads(|| self.shared.set_reminder_preferences_json(user_id, lead_days, time, timezone, mode))
+    }
+
+    /// Awaitable `set_reminder_preferences_json`
+    fn set_reminder_preferences_async(
+        &self,
+        py: Python<'_>,
+        user_id: i32,
+        lead_days: Option<u32>,
+        time: Option<String>,
+        timezone: Option<String>,
+        mode: Option<String>,
+    ) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.set_reminder_preferences_json(user_id, lead_days, time, timezone, mode))
+    }
+
+    /// Search the given user's storage by keyword and field
+    fn search_storage(&self, py: Python<'_>, user_id: i32, keyword: &str, field: &str) -> PyResult<String> {
+        log::debug!("search_storage called for user {}", user_id);
+        match py.allow_threads(|| self.shared.handler.handle_search(self.shared.storage().as_ref(), user_id, keyword, field)) {
             Ok(foods) => {
                 if foods.is_empty() {
                     Ok(format!("There is no {} available in storage", keyword))
                 } else {
                     let mut result = "These are the search results:\n".to_string();
-                    for food in foods {
-                        result.push_str(&format!(
-                            "- {} ({}): {} stored {}, expires {}\n",
-                            food.name,
-                            food.food_type,
-                            food.quantity,
-                            food.storage_type.to_lowercase(),
-                            food.expiry_date
-                        ));
+                    for food in &foods {
+                        result.push_str(&format!("- {}\n", SearchResult::from(food)));
                     }
                     Ok(result)
                 }
             }
-            Err(e) => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e.to_string()))
+            Err(e) => Err(e.into())
         }
     }
 
+    /// Like `search_storage`, but returns the matching items as a JSON list and raises on failure
+    fn search_storage_json(&self, py: Python<'_>, user_id: i32, keyword: &str, field: &str) -> PyResult<String> {
+        py.allow_threads(|| self.shared.search_json(user_id, keyword, field))
+    }
+
+    /// Awaitable `search_storage_json`
+    fn search_storage_async(&self, py: Python<'_>, user_id: i32, keyword: String, field: String) -> PyResult<PyObject> {
+        let shared = Arc::clone(&self.shared);
+        awaitable(py, move || shared.search_json(user_id, &keyword, &field))
+    }
+
     // fn search_by_type(&self, food_type: String) -> PyResult<String> {
     //     let food_type = self.handler.input_handler.get_food_type(&food_type)
     //         .map_err(|e| PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e))?;
@@ -138,11 +883,300 @@ impl PyCommandHandler {
     //     let results = self.storage.search_by_storage(&storage_type);
     //     Ok(format!("{:?}", results))
     // }
+}
 
-    // fn search_by_expiry(&self, date: String) -> PyResult<String> {
-    //     match self.storage.search_by_expiry(&date) {
-    //         Ok(results) => Ok(format!("{:?}", results)),
-    //         Err(e) => Err(PyErr::new::<pyo3::exceptions::PyRuntimeError, _>(e)),
-    //     }
-    // }
-} 
\ No newline at end of file
+/// The operations behind the `*_json` and `*_async` methods, which run without the GIL
+impl Shared {
+    /// Opens the repository and builds the handler. `timezone` (or `REMINDER_TIMEZONE`) is the
+    /// zone reminders are timed in for users without their own preference.
+    fn open(config: &DbConfig, timezone: Option<String>) -> Result<Self, FoodError> {
+        let storage = open_repository(config)?;
+
+        // A rules file takes precedence over rules stored in the database
+        let shelf_life = match env::var("SHELF_LIFE_RULES") {
+            Ok(path) => ShelfLifeRules::from_file(&path).map_err(FoodError::Validation)?,
+            Err(_) => {
+                let rules = storage.load_shelf_life_rules()?;
+                ShelfLifeRules::with_overrides(rules).map_err(FoodError::Validation)?
+            }
+        };
+
+        let mut handler = CommandHandler::with_shelf_life(shelf_life);
+        if let Some(timezone) = timezone.or_else(|| env::var("REMINDER_TIMEZONE").ok()) {
+            handler.reminder_timezone = handler.input_handler.get_timezone(&timezone).map_err(FoodError::Validation)?;
+        }
+
+        Ok(Shared {
+            handler,
+            storage: Mutex::new(storage),
+        })
+    }
+
+    fn scan_json(&self, callback: Option<PyObject>, webhook_url: Option<String>, smtp_url: Option<String>) -> PyResult<String> {
+        let scheduler = ExpiryScheduler::new(notification_channels(callback, webhook_url, smtp_url)?);
+        let report = runtime().block_on(scheduler.scan(&self.handler, &self.storage, chrono::Utc::now()))?;
+        to_json(&report)
+    }
+
+    fn storage(&self) -> MutexGuard<'_, Box<dyn Repository>> {
+        lock_repository(&self.storage)
+    }
+
+    fn migrate(&self) -> PyResult<Vec<String>> {
+        Ok(self.storage().migrate()?)
+    }
+
+    /// Shared body of `add_food` and `add_food_json`
+    #[allow(clippy::too_many_arguments)]
+    fn add(
+        &self,
+        user_id: i32,
+        name: String,
+        stock_date: String,
+        food_type: String,
+        storage_type: String,
+        quantity: String,
+        expiry_date: Option<String>,
+        calendar_json: Option<String>,
+        reminder_days: Option<String>,
+    ) -> Result<AddedFood, FoodError> {
+        // Malformed calendar settings are reported before anything is stored
+        let calendar = calendar_json.as_deref().map(CalendarSettings::from_json).transpose()?;
+
+        runtime().block_on(self.handler.handle_add(
+            name,
+            &self.storage,
+            stock_date,
+            food_type,
+            storage_type,
+            quantity,
+            expiry_date,
+            reminder_days,
+            user_id,
+            calendar,
+        ))
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn add_json(
+        &self,
+        user_id: i32,
+        name: String,
+        stock_date: String,
+        food_type: String,
+        storage_type: String,
+        quantity: String,
+        expiry_date: Option<String>,
+        calendar_json: Option<String>,
+        reminder_days: Option<String>,
+    ) -> PyResult<String> {
+        let added = self.add(user_id, name, stock_date, food_type, storage_type, quantity, expiry_date, calendar_json, reminder_days)?;
+        to_json(&json!({
+            "item": SearchResult::from(&added.stored),
+            "calendar_message": added.calendar_message,
+            "attachment": added.attachment,
+            "refreshed_token": added.refreshed_token,
+        }))
+    }
+
+    fn import_json(&self, user_id: i32, data: &str, format: &str, calendar_json: Option<String>) -> PyResult<String> {
+        let calendar = calendar_json.as_deref().map(CalendarSettings::from_json).transpose()?;
+        let report = runtime().block_on(self.handler.handle_import(&self.storage, user_id, data, format, calendar))?;
+        to_json(&report)
+    }
+
+    /// Runs `change` on one of the user's items, then brings the item's calendar event and the
+    /// digests listing it in line with the result. Malformed calendar settings are reported
+    /// before anything changes, and the storage lock is released before the calendar is called.
+    /// Returns the change's result with the Google token, if it was renewed on the way.
+    fn with_calendar_sync<T>(
+        &self,
+        user_id: i32,
+        food_id: i32,
+        calendar_json: Option<String>,
+        change: impl FnOnce(&mut dyn FoodRepository) -> Result<T, FoodError>,
+    ) -> Result<(T, Option<Value>), FoodError> {
+        let calendar = calendar_json.as_deref().map(CalendarSettings::from_json).transpose()?;
+        let (result, linked, expiry_before) = {
+            let mut storage = self.storage();
+            let linked = storage.calendar_event(user_id, food_id)?;
+            let expiry_before = storage.get_food(user_id, food_id)?.map(|stored| stored.food.expiry_date);
+            (change(storage.as_mut())?, linked, expiry_before)
+        };
+        let refreshed_token =
+            runtime().block_on(self.handler.sync_calendar(&self.storage, user_id, food_id, linked, expiry_before, calendar));
+        Ok((result, refreshed_token))
+    }
+
+    /// Shared body of `update_food` and `update_food_json`
+    #[allow(clippy::too_many_arguments)]
+    fn update(
+        &self,
+        user_id: i32,
+        food_id: i32,
+        name: Option<String>,
+        stock_date: Option<String>,
+        food_type: Option<String>,
+        storage_type: Option<String>,
+        quantity: Option<String>,
+        expiry_date: Option<String>,
+        calendar_json: Option<String>,
+        reminder_days: Option<String>,
+    ) -> Result<(StoredFood, Option<Value>), FoodError> {
+        self.with_calendar_sync(user_id, food_id, calendar_json, |storage| {
+            self.handler.handle_update(
+                storage,
+                user_id,
+                food_id,
+                name,
+                stock_date,
+                food_type,
+                storage_type,
+                quantity,
+                expiry_date,
+                reminder_days,
+            )
+        })
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn update_json(
+        &self,
+        user_id: i32,
+        food_id: i32,
+        name: Option<String>,
+        stock_date: Option<String>,
+        food_type: Option<String>,
+        storage_type: Option<String>,
+        quantity: Option<String>,
+        expiry_date: Option<String>,
+        calendar_json: Option<String>,
+        reminder_days: Option<String>,
+    ) -> PyResult<String> {
+        let (stored, refreshed_token) = self.update(
+            user_id,
+            food_id,
+            name,
+            stock_date,
+            food_type,
+            storage_type,
+            quantity,
+            expiry_date,
+            calendar_json,
+            reminder_days,
+        )?;
+        to_json(&json!({ "item": SearchResult::from(&stored), "refreshed_token": refreshed_token }))
+    }
+
+    /// Shared body of `move_food` and `move_food_json`
+    fn move_item(
+        &self,
+        user_id: i32,
+        food_id: i32,
+        storage_type: String,
+        moved_on: Option<String>,
+        calendar_json: Option<String>,
+    ) -> Result<(StoredFood, Option<Value>), FoodError> {
+        self.with_calendar_sync(user_id, food_id, calendar_json, |storage| {
+            self.handler.handle_move(storage, user_id, food_id, storage_type, moved_on)
+        })
+    }
+
+    fn move_json(
+        &self,
+        user_id: i32,
+        food_id: i32,
+        storage_type: String,
+        moved_on: Option<String>,
+        calendar_json: Option<String>,
+    ) -> PyResult<String> {
+        let (stored, refreshed_token) = self.move_item(user_id, food_id, storage_type, moved_on, calendar_json)?;
+        to_json(&json!({ "item": SearchResult::from(&stored), "refreshed_token": refreshed_token }))
+    }
+
+    /// Shared body of `delete_food` and `delete_food_json`
+    fn delete(&self, user_id: i32, food_id: i32, calendar_json: Option<String>) -> Result<((), Option<Value>), FoodError> {
+        self.with_calendar_sync(user_id, food_id, calendar_json, |storage| {
+            self.handler.handle_delete(storage, user_id, food_id)
+        })
+    }
+
+    fn delete_json(&self, user_id: i32, food_id: i32, calendar_json: Option<String>) -> PyResult<String> {
+        let ((), refreshed_token) = self.delete(user_id, food_id, calendar_json)?;
+        to_json(&json!({ "deleted": food_id, "refreshed_token": refreshed_token }))
+    }
+
+    /// Shared body of `consume_food` and `consume_food_json`
+    fn consume(&self, user_id: i32, food_id: i32, amount: String, calendar_json: Option<String>) -> Result<(Consumption, Option<Value>), FoodError> {
+        self.with_calendar_sync(user_id, food_id, calendar_json, |storage| {
+            self.handler.handle_consume(storage, user_id, food_id, amount)
+        })
+    }
+
+    fn consume_json(&self, user_id: i32, food_id: i32, amount: String, calendar_json: Option<String>) -> PyResult<String> {
+        let (consumption, refreshed_token) = self.consume(user_id, food_id, amount, calendar_json)?;
+        to_json(&json!({
+            "consumed": consumption.consumed.to_string(),
+            "remaining": consumption.remaining.to_string(),
+            "used_up": consumption.used_up,
+            "refreshed_token": refreshed_token,
+        }))
+    }
+
+    fn expiring_json(&self, user_id: i32, window: &str, today: Option<String>) -> PyResult<String> {
+        let (window, foods) = self.handler.handle_expiring(self.storage().as_ref(), user_id, window, today)?;
+        to_json(&json!({ "window": window.to_string(), "items": items_json(&foods) }))
+    }
+
+    fn view_all_json(&self, user_id: i32) -> PyResult<String> {
+        let foods = self.handler.handle_view_all(self.storage().as_ref(), user_id)?;
+        to_json(&items_json(&foods))
+    }
+
+    fn export(&self, user_id: i32, format: &str) -> PyResult<String> {
+        Ok(self.handler.handle_export(self.storage().as_ref(), user_id, format)?)
+    }
+
+    fn reminder_ics_json(&self, user_id: i32, food_id: i32) -> PyResult<String> {
+        let attachment = self.handler.handle_reminder_ics(self.storage().as_ref(), user_id, food_id)?;
+        to_json(&attachment)
+    }
+
+    fn reminder_preferences_json(&self, user_id: i32) -> PyResult<String> {
+        let preferences = self.handler.handle_reminder_preferences(self.storage().as_ref(), user_id)?;
+        to_json(&preferences)
+    }
+
+    fn set_reminder_preferences_json(
+        &self,
+        user_id: i32,
+        lead_days: Option<u32>,
+        time: Option<String>,
+        timezone: Option<String>,
+        mode: Option<String>,
+    ) -> PyResult<String> {
+        let preferences =
+            self.handler.handle_set_reminder_preferences(self.storage().as_mut(), user_id, lead_days, time, timezone, mode)?;
+        to_json(&preferences)
+    }
+
+    fn email_digest_json(&self, user_id: i32) -> PyResult<String> {
+        to_json(&self.handler.handle_email_digest(self.storage().as_ref(), user_id)?)
+    }
+
+    fn set_email_digest_json(
+        &self,
+        user_id: i32,
+        frequency: Option<String>,
+        address: Option<String>,
+        weekday: Option<String>,
+    ) -> PyResult<String> {
+        let digest = self.handler.handle_set_email_digest(self.storage().as_mut(), user_id, frequency, address, weekday)?;
+        to_json(&digest)
+    }
+
+    fn search_json(&self, user_id: i32, keyword: &str, field: &str) -> PyResult<String> {
+        let foods = self.handler.handle_search(self.storage().as_ref(), user_id, keyword, field)?;
+        to_json(&items_json(&foods))
+    }
+}
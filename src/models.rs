@@ -1,8 +1,11 @@
 use chrono::{NaiveDate, Duration};
 use std::fmt;
+use std::str::FromStr;
+use crate::shelf_life::ShelfLifeRules;
 
 
 #[derive(Debug, Clone, PartialEq)]
+#[allow(non_camel_case_types)]
 pub enum FoodType {
     Vegetable,
     Fruit,
@@ -31,18 +34,328 @@ impl fmt::Display for FoodType {
     }
 }
 
+/// Lowercases and unifies separators so user input ("non-dairy") and stored
+/// variant names ("Non_dairy") parse the same way
+fn normalize_key(s: &str) -> String {
+    s.trim().to_lowercase().replace(['_', ' '], "-")
+}
+
+impl FromStr for FoodType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match normalize_key(s).as_str() {
+            "vegetable" => Ok(FoodType::Vegetable),
+            "fruit" => Ok(FoodType::Fruit),
+            "grains" => Ok(FoodType::Grains),
+            "breakfast-cereal" => Ok(FoodType::Breakfast_cereal),
+            "beverage" => Ok(FoodType::Beverage),
+            "meat" => Ok(FoodType::Meat),
+            "dairy" => Ok(FoodType::Dairy),
+            "non-dairy" => Ok(FoodType::Non_dairy),
+            "edible-oils" => Ok(FoodType::Edible_oils),
+            _ => Err("Invalid food type".to_string()),
+        }
+    }
+}
+
+impl FoodType {
+    /// The nutrient a food of this type is mostly made of
+    pub fn major_nutrient(&self) -> MajorNutrient {
+        match self {
+            FoodType::Vegetable => MajorNutrient::Minerals_and_vitamins,
+            FoodType::Dairy => MajorNutrient::Balanced,
+            FoodType::Non_dairy => MajorNutrient::Carbohydrate,
+            FoodType::Fruit => MajorNutrient::Carbohydrate,
+            FoodType::Meat => MajorNutrient::Protein,
+            FoodType::Breakfast_cereal => MajorNutrient::Carbohydrate,
+            FoodType::Grains => MajorNutrient::Carbohydrate,
+            FoodType::Beverage => MajorNutrient::Sugars,
+            FoodType::Edible_oils => MajorNutrient::Fat,
+        }
+    }
+
+    /// Extra reminders, in days before expiry, for items of this type that have no schedule
+    /// of their own. Perishable food gets more warning; long-keeping food none beyond the user's usual one.
+    pub fn reminder_days(&self) -> &'static [u32] {
+        match self {
+            FoodType::Meat => &[3, 1, 0],
+            FoodType::Dairy | FoodType::Non_dairy | FoodType::Vegetable | FoodType::Fruit => &[2, 0],
+            FoodType::Beverage | FoodType::Grains | FoodType::Breakfast_cereal | FoodType::Edible_oils => &[],
+        }
+    }
+}
+
+/// What a unit measures; only units that share a base unit convert into each other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dimension {
+    Mass,
+    Volume,
+    Count,
+}
+
 /// Represents the unit of measurement for food quantity
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Unit {
     Grams(f32),
+    Kilograms(f32),
+    Ounces(f32),
+    Pounds(f32),
+    Millilitres(f32),
     Litres(f32),
+    Cups(f32),
+    Tablespoons(f32),
+    Teaspoons(f32),
+    Pieces(f32),
+    Dozens(f32),
+    Packs(f32),
+    Loaves(f32),
+}
+
+/// Builds a unit from its amount, e.g. `Unit::Grams`
+type UnitConstructor = fn(f32) -> Unit;
+
+/// Every name and symbol a unit is recognised by
+const UNIT_NAMES: &[(&[&str], UnitConstructor)] = &[
+    (&["g", "gram", "grams"], Unit::Grams),
+    (&["kg", "kgs", "kilo", "kilos", "kilogram", "kilograms"], Unit::Kilograms),
+    (&["oz", "ounce", "ounces"], Unit::Ounces),
+    (&["lb", "lbs", "pound", "pounds"], Unit::Pounds),
+    (&["ml", "millilitre", "millilitres", "milliliter", "milliliters"], Unit::Millilitres),
+    (&["l", "litre", "litres", "liter", "liters"], Unit::Litres),
+    (&["cup", "cups"], Unit::Cups),
+    (&["tbsp", "tablespoon", "tablespoons"], Unit::Tablespoons),
+    (&["tsp", "teaspoon", "teaspoons"], Unit::Teaspoons),
+    (&["", "pc", "pcs", "piece", "pieces"], Unit::Pieces),
+    (&["dozen", "dozens"], Unit::Dozens),
+    (&["pack", "packs", "packet", "packets"], Unit::Packs),
+    (&["loaf", "loaves"], Unit::Loaves),
+];
+
+/// Whether `a` turns into `b` by inserting, deleting or replacing at most one character
+fn within_one_edit(a: &str, b: &str) -> bool {
+    let (a, b): (Vec<char>, Vec<char>) = (a.chars().collect(), b.chars().collect());
+    let (short, long) = if a.len() <= b.len() { (&a, &b) } else { (&b, &a) };
+    if long.len() - short.len() > 1 {
+        return false;
+    }
+    let prefix = short.iter().zip(long.iter()).take_while(|(x, y)| x == y).count();
+    let skip = if short.len() == long.len() { 1 } else { 0 };
+    short.iter().skip(prefix + skip).eq(long.iter().skip(prefix + 1))
+}
+
+/// Whether an unrecognised word looks like a mistyped unit ("kgg", "pcks") rather than the
+/// name of what is counted ("eggs"). Single letters are left out, or "lemons" would be litres.
+fn looks_like_unit(word: &str) -> bool {
+    UNIT_NAMES
+        .iter()
+        .flat_map(|(names, _)| names.iter())
+        .any(|name| name.len() > 1 && within_one_edit(word, name))
+}
+
+/// Parses the number of a quantity. A comma only separates thousands, as in "1,000"; "1,5"
+/// is rejected rather than guessed at.
+fn parse_amount(number: &str) -> Result<f32, String> {
+    let mut groups = number.split(',');
+    let first = groups.next().unwrap_or("");
+    let mut digits = first.to_string();
+    if number.contains(',') {
+        let grouped = !first.is_empty()
+            && first.len() <= 3
+            && first.chars().all(|c| c.is_ascii_digit())
+            && number.split(',').skip(1).all(|group| {
+                let whole = group.split('.').next().unwrap_or("");
+                whole.len() == 3 && whole.chars().all(|c| c.is_ascii_digit())
+            });
+        if !grouped {
+            return Err(format!(
+                "Invalid number '{}'. Use '.' for decimals; ',' only separates thousands, as in 1,000",
+                number
+            ));
+        }
+        groups.for_each(|group| digits.push_str(group));
+    }
+    digits.parse::<f32>().map_err(|_| "Invalid number format".to_string())
+}
+
+impl Unit {
+    /// Builds a quantity from a number and a unit name or symbol such as "kg", "ml" or "loaf"
+    pub fn from_parts(value: f32, unit: &str) -> Result<Self, String> {
+        let unit = unit.trim().to_lowercase();
+        UNIT_NAMES
+            .iter()
+            .find(|(names, _)| names.contains(&unit.as_str()))
+            .map(|(_, unit)| unit(value))
+            .ok_or_else(|| format!("Unknown unit '{}'", unit))
+    }
+
+    /// The same unit holding a different amount
+    pub fn with_value(&self, value: f32) -> Unit {
+        match self {
+            Unit::Grams(_) => Unit::Grams(value),
+            Unit::Kilograms(_) => Unit::Kilograms(value),
+            Unit::Ounces(_) => Unit::Ounces(value),
+            Unit::Pounds(_) => Unit::Pounds(value),
+            Unit::Millilitres(_) => Unit::Millilitres(value),
+            Unit::Litres(_) => Unit::Litres(value),
+            Unit::Cups(_) => Unit::Cups(value),
+            Unit::Tablespoons(_) => Unit::Tablespoons(value),
+            Unit::Teaspoons(_) => Unit::Teaspoons(value),
+            Unit::Pieces(_) => Unit::Pieces(value),
+            Unit::Dozens(_) => Unit::Dozens(value),
+            Unit::Packs(_) => Unit::Packs(value),
+            Unit::Loaves(_) => Unit::Loaves(value),
+        }
+    }
+
+    /// The amount, in this quantity's own unit
+    pub fn value(&self) -> f32 {
+        match *self {
+            Unit::Grams(v)
+            | Unit::Kilograms(v)
+            | Unit::Ounces(v)
+            | Unit::Pounds(v)
+            | Unit::Millilitres(v)
+            | Unit::Litres(v)
+            | Unit::Cups(v)
+            | Unit::Tablespoons(v)
+            | Unit::Teaspoons(v)
+            | Unit::Pieces(v)
+            | Unit::Dozens(v)
+            | Unit::Packs(v)
+            | Unit::Loaves(v) => v,
+        }
+    }
+
+    /// Short symbol used for display and persistence
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Unit::Grams(_) => "g",
+            Unit::Kilograms(_) => "kg",
+            Unit::Ounces(_) => "oz",
+            Unit::Pounds(_) => "lb",
+            Unit::Millilitres(_) => "ml",
+            Unit::Litres(_) => "L",
+            Unit::Cups(_) => "cup",
+            Unit::Tablespoons(_) => "tbsp",
+            Unit::Teaspoons(_) => "tsp",
+            Unit::Pieces(_) => "pcs",
+            Unit::Dozens(_) => "dozen",
+            Unit::Packs(_) => "pack",
+            Unit::Loaves(_) => "loaf",
+        }
+    }
+
+    pub fn dimension(&self) -> Dimension {
+        match self {
+            Unit::Grams(_) | Unit::Kilograms(_) | Unit::Ounces(_) | Unit::Pounds(_) => Dimension::Mass,
+            Unit::Millilitres(_) | Unit::Litres(_) | Unit::Cups(_) | Unit::Tablespoons(_) | Unit::Teaspoons(_) => {
+                Dimension::Volume
+            }
+            Unit::Pieces(_) | Unit::Dozens(_) | Unit::Packs(_) | Unit::Loaves(_) => Dimension::Count,
+        }
+    }
+
+    /// How many base units one of this unit is worth
+    fn base_factor(&self) -> f32 {
+        match self {
+            Unit::Kilograms(_) | Unit::Litres(_) => 1000.0,
+            Unit::Ounces(_) => 28.349_523,
+            Unit::Pounds(_) => 453.592_37,
+            Unit::Cups(_) => 240.0,
+            Unit::Tablespoons(_) => 15.0,
+            Unit::Teaspoons(_) => 5.0,
+            Unit::Dozens(_) => 12.0,
+            _ => 1.0,
+        }
+    }
+
+    /// The same amount in the canonical unit quantities are stored in:
+    /// grams, millilitres, pieces, packs or loaves
+    pub fn to_base(self) -> Unit {
+        let value = self.value() * self.base_factor();
+        match self {
+            Unit::Packs(_) => Unit::Packs(value),
+            Unit::Loaves(_) => Unit::Loaves(value),
+            _ => match self.dimension() {
+                Dimension::Mass => Unit::Grams(value),
+                Dimension::Volume => Unit::Millilitres(value),
+                Dimension::Count => Unit::Pieces(value),
+            },
+        }
+    }
+
+    /// Whether the two quantities can be converted into each other
+    pub fn is_compatible(&self, other: &Unit) -> bool {
+        self.to_base().symbol() == other.to_base().symbol()
+    }
+
+    /// Expresses this quantity in the unit of `target`, e.g. 1.5L as millilitres
+    pub fn convert_to(&self, target: &Unit) -> Result<Unit, String> {
+        if !self.is_compatible(target) {
+            return Err(format!(
+                "Cannot convert {} to {}",
+                self.symbol(),
+                target.symbol()
+            ));
+        }
+        Ok(target.with_value(self.value() * self.base_factor() / target.base_factor()))
+    }
+
+    /// Sums two compatible quantities, in the unit of `self`
+    pub fn checked_add(&self, other: &Unit) -> Result<Unit, String> {
+        let other = other.convert_to(self)?;
+        Ok(self.with_value(self.value() + other.value()))
+    }
+
+    /// Picks a readable unit for a base quantity, e.g. 1500g as 1.5kg
+    pub fn humanize(&self) -> Unit {
+        match self.to_base() {
+            Unit::Grams(g) if g >= 1000.0 => Unit::Kilograms(g / 1000.0),
+            Unit::Millilitres(ml) if ml >= 1000.0 => Unit::Litres(ml / 1000.0),
+            base => base,
+        }
+    }
 }
 
 impl fmt::Display for Unit {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        match self {
-            Unit::Grams(g) => write!(f, "{}g", g),
-            Unit::Litres(l) => write!(f, "{}L", l),
+        match self.dimension() {
+            Dimension::Count => write!(f, "{} {}", self.value(), self.symbol()),
+            _ => write!(f, "{}{}", self.value(), self.symbol()),
+        }
+    }
+}
+
+impl FromStr for Unit {
+    type Err = String;
+
+    /// Parses quantities like "2kg", "500 ml", "6 eggs", "1 loaf" or "1,000g". A bare number or
+    /// a word that is not a unit ("eggs") counts pieces, unless the word is a unit misspelled.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let input = s.trim();
+        let split = input
+            .find(|c: char| !(c.is_ascii_digit() || c == '.' || c == ','))
+            .unwrap_or(input.len());
+        let (number_part, unit_part) = input.split_at(split);
+
+        let value = parse_amount(number_part)?;
+        if value < 0.0 {
+            return Err("Quantity cannot be negative".to_string());
+        }
+
+        let unit_part = unit_part.trim();
+        let first_word = unit_part.split_whitespace().next().unwrap_or("");
+        match Unit::from_parts(value, unit_part).or_else(|_| Unit::from_parts(value, first_word)) {
+            Ok(unit) => Ok(unit),
+            // "6 eggs": the word names the item, so the number counts pieces
+            Err(_) if first_word.chars().all(char::is_alphabetic) && !looks_like_unit(&first_word.to_lowercase()) => {
+                Ok(Unit::Pieces(value))
+            }
+            Err(_) => Err(format!(
+                "Invalid unit in '{}'. Use a mass (g, kg, oz, lb), volume (ml, l, cup, tbsp, tsp) or count (pieces, dozen, pack, loaf)",
+                input
+            )),
         }
     }
 }
@@ -52,6 +365,8 @@ impl fmt::Display for Unit {
 pub enum StorageType {
     Cold,
     RoomTemperature,
+    Frozen,
+    Pantry,
 }
 
 impl fmt::Display for StorageType {
@@ -59,12 +374,29 @@ impl fmt::Display for StorageType {
         match self {
             StorageType::Cold => write!(f, "Cold Storage"),
             StorageType::RoomTemperature => write!(f, "Room Temperature"),
+            StorageType::Frozen => write!(f, "Frozen"),
+            StorageType::Pantry => write!(f, "Pantry"),
+        }
+    }
+}
+
+impl FromStr for StorageType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match normalize_key(s).as_str() {
+            "cold" | "cold-storage" | "fridge" => Ok(StorageType::Cold),
+            "warm" | "roomtemperature" | "room-temperature" => Ok(StorageType::RoomTemperature),
+            "frozen" | "freezer" => Ok(StorageType::Frozen),
+            "pantry" | "dry" => Ok(StorageType::Pantry),
+            _ => Err("Invalid storage type".to_string()),
         }
     }
 }
 
 /// Represents the major nutrient content of food items
 #[derive(Debug, Clone, PartialEq)]
+#[allow(non_camel_case_types)]
 pub enum MajorNutrient {
     Protein,
     Fat,
@@ -89,6 +421,58 @@ impl fmt::Display for MajorNutrient {
     }
 }
 
+/// A span of expiry dates to look up, relative to a given day
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExpiryWindow {
+    Expired,
+    Today,
+    Tomorrow,
+    ThisWeek,
+}
+
+impl ExpiryWindow {
+    /// Inclusive `(from, to)` expiry bounds for this window; `None` means unbounded
+    pub fn date_range(&self, today: NaiveDate) -> (Option<NaiveDate>, Option<NaiveDate>) {
+        match self {
+            ExpiryWindow::Expired => (None, Some(today - Duration::days(1))),
+            ExpiryWindow::Today => (Some(today), Some(today)),
+            ExpiryWindow::Tomorrow => {
+                let tomorrow = today + Duration::days(1);
+                (Some(tomorrow), Some(tomorrow))
+            }
+            ExpiryWindow::ThisWeek => (Some(today), Some(today + Duration::days(6))),
+        }
+    }
+}
+
+impl fmt::Display for ExpiryWindow {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ExpiryWindow::Expired => write!(f, "already expired"),
+            ExpiryWindow::Today => write!(f, "expiring today"),
+            ExpiryWindow::Tomorrow => write!(f, "expiring tomorrow"),
+            ExpiryWindow::ThisWeek => write!(f, "expiring this week"),
+        }
+    }
+}
+
+impl FromStr for MajorNutrient {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match normalize_key(s).as_str() {
+            "protein" => Ok(MajorNutrient::Protein),
+            "fat" => Ok(MajorNutrient::Fat),
+            "carbohydrate" => Ok(MajorNutrient::Carbohydrate),
+            "sugars" => Ok(MajorNutrient::Sugars),
+            "water" => Ok(MajorNutrient::Water),
+            "minerals-and-vitamins" => Ok(MajorNutrient::Minerals_and_vitamins),
+            "balanced" => Ok(MajorNutrient::Balanced),
+            _ => Err("Invalid nutrient".to_string()),
+        }
+    }
+}
+
 /// Food stock item with different properties
 #[derive(Debug, Clone)]
 pub struct FoodStock {
@@ -99,6 +483,8 @@ pub struct FoodStock {
     pub storage_type: StorageType,
     pub expiry_date: NaiveDate,
     pub quantity: Unit,
+    /// Days before expiry to be reminded on, furthest first; empty follows the food type
+    pub reminder_days: Vec<u32>,
 }
 
 impl FoodStock {
@@ -120,45 +506,149 @@ impl FoodStock {
             storage_type,
             expiry_date,
             quantity,
+            reminder_days: Vec::new(),
         }
     }
 
-    /// Estimates the expiry date based on food type and storage conditions
-    pub fn estimate_expiry(&self) -> NaiveDate {
-        let days_to_add = match (&self.food_type, &self.storage_type) {
-            (FoodType::Meat, StorageType::Cold) => 7,
-            (FoodType::Meat, StorageType::RoomTemperature) => 2,
-            (FoodType::Vegetable, StorageType::Cold) => 5,
-            (FoodType::Vegetable, StorageType::RoomTemperature) => 2,
-            (FoodType::Grains, _) => 90,
-            (FoodType::Dairy, StorageType::Cold) => 10,
-            (FoodType::Dairy, StorageType::RoomTemperature) => 1,
-            (FoodType::Non_dairy, StorageType::Cold) => 10,
-            (FoodType::Non_dairy, StorageType::RoomTemperature) => 2,
-            (FoodType::Fruit, StorageType::Cold) => 7,
-            (FoodType::Fruit, StorageType::RoomTemperature) => 3,
-            (_, StorageType::Cold) => 5,
-            (_, StorageType::RoomTemperature) => 2,
-        };
+    /// Estimates the expiry date from the shelf-life rules that best match this item
+    pub fn estimate_expiry(&self, rules: &ShelfLifeRules) -> NaiveDate {
+        let days_to_add = rules.days_for(&self.name, &self.food_type, &self.storage_type);
         self.stock_date + Duration::days(days_to_add)
     }
 }
 
+/// Changes to apply to a stored food item; `None` fields are left untouched
+#[derive(Debug, Clone, Default)]
+pub struct FoodUpdate {
+    pub name: Option<String>,
+    pub stock_date: Option<NaiveDate>,
+    pub food_type: Option<FoodType>,
+    pub storage_type: Option<StorageType>,
+    pub expiry_date: Option<NaiveDate>,
+    pub quantity: Option<Unit>,
+    /// An empty schedule goes back to the food type's
+    pub reminder_days: Option<Vec<u32>>,
+}
+
+impl FoodUpdate {
+    /// Returns true when the update would not change anything
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none()
+            && self.stock_date.is_none()
+            && self.food_type.is_none()
+            && self.storage_type.is_none()
+            && self.expiry_date.is_none()
+            && self.quantity.is_none()
+            && self.reminder_days.is_none()
+    }
+}
+
 impl fmt::Display for FoodStock {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        let quantity_str = match &self.quantity {
-            Unit::Grams(g) => format!("{}g", g),
-            Unit::Litres(l) => format!("{}L", l),
-        };
-        
         write!(f, "Food Stock: {} - {} ({}) - Stored: {}, Expires: {}, Storage: {}, Nutrient: {}",
             self.name,
             self.food_type,
-            quantity_str,
+            self.quantity,
             self.stock_date,
             self.expiry_date,
             self.storage_type,
             self.nutrient
         )
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(input: &str) -> Result<Unit, String> {
+        input.parse()
+    }
+
+    #[test]
+    fn parses_mass_volume_and_count_units() {
+        assert_eq!(parse("2kg"), Ok(Unit::Kilograms(2.0)));
+        assert_eq!(parse("500 ml"), Ok(Unit::Millilitres(500.0)));
+        assert_eq!(parse("1 loaf"), Ok(Unit::Loaves(1.0)));
+        assert_eq!(parse("2 Cups"), Ok(Unit::Cups(2.0)));
+        assert_eq!(parse("1.5L"), Ok(Unit::Litres(1.5)));
+        assert_eq!(parse("3"), Ok(Unit::Pieces(3.0)));
+        assert_eq!(parse("500 ml milk"), Ok(Unit::Millilitres(500.0)));
+    }
+
+    #[test]
+    fn counts_named_items_as_pieces() {
+        assert_eq!(parse("6 eggs"), Ok(Unit::Pieces(6.0)));
+        assert_eq!(parse("4 lemons"), Ok(Unit::Pieces(4.0)));
+        assert_eq!(parse("2 large apples"), Ok(Unit::Pieces(2.0)));
+    }
+
+    #[test]
+    fn rejects_misspelled_units() {
+        assert!(parse("2 kgg").is_err());
+        assert!(parse("2kgg").is_err());
+        assert!(parse("3 pcks").is_err());
+        assert!(parse("1 mll").is_err());
+        assert!(parse("2 kg!").is_err());
+    }
+
+    #[test]
+    fn reads_commas_as_thousands_separators_only() {
+        assert_eq!(parse("1,000g"), Ok(Unit::Grams(1000.0)));
+        assert_eq!(parse("12,500 ml"), Ok(Unit::Millilitres(12500.0)));
+        assert_eq!(parse("1,000,000g"), Ok(Unit::Grams(1_000_000.0)));
+        assert_eq!(parse("1,000.5g"), Ok(Unit::Grams(1000.5)));
+        assert!(parse("1,5kg").is_err());
+        assert!(parse("1,00g").is_err());
+        assert!(parse(",500g").is_err());
+        assert!(parse("1000,000g").is_err());
+    }
+
+    #[test]
+    fn rejects_missing_numbers() {
+        assert!(parse("kg").is_err());
+        assert!(parse("").is_err());
+        assert!(parse("-2kg").is_err());
+    }
+
+    #[test]
+    fn normalises_to_base_units() {
+        assert_eq!(Unit::Kilograms(1.5).to_base(), Unit::Grams(1500.0));
+        assert_eq!(Unit::Litres(2.0).to_base(), Unit::Millilitres(2000.0));
+        assert_eq!(Unit::Dozens(2.0).to_base(), Unit::Pieces(24.0));
+        assert_eq!(Unit::Packs(3.0).to_base(), Unit::Packs(3.0));
+        assert_eq!(Unit::Loaves(1.0).to_base(), Unit::Loaves(1.0));
+    }
+
+    #[test]
+    fn converts_within_a_dimension_only() {
+        let sum = Unit::Millilitres(500.0).checked_add(&Unit::Litres(1.0)).unwrap();
+        assert_eq!(sum, Unit::Millilitres(1500.0));
+        assert_eq!(Unit::Cups(1.0).convert_to(&Unit::Millilitres(0.0)), Ok(Unit::Millilitres(240.0)));
+        assert!(Unit::Grams(1.0).convert_to(&Unit::Millilitres(0.0)).is_err());
+        assert!(Unit::Pieces(1.0).convert_to(&Unit::Packs(0.0)).is_err());
+        assert!(!Unit::Loaves(1.0).is_compatible(&Unit::Pieces(1.0)));
+    }
+
+    #[test]
+    fn humanizes_large_base_quantities() {
+        assert_eq!(Unit::Grams(1500.0).humanize(), Unit::Kilograms(1.5));
+        assert_eq!(Unit::Millilitres(250.0).humanize(), Unit::Millilitres(250.0));
+        assert_eq!(Unit::Litres(2.0).humanize(), Unit::Litres(2.0));
+    }
+
+    #[test]
+    fn displays_counts_with_a_space() {
+        assert_eq!(Unit::Kilograms(2.0).to_string(), "2kg");
+        assert_eq!(Unit::Pieces(6.0).to_string(), "6 pcs");
+    }
+
+    #[test]
+    fn finds_single_edits() {
+        assert!(within_one_edit("kgg", "kg"));
+        assert!(within_one_edit("pcks", "pcs"));
+        assert!(within_one_edit("lb", "lb"));
+        assert!(within_one_edit("cap", "cup"));
+        assert!(!within_one_edit("eggs", "kg"));
+        assert!(!within_one_edit("kiwi", "kilo"));
+    }
+}
@@ -0,0 +1,201 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc, Weekday};
+use serde::ser::SerializeStruct;
+use serde::Serialize;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use crate::error::FoodError;
+use crate::reminder::ReminderPreferences;
+use crate::storage::{Repository, SearchResult, StoredFood};
+
+mod email;
+mod webhook;
+
+pub use email::EmailChannel;
+pub use webhook::WebhookChannel;
+
+/// Future returned by [`NotificationChannel::send`]
+pub type NotifyFuture<'a> = Pin<Box<dyn Future<Output = Result<(), FoodError>> + 'a>>;
+
+/// An item that reached one of its reminder stages
+#[derive(Debug, Clone, Serialize)]
+pub struct DueItem {
+    #[serde(flatten)]
+    pub item: SearchResult,
+    /// Days left until the item expires on the user's calendar; negative once it has expired
+    pub days_left: i64,
+    /// The reminder stage reached, in days before expiry
+    pub stage: u32,
+}
+
+impl DueItem {
+    /// The item as due on the latest of its reminder stages that starts by `by`, if any does.
+    /// `today` is the user's local date, which `days_left` counts from.
+    pub fn reached(stored: &StoredFood, preferences: &ReminderPreferences, today: NaiveDate, by: DateTime<Utc>) -> Option<Self> {
+        let expiry_date = stored.food.expiry_date;
+        let stage = preferences
+            .stages_for(&stored.food)
+            .into_iter()
+            .rev()
+            .find(|days| preferences.reminder_start(expiry_date, *days) <= by)?;
+        Some(DueItem {
+            item: SearchResult::from(stored),
+            days_left: (expiry_date - today).num_days(),
+            stage,
+        })
+    }
+}
+
+/// What one user should hear about from one scan, soonest expiry first
+#[derive(Debug, Clone, Serialize)]
+pub struct ExpiryNotice {
+    pub user_id: i32,
+    /// The user's local date when the scan ran
+    pub date: NaiveDate,
+    pub items: Vec<DueItem>,
+    /// Where the channel delivers the notice, for channels that address each user, such as email
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipient: Option<String>,
+}
+
+impl ExpiryNotice {
+    /// A one-line summary, e.g. "2 items expire soon, 1 has expired"
+    pub fn summary(&self) -> String {
+        let expired = self.items.iter().filter(|due| due.days_left < 0).count();
+        let expiring = self.items.len() - expired;
+        let count = |n: usize| if n == 1 { "1 item".to_string() } else { format!("{} items", n) };
+        match (expiring, expired) {
+            (0, _) => format!("{} expired", count(expired)),
+            (_, 0) => format!("{} expiring soon", count(expiring)),
+            _ => format!("{} expiring soon, {} expired", count(expiring), count(expired)),
+        }
+    }
+}
+
+/// Somewhere expiry notices can be delivered, such as a webhook or a Python callback
+pub trait NotificationChannel: Send {
+    /// Name deliveries are recorded under, so each channel sends an item's stage once.
+    /// Renaming a channel makes it send everything again.
+    fn name(&self) -> &str;
+
+    /// Whether the channel sends digests. A digest channel's `prepare` gets an empty notice for
+    /// every user with food coming up on each scan and fills in the items itself, rather than
+    /// being handed each item once per reminder stage.
+    fn sends_digests(&self) -> bool {
+        false
+    }
+
+    /// Delivers `notice`. On failure nothing is recorded, so the next scan tries again.
+    fn send<'a>(&'a self, notice: &'a ExpiryNotice) -> NotifyFuture<'a>;
+
+    /// Called with the storage lock held before `notice` is sent, to address it or hold it back.
+    /// Returning `false` keeps the user's items for a later scan; by default everything is sent
+    /// as soon as it is due.
+    fn prepare(
+        &self,
+        _storage: &dyn Repository,
+        _notice: &mut ExpiryNotice,
+        _preferences: &ReminderPreferences,
+        _now: DateTime<Utc>,
+    ) -> Result<bool, FoodError> {
+        Ok(true)
+    }
+}
+
+/// Logs notices at info level, for running the scanner without any other channel
+pub struct LogChannel;
+
+impl NotificationChannel for LogChannel {
+    fn name(&self) -> &str {
+        "log"
+    }
+
+    fn send<'a>(&'a self, notice: &'a ExpiryNotice) -> NotifyFuture<'a> {
+        Box::pin(async move {
+            log::info!("User {}: {}", notice.user_id, notice.summary());
+            for due in &notice.items {
+                log::info!("  {}", due.item);
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Most days between two digests, for a weekly one
+pub const MAX_DIGEST_DAYS: i64 = 7;
+
+/// How often a user's email digest goes out
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DigestFrequency {
+    Daily,
+    /// Once a week, on the given day
+    Weekly(Weekday),
+}
+
+impl DigestFrequency {
+    /// Whether a digest goes out on a day that is a `weekday`
+    pub fn includes(&self, weekday: Weekday) -> bool {
+        match self {
+            DigestFrequency::Daily => true,
+            DigestFrequency::Weekly(day) => *day == weekday,
+        }
+    }
+
+    /// Time from one digest to the next
+    pub fn period(&self) -> Duration {
+        match self {
+            DigestFrequency::Daily => Duration::days(1),
+            DigestFrequency::Weekly(_) => Duration::days(MAX_DIGEST_DAYS),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestFrequency::Daily => "daily",
+            DigestFrequency::Weekly(_) => "weekly",
+        }
+    }
+}
+
+impl FromStr for DigestFrequency {
+    type Err = String;
+
+    /// Parses "daily" or "weekly", which goes out on Mondays until another day is chosen
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "daily" => Ok(DigestFrequency::Daily),
+            "weekly" => Ok(DigestFrequency::Weekly(Weekday::Mon)),
+            _ => Err(format!("Invalid digest frequency '{}'. Use 'daily', 'weekly' or 'off'", s)),
+        }
+    }
+}
+
+impl fmt::Display for DigestFrequency {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A user's subscription to email digests of their expiring and expired food
+#[derive(Debug, Clone, PartialEq)]
+pub struct EmailDigest {
+    pub address: String,
+    pub frequency: DigestFrequency,
+}
+
+/// Serialized as `{"address": "ann@example.com", "frequency": "weekly", "weekday": "Mon"}`,
+/// with `weekday` null for daily digests
+impl Serialize for EmailDigest {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let weekday = match self.frequency {
+            DigestFrequency::Daily => None,
+            DigestFrequency::Weekly(day) => Some(day.to_string()),
+        };
+        let mut state = serializer.serialize_struct("EmailDigest", 3)?;
+        state.serialize_field("address", &self.address)?;
+        state.serialize_field("frequency", self.frequency.as_str())?;
+        state.serialize_field("weekday", &weekday)?;
+        state.end()
+    }
+}
@@ -0,0 +1,50 @@
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Request};
+use crate::error::FoodError;
+use crate::http::https_client;
+use super::{ExpiryNotice, NotificationChannel, NotifyFuture};
+
+/// POSTs each notice as JSON (`{"user_id", "date", "items": [...]}`) to a URL, e.g. a chat
+/// bot or push service. Any 2xx answer counts as delivered.
+pub struct WebhookChannel {
+    url: String,
+}
+
+impl WebhookChannel {
+    pub fn new(url: String) -> Self {
+        Self { url }
+    }
+
+    async fn post(&self, notice: &ExpiryNotice) -> Result<(), FoodError> {
+        log::debug!("Posting expiry notice for user {} to {}", notice.user_id, self.url);
+        let body = serde_json::to_vec(notice)
+            .map_err(|e| FoodError::Notification(format!("Could not encode the notice: {}", e)))?;
+        let request = Request::post(&self.url)
+            .header(CONTENT_TYPE, "application/json")
+            .body(Body::from(body))
+            .map_err(|e| FoodError::Notification(format!("Invalid webhook URL '{}': {}", self.url, e)))?;
+
+        let response = https_client()
+            .request(request)
+            .await
+            .map_err(|e| FoodError::Notification(format!("Could not reach webhook {}: {}", self.url, e)))?;
+        if !response.status().is_success() {
+            return Err(FoodError::Notification(format!(
+                "Webhook {} answered {}",
+                self.url,
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl NotificationChannel for WebhookChannel {
+    fn name(&self) -> &str {
+        "webhook"
+    }
+
+    fn send<'a>(&'a self, notice: &'a ExpiryNotice) -> NotifyFuture<'a> {
+        Box::pin(self.post(notice))
+    }
+}
@@ -1,197 +1,670 @@
-use chrono::{Duration, Utc, TimeZone, NaiveDate, DateTime};
-use google_calendar3::{CalendarHub, oauth2, api::Event, api::EventDateTime};
-use hyper::{Client};
-use hyper_rustls::HttpsConnectorBuilder;
-use std::default::Default;
-use std::path::Path;
-use crate::models::{FoodStock, FoodType, StorageType, Unit, MajorNutrient};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveDateTime, NaiveTime, Offset, Utc, TimeZone};
+use chrono_tz::{OffsetComponents, OffsetName, Tz};
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+use crate::error::FoodError;
+use crate::models::FoodStock;
+use crate::storage::StoredFood;
+use serde::ser::SerializeStruct;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
-// Simple URL encoding function for basic characters
-fn simple_url_encode(s: &str) -> String {
-    s.replace(" ", "%20")
-     .replace("!", "%21")
-     .replace("(", "%28")
-     .replace(")", "%29")
-     .replace(",", "%2C")
-     .replace(":", "%3A")
-     .replace("'", "%27")
-}
-
-pub fn generate_calendar_links(food: &FoodStock) -> String {
-    // Generate direct calendar links for adding food expiry reminders
-    
-    // Calculate reminder date (1 day before expiry)
-    let reminder_date = food.expiry_date - Duration::days(1);
-    let reminder_datetime = Utc.from_utc_date(&reminder_date).and_hms_opt(9, 0, 0).unwrap();
-    let end_datetime = reminder_datetime + Duration::hours(1);
-    
-    // Event details
-    let event_title = format!("Check your {} before it expires!", food.name);
-    let quantity_str = match &food.quantity {
-        Unit::Grams(g) => format!("{}g", g),
-        Unit::Litres(l) => format!("{}L", l),
-    };
-    
-    let event_description = format!(
-        "Your {} ({}, {}, stored in {} storage) is expiring on {}. Check freshness and plan to use it soon.",
+mod caldav;
+mod google;
+
+pub use caldav::CalDavCalendar;
+pub use google::GoogleCalendar;
+
+/// Future returned by [`CalendarProvider`] methods
+pub type ProviderFuture<'a, T> = Pin<Box<dyn Future<Output = Result<T, FoodError>> + 'a>>;
+
+/// An event a provider created, as that provider identifies it
+pub struct CalendarEvent {
+    pub id: String,
+    /// Where the user can open the event, if the provider has a web view
+    pub link: Option<String>,
+}
+
+/// A calendar service that expiry reminders can be pushed to
+pub trait CalendarProvider {
+    /// Creates `event`, an item's reminder or a day's digest; `uid` names it as in the `.ics` export
+    fn create_event<'a>(&'a self, uid: &'a str, event: &'a ReminderEvent) -> ProviderFuture<'a, CalendarEvent>;
+
+    /// Rewrites an event made by `create_event` to match `event` as it is now
+    fn update_event<'a>(&'a self, event_id: &'a str, uid: &'a str, event: &'a ReminderEvent) -> ProviderFuture<'a, CalendarEvent>;
+
+    /// Removes an event made by `create_event`. An event that is already gone counts as removed.
+    fn delete_event<'a>(&'a self, event_id: &'a str) -> ProviderFuture<'a, ()>;
+
+    /// Credentials the provider renewed while working, which should replace the stored ones
+    fn refreshed_token(&self) -> Option<Value> {
+        None
+    }
+}
+
+/// Which calendar a user's reminders go to and the credentials to reach it,
+/// e.g. `{"provider": "caldav", "url": "...", "username": "...", "password": "..."}`
+#[derive(Deserialize)]
+#[serde(tag = "provider", rename_all = "lowercase")]
+pub enum CalendarSettings {
+    Google {
+        token: Value,
+    },
+    Caldav {
+        /// URL of the calendar collection
+        url: String,
+        username: Option<String>,
+        password: Option<String>,
+    },
+}
+
+impl CalendarSettings {
+    /// Reads a user's settings. A bare Google token, as passed before providers could be
+    /// chosen, selects Google.
+    pub fn from_json(json: &str) -> Result<Self, FoodError> {
+        let value: Value = serde_json::from_str(json)
+            .map_err(|e| FoodError::Calendar(format!("Calendar settings are not valid JSON: {}", e)))?;
+        if value.get("provider").is_none() {
+            return Ok(CalendarSettings::Google { token: value });
+        }
+        serde_json::from_value(value).map_err(|e| FoodError::Calendar(format!("Invalid calendar settings: {}", e)))
+    }
+
+    /// The provider's name as it appears in the `provider` field
+    pub fn name(&self) -> &'static str {
+        match self {
+            CalendarSettings::Google { .. } => "google",
+            CalendarSettings::Caldav { .. } => "caldav",
+        }
+    }
+
+    pub fn provider(self) -> Box<dyn CalendarProvider> {
+        match self {
+            CalendarSettings::Google { token } => Box::new(GoogleCalendar::new(token)),
+            CalendarSettings::Caldav { url, username, password } => Box::new(CalDavCalendar::new(url, username, password)),
+        }
+    }
+}
+
+/// Days before expiry a reminder goes off unless the user chose otherwise
+pub const DEFAULT_LEAD_DAYS: u32 = 1;
+
+/// Most days ahead of expiry a user may ask to be reminded. Four weeks is the longest
+/// reminder Google Calendar accepts ahead of an event.
+pub const MAX_LEAD_DAYS: u32 = 28;
+
+/// Most reminders one item can have; Google accepts no more alarms on an event
+pub const MAX_REMINDER_STAGES: usize = 5;
+
+/// How a user's reminders are pushed to their calendar
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReminderMode {
+    /// One event per food item
+    Item,
+    /// One "use it up" event per day, listing every item expiring that day
+    Digest,
+}
+
+impl ReminderMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReminderMode::Item => "item",
+            ReminderMode::Digest => "digest",
+        }
+    }
+}
+
+impl FromStr for ReminderMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "item" | "per-item" => Ok(ReminderMode::Item),
+            "digest" | "daily" => Ok(ReminderMode::Digest),
+            _ => Err(format!("Invalid reminder mode '{}'. Use 'item' or 'digest'", s)),
+        }
+    }
+}
+
+impl fmt::Display for ReminderMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// When a user's expiry reminders go off: `lead_days` before the expiry date, at `time`
+/// on the clock in `timezone`. Calendar events, links and `.ics` files all follow it;
+/// `mode` picks per-item or daily digest events for the user's calendar.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReminderPreferences {
+    pub lead_days: u32,
+    pub time: NaiveTime,
+    pub timezone: Tz,
+    pub mode: ReminderMode,
+}
+
+impl ReminderPreferences {
+    /// The default schedule, 9:00 on the day before expiry, in `timezone`, one event per item
+    pub fn with_timezone(timezone: Tz) -> Self {
+        Self {
+            lead_days: DEFAULT_LEAD_DAYS,
+            time: NaiveTime::from_hms_opt(9, 0, 0).unwrap(),
+            timezone,
+            mode: ReminderMode::Item,
+        }
+    }
+
+    /// The days before expiry `food` is reminded on, furthest first: the item's own schedule,
+    /// or else its food type's stages plus the user's `lead_days`. Only the
+    /// [`MAX_REMINDER_STAGES`] closest to expiry are kept.
+    pub fn stages_for(&self, food: &FoodStock) -> Vec<u32> {
+        let mut stages = if food.reminder_days.is_empty() {
+            let mut stages = food.food_type.reminder_days().to_vec();
+            stages.push(self.lead_days);
+            stages
+        } else {
+            food.reminder_days.clone()
+        };
+        stages.sort_unstable();
+        stages.dedup();
+        stages.truncate(MAX_REMINDER_STAGES);
+        stages.reverse();
+        stages
+    }
+
+    /// When a reminder `days_before` food expiring on `expiry_date` starts
+    pub fn reminder_start(&self, expiry_date: NaiveDate, days_before: u32) -> DateTime<Tz> {
+        let local = (expiry_date - Duration::days(i64::from(days_before))).and_time(self.time);
+        self.timezone
+            .from_local_datetime(&local)
+            .earliest()
+            // Only a time skipped by a daylight saving change lands here; read it as UTC instead
+            .unwrap_or_else(|| Utc.from_utc_datetime(&local).with_timezone(&self.timezone))
+    }
+}
+
+/// Serialized as `{"lead_days": 1, "time": "09:00", "timezone": "Europe/Berlin", "mode": "item"}`
+impl Serialize for ReminderPreferences {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut state = serializer.serialize_struct("ReminderPreferences", 4)?;
+        state.serialize_field("lead_days", &self.lead_days)?;
+        state.serialize_field("time", &self.time.format("%H:%M").to_string())?;
+        state.serialize_field("timezone", self.timezone.name())?;
+        state.serialize_field("mode", self.mode.as_str())?;
+        state.end()
+    }
+}
+
+/// What a food's expiry reminder says and when it happens. Both the calendar links and the
+/// iCalendar export are built from it, so they always describe the same event.
+#[derive(Clone)]
+pub struct ReminderEvent {
+    pub title: String,
+    pub description: String,
+    pub location: String,
+    pub start: DateTime<Tz>,
+    pub end: DateTime<Tz>,
+    /// Days before `start` each alarm goes off, furthest first; 0 is the start itself
+    pub alarms: Vec<u32>,
+}
+
+impl ReminderEvent {
+    /// The hour-long reminder for `food` on its last stage, with an alarm for every stage before it
+    pub fn for_food(food: &FoodStock, preferences: &ReminderPreferences) -> Self {
+        let stages = preferences.stages_for(food);
+        let last = stages.last().copied().unwrap_or(preferences.lead_days);
+        let mut event = Self::for_stage(food, preferences, last);
+        event.alarms = stages.iter().map(|days| days - last).collect();
+        event
+    }
+
+    /// A reminder of its own for `food`, `days_before` it expires
+    pub fn for_stage(food: &FoodStock, preferences: &ReminderPreferences, days_before: u32) -> Self {
+        let start = preferences.reminder_start(food.expiry_date, days_before);
+        Self {
+            title: format!("Check your {} before it expires!", food.name),
+            description: format!(
+                "Your {} ({}, {}, stored in {} storage) is expiring on {}. Check freshness and plan to use it soon.",
+                food.name,
+                food.food_type,
+                food.quantity.humanize(),
+                food.storage_type,
+                food.expiry_date
+            ),
+            location: "Kitchen/Pantry".to_string(),
+            start,
+            end: start + Duration::hours(1),
+            alarms: vec![0],
+        }
+    }
+
+    /// The "use it up" digest for `date`, listing every item in `foods` (those expiring that day).
+    /// It goes off `lead_days` before the day like a single item's reminder.
+    pub fn digest(date: NaiveDate, foods: &[StoredFood], preferences: &ReminderPreferences) -> Self {
+        let start = preferences.reminder_start(date, preferences.lead_days);
+        let title = match foods {
+            [stored] => format!("Use it up: your {} expires on {}", stored.food.name, date.format("%B %-d")),
+            _ => format!("Use it up: {} items expire on {}", foods.len(), date.format("%B %-d")),
+        };
+        let items: Vec<String> = foods
+            .iter()
+            .map(|stored| {
+                let food = &stored.food;
+                format!("• {} ({}, {})", food.name, food.quantity.humanize(), food.storage_type)
+            })
+            .collect();
+        Self {
+            title,
+            description: format!("Expiring on {}:\n{}\n\nPlan a meal around them soon.", date, items.join("\n")),
+            location: "Kitchen/Pantry".to_string(),
+            start,
+            end: start + Duration::hours(1),
+            alarms: vec![0],
+        }
+    }
+
+    /// Appends the event as an RFC 5545 `VEVENT` identified by `uid`, with a `VALARM` for
+    /// each of its alarms. Start and end are local times in `tz`, whose `VTIMEZONE` the
+    /// calendar must contain, so the reminder stays at the user's hour across DST changes.
+    fn write_vevent(&self, out: &mut String, uid: &str, stamp: &DateTime<Utc>, tz: Tz) {
+        ics_line(out, "BEGIN:VEVENT");
+        ics_line(out, &format!("UID:{}", ics_escape(uid)));
+        ics_line(out, &format!("DTSTAMP:{}", ics_timestamp(stamp)));
+        ics_line(out, &format!("DTSTART;TZID={}:{}", tz.name(), ics_local_time(&self.start.with_timezone(&tz))));
+        ics_line(out, &format!("DTEND;TZID={}:{}", tz.name(), ics_local_time(&self.end.with_timezone(&tz))));
+        ics_line(out, &format!("SUMMARY:{}", ics_escape(&self.title)));
+        ics_line(out, &format!("DESCRIPTION:{}", ics_escape(&self.description)));
+        ics_line(out, &format!("LOCATION:{}", ics_escape(&self.location)));
+        for days in &self.alarms {
+            ics_line(out, "BEGIN:VALARM");
+            ics_line(out, "ACTION:DISPLAY");
+            ics_line(out, &format!("DESCRIPTION:{}", ics_escape(&self.title)));
+            ics_line(out, &if *days == 0 { "TRIGGER:PT0S".to_string() } else { format!("TRIGGER:-P{}D", days) });
+            ics_line(out, "END:VALARM");
+        }
+        ics_line(out, "END:VEVENT");
+    }
+}
+
+/// Escapes an iCalendar TEXT value (RFC 5545 section 3.3.11)
+fn ics_escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn ics_timestamp<Z: TimeZone>(time: &DateTime<Z>) -> String {
+    time.with_timezone(&Utc).format("%Y%m%dT%H%M%SZ").to_string()
+}
+
+/// A local DATE-TIME, to be qualified by a `TZID` parameter
+fn ics_local_time(time: &DateTime<Tz>) -> String {
+    time.format("%Y%m%dT%H%M%S").to_string()
+}
+
+/// A UTC-OFFSET value such as `+0200` or `-0930`, with seconds only when there are any
+fn ics_utc_offset(seconds: i32) -> String {
+    let sign = if seconds < 0 { '-' } else { '+' };
+    let seconds = seconds.abs();
+    let (hours, minutes, rest) = (seconds / 3600, seconds / 60 % 60, seconds % 60);
+    if rest == 0 {
+        format!("{}{:02}{:02}", sign, hours, minutes)
+    } else {
+        format!("{}{:02}{:02}{:02}", sign, hours, minutes, rest)
+    }
+}
+
+/// The offset from UTC a zone is on at one instant, in seconds, and whether it is daylight time
+#[derive(Clone, Copy, PartialEq)]
+struct ZoneOffset {
+    seconds: i32,
+    daylight: bool,
+}
+
+impl ZoneOffset {
+    fn at(tz: Tz, utc: NaiveDateTime) -> Self {
+        let offset = tz.offset_from_utc_datetime(&utc);
+        Self { seconds: offset.fix().local_minus_utc(), daylight: !offset.dst_offset().is_zero() }
+    }
+}
+
+/// One `STANDARD` or `DAYLIGHT` observance: from `start` (UTC) on, the zone moves from `from` to `to`
+struct Observance {
+    start: NaiveDateTime,
+    from: ZoneOffset,
+    to: ZoneOffset,
+    name: Option<String>,
+}
+
+/// The zone's observances from the start of `first_year` to the end of `last_year`: the offset in
+/// force at the start, then each change, found to the second. Each is written with its own
+/// `DTSTART` rather than an `RRULE`, so zones whose rules changed over the years come out right.
+fn observances(tz: Tz, first_year: i32, last_year: i32) -> Vec<Observance> {
+    let year_start = |year| NaiveDate::from_ymd_opt(year, 1, 1).unwrap_or(NaiveDate::MIN).and_time(NaiveTime::MIN);
+    let name = |utc: NaiveDateTime| tz.offset_from_utc_datetime(&utc).abbreviation().map(str::to_string);
+    let start = year_start(first_year);
+    let end = year_start(last_year + 1);
+
+    let mut current = ZoneOffset::at(tz, start);
+    let mut observances = vec![Observance { start, from: current, to: current, name: name(start) }];
+    let mut day = start;
+    while day < end {
+        let next_day = day + Duration::days(1);
+        let next = ZoneOffset::at(tz, next_day);
+        if next != current {
+            let (mut before, mut after) = (day, next_day);
+            while after - before > Duration::seconds(1) {
+                let middle = before + (after - before) / 2;
+                if ZoneOffset::at(tz, middle) == current {
+                    before = middle;
+                } else {
+                    after = middle;
+                }
+            }
+            observances.push(Observance { start: after, from: current, to: next, name: name(after) });
+            current = next;
+        }
+        day = next_day;
+    }
+    observances
+}
+
+/// Appends the `VTIMEZONE` defining `tz` over the given years
+fn write_vtimezone(out: &mut String, tz: Tz, first_year: i32, last_year: i32) {
+    ics_line(out, "BEGIN:VTIMEZONE");
+    ics_line(out, &format!("TZID:{}", tz.name()));
+    for observance in observances(tz, first_year, last_year) {
+        let kind = if observance.to.daylight { "DAYLIGHT" } else { "STANDARD" };
+        // An observance starts at the local time of the offset it replaces
+        let local_start = observance.start + Duration::seconds(i64::from(observance.from.seconds));
+        ics_line(out, &format!("BEGIN:{}", kind));
+        ics_line(out, &format!("DTSTART:{}", local_start.format("%Y%m%dT%H%M%S")));
+        ics_line(out, &format!("TZOFFSETFROM:{}", ics_utc_offset(observance.from.seconds)));
+        ics_line(out, &format!("TZOFFSETTO:{}", ics_utc_offset(observance.to.seconds)));
+        if let Some(name) = observance.name {
+            ics_line(out, &format!("TZNAME:{}", ics_escape(&name)));
+        }
+        ics_line(out, &format!("END:{}", kind));
+    }
+    ics_line(out, "END:VTIMEZONE");
+}
+
+/// Appends one content line, folded so no physical line exceeds 75 octets (RFC 5545 section 3.1)
+fn ics_line(out: &mut String, line: &str) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > 75 {
+            out.push_str("\r\n ");
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Stable iCalendar `UID` of a stored item's reminder, so re-importing a feed updates events instead of duplicating them
+pub fn reminder_uid(food_id: i32) -> String {
+    format!("food-{}@food_agent", food_id)
+}
+
+/// Stable iCalendar `UID` of a user's digest for the food expiring on `date`
+pub fn digest_uid(user_id: i32, date: NaiveDate) -> String {
+    format!("digest-{}-{}@food_agent", user_id, date.format("%Y%m%d"))
+}
+
+/// An iCalendar feed with one event per `(uid, event)` pair, which any calendar app can import or
+/// subscribe to. Event times are written in `tz`, defined by a `VTIMEZONE` covering the years
+/// the events fall in, plus one either side.
+pub fn ics_calendar(events: &[(String, ReminderEvent)], tz: Tz) -> String {
+    let stamp = Utc::now();
+    let mut out = String::new();
+    ics_line(&mut out, "BEGIN:VCALENDAR");
+    ics_line(&mut out, "VERSION:2.0");
+    ics_line(&mut out, "PRODID:-//food_agent//Food Expiry Reminders//EN");
+    ics_line(&mut out, "CALSCALE:GREGORIAN");
+    ics_line(&mut out, "X-WR-CALNAME:Food expiry reminders");
+    ics_line(&mut out, &format!("X-WR-TIMEZONE:{}", tz.name()));
+    let years = events
+        .iter()
+        .flat_map(|(_, event)| [event.start.with_timezone(&tz).year(), event.end.with_timezone(&tz).year()]);
+    if let (Some(first), Some(last)) = (years.clone().min(), years.max()) {
+        write_vtimezone(&mut out, tz, first - 1, last + 1);
+    }
+    for (uid, event) in events {
+        event.write_vevent(&mut out, uid, &stamp, tz);
+    }
+    ics_line(&mut out, "END:VCALENDAR");
+    out
+}
+
+/// MIME type of iCalendar data
+pub const ICS_CONTENT_TYPE: &str = "text/calendar; charset=utf-8";
+
+/// A single item's reminder as a downloadable `.ics` file
+#[derive(Debug, Serialize)]
+pub struct CalendarAttachment {
+    pub filename: String,
+    pub content_type: &'static str,
+    pub content: String,
+}
+
+impl CalendarAttachment {
+    pub fn for_food(stored: &StoredFood, preferences: &ReminderPreferences) -> Self {
+        let slug: String = stored
+            .food
+            .name
+            .to_lowercase()
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|word| !word.is_empty())
+            .collect::<Vec<_>>()
+            .join("-");
+        let event = ReminderEvent::for_food(&stored.food, preferences);
+        Self {
+            filename: format!("{}-expiry.ics", if slug.is_empty() { "food" } else { &slug }),
+            content_type: ICS_CONTENT_TYPE,
+            content: ics_calendar(&[(reminder_uid(stored.id), event)], preferences.timezone),
+        }
+    }
+}
+
+/// How a reminder stage reads to the user, e.g. "2 days before expiry"
+fn stage_label(days_before: u32) -> String {
+    match days_before {
+        0 => "On the expiry day".to_string(),
+        1 => "1 day before expiry".to_string(),
+        days => format!("{} days before expiry", days),
+    }
+}
+
+/// Links to add `food`'s reminders to Google or Outlook Calendar, which take no alarms,
+/// so every stage of its schedule gets links of its own
+pub fn generate_calendar_links(food: &FoodStock, preferences: &ReminderPreferences) -> String {
+    let stages: Vec<String> = preferences
+        .stages_for(food)
+        .into_iter()
+        .map(|days| {
+            let event = ReminderEvent::for_stage(food, preferences, days);
+            let start_iso = event.start.with_timezone(&Utc).format("%Y-%m-%dT%H:%M:%SZ").to_string();
+            let end_iso = event.end.with_timezone(&Utc).format("%Y-%m-%dT%H:%M:%SZ").to_string();
+            let title: String = form_urlencoded::byte_serialize(event.title.as_bytes()).collect();
+            let description: String = form_urlencoded::byte_serialize(event.description.as_bytes()).collect();
+            let location: String = form_urlencoded::byte_serialize(event.location.as_bytes()).collect();
+
+            let google_url = format!(
+                "https://calendar.google.com/calendar/render?action=TEMPLATE&text={}&dates={}/{}&details={}&location={}",
+                title,
+                ics_timestamp(&event.start),
+                ics_timestamp(&event.end),
+                description,
+                location
+            );
+            let outlook_url = format!(
+                "https://outlook.live.com/calendar/0/deeplink/compose?subject={}&startdt={}&enddt={}&body={}&location={}",
+                title,
+                start_iso,
+                end_iso,
+                description,
+                location
+            );
+            format!(
+                "**{}** ({})\n🔗 **[Add to Google Calendar]({})**\n🔗 **[Add to Outlook Calendar]({})**",
+                stage_label(days),
+                event.start.format("%B %d, %Y at %-I:%M %p %Z"),
+                google_url,
+                outlook_url
+            )
+        })
+        .collect();
+
+    format!(
+        "📅 **Calendar Reminder Links**\n\nClick the links below to add these reminders to your calendar:\n\n{}\n\n**Event Details:**\n• **Title:** Check your {} before it expires!\n• **Food:** {} ({}, {})\n• **Storage:** {} storage\n• **Expires:** {}\n\n💡 **Tip:** Connect your Google Calendar in settings for automatic reminders!",
+        stages.join("\n\n"),
         food.name,
-        food.food_type,
-        quantity_str,
-        food.storage_type,
-        food.expiry_date
-    );
-    
-    // Format dates for calendar URLs
-    let start_utc = reminder_datetime.format("%Y%m%dT%H%M%SZ").to_string();
-    let end_utc = end_datetime.format("%Y%m%dT%H%M%SZ").to_string();
-    let start_iso = reminder_datetime.format("%Y-%m-%dT%H:%M:%S").to_string();
-    let end_iso = end_datetime.format("%Y-%m-%dT%H:%M:%S").to_string();
-    
-    // Create Google Calendar URL
-    let google_url = format!(
-        "https://calendar.google.com/calendar/render?action=TEMPLATE&text={}&dates={}/{}&details={}&location={}",
-        simple_url_encode(&event_title),
-        start_utc,
-        end_utc,
-        simple_url_encode(&event_description),
-        simple_url_encode("Kitchen/Pantry")
-    );
-    
-    // Create Outlook Calendar URL
-    let outlook_url = format!(
-        "https://outlook.live.com/calendar/0/deeplink/compose?subject={}&startdt={}&enddt={}&body={}&location={}",
-        simple_url_encode(&event_title),
-        start_iso,
-        end_iso,
-        simple_url_encode(&event_description),
-        simple_url_encode("Kitchen/Pantry")
-    );
-    
-    // Create the calendar message with links
-    let calendar_message = format!(
-        "📅 **Calendar Reminder Links**\n\nClick any link below to add this reminder to your calendar:\n\n🔗 **[Add to Google Calendar]({})**\n🔗 **[Add to Outlook Calendar]({})**\n\n**Event Details:**\n• **Title:** {}\n• **Date:** {}\n• **Food:** {} ({}, {})\n• **Storage:** {} storage\n• **Expires:** {}\n\n💡 **Tip:** Connect your Google Calendar in settings for automatic reminders!",
-        google_url,
-        outlook_url,
-        event_title,
-        reminder_datetime.format("%B %d, %Y at 9:00 AM"),
         food.name,
         food.food_type,
-        quantity_str,
+        food.quantity,
         food.storage_type,
         food.expiry_date
-    );
-    
-    calendar_message
-}
-
-pub async fn create_calendar_event_with_user_token(food: &FoodStock, user_id: i32, token_data: Option<Value>) -> Result<String, Box<dyn std::error::Error>> {
-    // If no token data provided, return calendar links instead of error
-    let token_data = match token_data {
-        Some(data) => data,
-        None => {
-            println!("DEBUG: No Google Calendar token available for user {}, generating calendar links", user_id);
-            return Ok(generate_calendar_links(food));
-        }
-    };
-
-    println!("DEBUG: Creating calendar event for user {} with stored token", user_id);
-
-    // Try multiple possible locations for secrets.json
-    let possible_paths = [
-        "secrets.json",
-        "../secrets.json",
-        "../../secrets.json",
-        "./secrets.json",
-    ];
-    
-    let mut secrets_path = None;
-    for path in &possible_paths {
-        if Path::new(path).exists() {
-            secrets_path = Some(*path);
-            break;
-        }
-    }
-    
-    let secrets_path = secrets_path.ok_or_else(|| {
-        format!("secrets.json not found. Searched in: {:?}. Please ensure your OAuth 2.0 credentials are saved as 'secrets.json' in an accessible location.", possible_paths)
-    })?;
-
-    let secret = oauth2::read_application_secret(secrets_path).await?;
-    
-    // Create authenticator with stored credentials - for now, we'll use file-based approach
-    // but pass the token data through a temporary file or memory
-    let auth = oauth2::InstalledFlowAuthenticator::builder(secret, oauth2::InstalledFlowReturnMethod::HTTPRedirect)
-        .persist_tokens_to_disk("token.json") // This will be ignored if token exists
-        .build()
-        .await?;
-
-    let hub = CalendarHub::new(
-        Client::builder().build(
-            HttpsConnectorBuilder::new()
-                .with_native_roots()
-                .https_or_http()
-                .enable_http1()
-                .build(),
-        ),
-        auth,
-    );
-
-    let reminder_date = Utc.from_utc_date(&food.expiry_date).and_hms_opt(9, 0, 0).unwrap();
-
-    let summary = format!("Check your {} before it expires!", food.name);
-    let quantity_str = match &food.quantity {
-        Unit::Grams(g) => format!("{}g", g),
-        Unit::Litres(l) => format!("{}L", l),
-    };
-    let description = format!(
-        "Your {} ({}) will get bad after {}. Storage type: {}",
-        food.food_type,
-        quantity_str,
-        food.expiry_date,
-        food.storage_type,
-    );
-
-    let event = Event {
-        summary: Some(summary),
-        description: Some(description),
-        start: Some(EventDateTime {
-            date_time: Some(reminder_date),
-            time_zone: Some("UTC".to_string()),
-            ..Default::default()
-        }),
-        end: Some(EventDateTime {
-            date_time: Some(reminder_date + Duration::hours(1)),
-            time_zone: Some("UTC".to_string()),
-            ..Default::default()
-        }),
-        ..Default::default()
-    };
-
-    let result = hub.events().insert(event, "primary").doit().await;
-
-    match result {
-        Ok((_, event)) => {
-            println!("DEBUG: Successfully created calendar event for user {}", user_id);
-            if let Some(html_link) = event.html_link {
-                Ok(format!("Calendar event created: {}", html_link))
-            } else {
-                Ok("Calendar event created successfully".to_string())
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn event_at(tz: Tz, year: i32, month: u32, day: u32) -> ReminderEvent {
+        let start = tz.with_ymd_and_hms(year, month, day, 9, 0, 0).unwrap();
+        ReminderEvent {
+            title: "Check your milk before it expires!".to_string(),
+            description: "Milk".to_string(),
+            location: "Kitchen/Pantry".to_string(),
+            start,
+            end: start + Duration::hours(1),
+            alarms: vec![2, 0],
+        }
+    }
+
+    /// The unfolded content lines of `ics`
+    fn lines(ics: &str) -> Vec<String> {
+        ics.replace("\r\n ", "").split("\r\n").filter(|line| !line.is_empty()).map(str::to_string).collect()
+    }
+
+    /// The lines of every observance of the given kind, each joined with "|"
+    fn observances_of(ics: &str, kind: &str) -> Vec<String> {
+        let lines = lines(ics);
+        let mut found = Vec::new();
+        let mut current: Option<Vec<String>> = None;
+        for line in lines {
+            if line == format!("BEGIN:{}", kind) {
+                current = Some(Vec::new());
+            } else if line == format!("END:{}", kind) {
+                found.push(current.take().unwrap().join("|"));
+            } else if let Some(current) = current.as_mut() {
+                current.push(line);
             }
-        },
-        Err(e) => {
-            eprintln!("DEBUG: Error creating calendar event for user {}: {:?}", user_id, e);
-            // If calendar creation fails, return calendar links as fallback
-            Ok(generate_calendar_links(food))
         }
+        found
+    }
+
+    #[test]
+    fn escapes_text_values() {
+        assert_eq!(ics_escape("milk, eggs; bread"), r"milk\, eggs\; bread");
+        assert_eq!(ics_escape("a\\b"), "a\\\\b");
+        assert_eq!(ics_escape("line one\r\nline two\nthree"), "line one\\nline two\\nthree");
+    }
+
+    #[test]
+    fn links_encode_every_reserved_character() {
+        use crate::models::{FoodType, StorageType, Unit};
+        let date = NaiveDate::from_ymd_opt(2027, 3, 10).unwrap();
+        let food = FoodStock::new(
+            "mac & cheese #2?\n50%+".to_string(),
+            date,
+            FoodType::Dairy,
+            FoodType::Dairy.major_nutrient(),
+            StorageType::Cold,
+            date,
+            Unit::Pieces(1.0),
+        );
+        let links = generate_calendar_links(&food, &ReminderPreferences::with_timezone(Tz::UTC));
+        assert!(links.contains("text=Check+your+mac+%26+cheese+%232%3F%0A50%25%2B+before+it+expires%21&dates="));
+        assert!(links.contains("subject=Check+your+mac+%26+cheese+%232%3F%0A50%25%2B+before+it+expires%21&startdt="));
+    }
+
+    #[test]
+    fn folds_lines_at_75_octets() {
+        let mut out = String::new();
+        ics_line(&mut out, &"a".repeat(160));
+        let physical: Vec<&str> = out.trim_end_matches("\r\n").split("\r\n").collect();
+        assert_eq!(physical.iter().map(|line| line.len()).collect::<Vec<_>>(), vec![75, 75, 12]);
+        assert!(physical[1..].iter().all(|line| line.starts_with(' ')));
+
+        let mut short = String::new();
+        ics_line(&mut short, "SUMMARY:milk");
+        assert_eq!(short, "SUMMARY:milk\r\n");
+    }
+
+    #[test]
+    fn folding_never_splits_a_character() {
+        let line = format!("SUMMARY:{}", "é".repeat(60));
+        let mut out = String::new();
+        ics_line(&mut out, &line);
+        for physical in out.trim_end_matches("\r\n").split("\r\n") {
+            assert!(physical.len() <= 75, "{} octets", physical.len());
+        }
+        assert_eq!(lines(&out), vec![line]);
+    }
+
+    #[test]
+    fn writes_utc_offsets() {
+        assert_eq!(ics_utc_offset(7200), "+0200");
+        assert_eq!(ics_utc_offset(0), "+0000");
+        assert_eq!(ics_utc_offset(-(9 * 3600 + 30 * 60)), "-0930");
+        assert_eq!(ics_utc_offset(-(17 * 60 + 30)), "-001730");
+    }
+
+    #[test]
+    fn events_use_the_zone_with_a_matching_vtimezone() {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let ics = ics_calendar(&[("food-1@food_agent".to_string(), event_at(berlin, 2026, 7, 10))], berlin);
+        let lines = lines(&ics);
+
+        assert!(lines.contains(&"DTSTART;TZID=Europe/Berlin:20260710T090000".to_string()));
+        assert!(lines.contains(&"DTEND;TZID=Europe/Berlin:20260710T100000".to_string()));
+        assert!(lines.contains(&"TZID:Europe/Berlin".to_string()));
+        let vtimezone = lines.iter().position(|line| line == "BEGIN:VTIMEZONE").unwrap();
+        let vevent = lines.iter().position(|line| line == "BEGIN:VEVENT").unwrap();
+        assert!(vtimezone < vevent);
+
+        let daylight = observances_of(&ics, "DAYLIGHT");
+        let standard = observances_of(&ics, "STANDARD");
+        assert!(daylight.contains(&"DTSTART:20260329T020000|TZOFFSETFROM:+0100|TZOFFSETTO:+0200|TZNAME:CEST".to_string()));
+        assert!(standard.contains(&"DTSTART:20261025T030000|TZOFFSETFROM:+0200|TZOFFSETTO:+0100|TZNAME:CET".to_string()));
+        // 2025 to 2027: the zone as of January 2025, then two changes a year
+        assert_eq!(daylight.len(), 3);
+        assert_eq!(standard.len(), 4);
+        assert!(standard[0].starts_with("DTSTART:20250101T010000|TZOFFSETFROM:+0100|TZOFFSETTO:+0100"));
+    }
+
+    #[test]
+    fn fixed_offset_zones_have_one_observance() {
+        let kolkata: Tz = "Asia/Kolkata".parse().unwrap();
+        let ics = ics_calendar(&[("food-1@food_agent".to_string(), event_at(kolkata, 2026, 3, 1))], kolkata);
+        assert_eq!(observances_of(&ics, "STANDARD").len(), 1);
+        assert!(observances_of(&ics, "DAYLIGHT").is_empty());
+        assert!(ics.contains("TZOFFSETTO:+0530\r\n"));
+        assert!(ics.contains("DTSTART;TZID=Asia/Kolkata:20260301T090000\r\n"));
     }
-}
 
-// Keep the original function for backward compatibility, but mark it as deprecated
-pub async fn create_calendar_event(food: &FoodStock) -> Result<(), Box<dyn std::error::Error>> {
-    // This is the old implementation - we'll keep it for now but use the new one
-    match create_calendar_event_with_user_token(food, 0, None).await {
-        Ok(_) => Ok(()),
-        Err(e) => Err(e)
+    #[test]
+    fn empty_calendars_need_no_vtimezone() {
+        let ics = ics_calendar(&[], Tz::UTC);
+        assert!(!ics.contains("VTIMEZONE"));
+        assert!(ics.ends_with("END:VCALENDAR\r\n"));
     }
 }
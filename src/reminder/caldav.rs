@@ -0,0 +1,202 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use hyper::header::{AUTHORIZATION, CONTENT_TYPE};
+use hyper::{Body, Method, Request, Response, StatusCode};
+use crate::error::FoodError;
+use crate::http::https_client;
+use super::{ics_calendar, CalendarEvent, CalendarProvider, ProviderFuture, ReminderEvent, ICS_CONTENT_TYPE};
+
+/// Pushes reminders to a CalDAV calendar collection, such as a Nextcloud calendar.
+/// Each reminder is its own `.ics` resource named after its UID, so pushing it again
+/// replaces it. Plain `http://` URLs work too, e.g. for a local CalDAV stand-in.
+pub struct CalDavCalendar {
+    calendar_url: String,
+    username: Option<String>,
+    password: Option<String>,
+}
+
+impl CalDavCalendar {
+    /// `calendar_url` is the collection, e.g. `https://cloud.example.com/remote.php/dav/calendars/me/personal/`.
+    /// With a username, requests use HTTP basic auth.
+    pub fn new(calendar_url: String, username: Option<String>, password: Option<String>) -> Self {
+        Self { calendar_url, username, password }
+    }
+
+    fn resource_url(&self, uid: &str) -> String {
+        format!("{}/{}.ics", self.calendar_url.trim_end_matches('/'), uid)
+    }
+
+    /// Sends a request for one event resource, with basic auth when a username is set
+    async fn send(&self, method: Method, url: &str, body: Body) -> Result<Response<Body>, FoodError> {
+        let mut request = Request::builder().method(method).uri(url).header(CONTENT_TYPE, ICS_CONTENT_TYPE);
+        if let Some(username) = &self.username {
+            let credentials = format!("{}:{}", username, self.password.as_deref().unwrap_or_default());
+            request = request.header(AUTHORIZATION, format!("Basic {}", STANDARD.encode(credentials)));
+        }
+        let request = request
+            .body(body)
+            .map_err(|e| FoodError::Calendar(format!("Invalid CalDAV URL '{}': {}", url, e)))?;
+
+        https_client()
+            .request(request)
+            .await
+            .map_err(|e| FoodError::Calendar(format!("Could not reach CalDAV server: {}", e)))
+    }
+
+    /// Writes `event` to `url` as a calendar holding just that event, creating or replacing the resource
+    async fn put(&self, url: String, uid: &str, event: &ReminderEvent) -> Result<CalendarEvent, FoodError> {
+        log::debug!("Putting CalDAV event {} to {}", uid, url);
+        let content = ics_calendar(&[(uid.to_string(), event.clone())], event.start.timezone());
+        let response = self.send(Method::PUT, &url, Body::from(content)).await?;
+        if !response.status().is_success() {
+            return Err(FoodError::Calendar(format!(
+                "CalDAV server answered {} for {}",
+                response.status(),
+                url
+            )));
+        }
+        Ok(CalendarEvent { id: url, link: None })
+    }
+
+    async fn delete(&self, url: &str) -> Result<(), FoodError> {
+        log::debug!("Deleting CalDAV event {}", url);
+        let response = self.send(Method::DELETE, url, Body::empty()).await?;
+        let status = response.status();
+        if !status.is_success() && status != StatusCode::NOT_FOUND && status != StatusCode::GONE {
+            return Err(FoodError::Calendar(format!("CalDAV server answered {} for {}", status, url)));
+        }
+        Ok(())
+    }
+}
+
+impl CalendarProvider for CalDavCalendar {
+    fn create_event<'a>(&'a self, uid: &'a str, event: &'a ReminderEvent) -> ProviderFuture<'a, CalendarEvent> {
+        Box::pin(self.put(self.resource_url(uid), uid, event))
+    }
+
+    /// The event id is the resource URL, so the reminder is written back where it was created
+    fn update_event<'a>(&'a self, event_id: &'a str, uid: &'a str, event: &'a ReminderEvent) -> ProviderFuture<'a, CalendarEvent> {
+        Box::pin(self.put(event_id.to_string(), uid, event))
+    }
+
+    fn delete_event<'a>(&'a self, event_id: &'a str) -> ProviderFuture<'a, ()> {
+        Box::pin(self.delete(event_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use chrono_tz::Tz;
+    use hyper::service::{make_service_fn, service_fn};
+    use hyper::Server;
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::sync::{Arc, Mutex};
+
+    /// A request the stand-in server received
+    struct Recorded {
+        method: Method,
+        path: String,
+        authorization: Option<String>,
+        content_type: Option<String>,
+        body: String,
+    }
+
+    /// Starts a CalDAV stand-in on a free local port that records every request and answers
+    /// `status` to it, returning the collection URL
+    fn serve(status: StatusCode, requests: Arc<Mutex<Vec<Recorded>>>) -> String {
+        let make_service = make_service_fn(move |_| {
+            let requests = Arc::clone(&requests);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                    let requests = Arc::clone(&requests);
+                    async move {
+                        let headers = request.headers();
+                        let header = |name| headers.get(name).map(|value| value.to_str().unwrap().to_string());
+                        let (authorization, content_type) = (header(AUTHORIZATION), header(CONTENT_TYPE));
+                        let (method, path) = (request.method().clone(), request.uri().path().to_string());
+                        let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                        requests.lock().unwrap().push(Recorded {
+                            method,
+                            path,
+                            authorization,
+                            content_type,
+                            body: String::from_utf8(body.to_vec()).unwrap(),
+                        });
+                        Ok::<_, Infallible>(Response::builder().status(status).body(Body::empty()).unwrap())
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_service);
+        let url = format!("http://{}/calendars/alice/food/", server.local_addr());
+        tokio::spawn(server);
+        url
+    }
+
+    fn reminder() -> ReminderEvent {
+        let berlin: Tz = "Europe/Berlin".parse().unwrap();
+        let start = berlin.with_ymd_and_hms(2026, 3, 14, 9, 0, 0).unwrap();
+        ReminderEvent {
+            title: "Check your milk before it expires!".to_string(),
+            description: "Your milk is expiring on 2026-03-15.".to_string(),
+            location: "Kitchen/Pantry".to_string(),
+            start,
+            end: start + chrono::Duration::hours(1),
+            alarms: vec![0],
+        }
+    }
+
+    #[tokio::test]
+    async fn puts_the_event_with_basic_auth_then_deletes_it() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let url = serve(StatusCode::CREATED, Arc::clone(&requests));
+        let calendar = CalDavCalendar::new(url.clone(), Some("alice".to_string()), Some("s3cret".to_string()));
+
+        let event = calendar.create_event("food-7@food_agent", &reminder()).await.unwrap();
+        assert_eq!(event.id, format!("{}food-7@food_agent.ics", url));
+        calendar.delete_event(&event.id).await.unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        let (put, delete) = (&requests[0], &requests[1]);
+        let expected_auth = format!("Basic {}", STANDARD.encode("alice:s3cret"));
+
+        assert_eq!(put.method, Method::PUT);
+        assert_eq!(put.path, "/calendars/alice/food/food-7@food_agent.ics");
+        assert_eq!(put.authorization.as_deref(), Some(expected_auth.as_str()));
+        assert_eq!(put.content_type.as_deref(), Some(ICS_CONTENT_TYPE));
+        assert!(put.body.starts_with("BEGIN:VCALENDAR\r\n"));
+        assert!(put.body.contains("\r\nUID:food-7@food_agent\r\n"));
+        assert!(put.body.contains("\r\nDTSTART;TZID=Europe/Berlin:20260314T090000\r\n"));
+        assert!(put.body.contains("\r\nSUMMARY:Check your milk before it expires!\r\n"));
+        assert!(put.body.contains("\r\nBEGIN:VTIMEZONE\r\n"));
+
+        assert_eq!(delete.method, Method::DELETE);
+        assert_eq!(delete.path, put.path);
+        assert_eq!(delete.authorization.as_deref(), Some(expected_auth.as_str()));
+        assert!(delete.body.is_empty());
+    }
+
+    #[tokio::test]
+    async fn sends_no_credentials_without_a_username() {
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let url = serve(StatusCode::NO_CONTENT, Arc::clone(&requests));
+        let event = CalDavCalendar::new(url, None, None).create_event("food-1@food_agent", &reminder()).await.unwrap();
+        assert!(event.link.is_none());
+        assert!(requests.lock().unwrap()[0].authorization.is_none());
+    }
+
+    #[tokio::test]
+    async fn reports_rejected_writes_but_not_missing_deletes() {
+        let url = serve(StatusCode::FORBIDDEN, Arc::new(Mutex::new(Vec::new())));
+        let calendar = CalDavCalendar::new(url, None, None);
+        assert!(matches!(calendar.create_event("food-1@food_agent", &reminder()).await, Err(FoodError::Calendar(_))));
+
+        let url = serve(StatusCode::NOT_FOUND, Arc::new(Mutex::new(Vec::new())));
+        let calendar = CalDavCalendar::new(url.clone(), None, None);
+        calendar.delete_event(&format!("{}food-1@food_agent.ics", url)).await.unwrap();
+    }
+}
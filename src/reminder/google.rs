@@ -0,0 +1,281 @@
+use std::cell::{Cell, RefCell};
+use std::future::Future;
+use chrono::{Duration, NaiveDateTime, Utc};
+use google_calendar3::{api::Event, api::EventDateTime, api::EventReminder, api::EventReminders, CalendarHub, Error as GoogleError};
+use hyper::client::HttpConnector;
+use hyper::header::CONTENT_TYPE;
+use hyper::{Body, Request, StatusCode};
+use hyper_rustls::HttpsConnector;
+use serde_json::Value;
+use crate::error::FoodError;
+use crate::http::https_client;
+use super::{CalendarEvent, CalendarProvider, ProviderFuture, ReminderEvent};
+
+/// Where Google issues new access tokens when the stored token doesn't name one
+const DEFAULT_TOKEN_URI: &str = "https://oauth2.googleapis.com/token";
+
+/// Access tokens this close to their expiry are refreshed before use
+const EXPIRY_MARGIN_SECS: i64 = 60;
+
+/// Pushes reminders to the user's primary Google Calendar with the user's own OAuth token.
+/// An expired access token is refreshed in memory; the caller gets the refreshed token back
+/// from [`CalendarProvider::refreshed_token`] so it can be persisted.
+pub struct GoogleCalendar {
+    /// The token as google-auth stores it: `token`, `refresh_token`, `token_uri`,
+    /// `client_id`, `client_secret` and optionally `expiry`
+    token: RefCell<Value>,
+    refreshed: Cell<bool>,
+}
+
+/// The HTTP status Google answered with, for errors that carry one
+fn error_status(error: &GoogleError) -> Option<StatusCode> {
+    match error {
+        GoogleError::BadRequest(body) => body["error"]["code"]
+            .as_u64()
+            .and_then(|code| u16::try_from(code).ok())
+            .and_then(|code| StatusCode::from_u16(code).ok()),
+        GoogleError::Failure(response) => Some(response.status()),
+        _ => None,
+    }
+}
+
+/// Longest popup reminder Google accepts ahead of an event, four weeks in minutes
+const MAX_REMINDER_MINUTES: u32 = 40_320;
+
+/// The Google Calendar form of a reminder, with a popup for every alarm. Fails when an alarm is
+/// further ahead than Google allows, which only items stored before the limit was lowered can have.
+fn google_event(reminder: &ReminderEvent) -> Result<Event, FoodError> {
+    let time_zone = reminder.start.timezone().name();
+    let overrides = reminder
+        .alarms
+        .iter()
+        .map(|days| {
+            let minutes = days.saturating_mul(24 * 60);
+            if minutes > MAX_REMINDER_MINUTES {
+                return Err(FoodError::Calendar(format!(
+                    "Google Calendar reminders can be at most {} days ahead, not {}",
+                    MAX_REMINDER_MINUTES / (24 * 60),
+                    days
+                )));
+            }
+            Ok(EventReminder {
+                method: Some("popup".to_string()),
+                minutes: Some(minutes as i32),
+            })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Event {
+        summary: Some(reminder.title.clone()),
+        description: Some(reminder.description.clone()),
+        location: Some(reminder.location.clone()),
+        start: Some(EventDateTime {
+            date_time: Some(reminder.start.with_timezone(&Utc)),
+            time_zone: Some(time_zone.to_string()),
+            ..Default::default()
+        }),
+        end: Some(EventDateTime {
+            date_time: Some(reminder.end.with_timezone(&Utc)),
+            time_zone: Some(time_zone.to_string()),
+            ..Default::default()
+        }),
+        reminders: Some(EventReminders {
+            use_default: Some(false),
+            overrides: Some(overrides),
+        }),
+        ..Default::default()
+    })
+}
+
+impl GoogleCalendar {
+    pub fn new(token: Value) -> Self {
+        Self {
+            token: RefCell::new(token),
+            refreshed: Cell::new(false),
+        }
+    }
+
+    fn field(&self, name: &str) -> Option<String> {
+        self.token.borrow().get(name).and_then(Value::as_str).map(str::to_string)
+    }
+
+    /// The stored access token, unless it is missing or about to expire
+    fn valid_access_token(&self) -> Option<String> {
+        let expired = self.field("expiry").is_some_and(|expiry| {
+            // google-auth writes UTC times like "2024-05-01T12:00:00.123456Z"
+            NaiveDateTime::parse_from_str(expiry.trim_end_matches('Z'), "%Y-%m-%dT%H:%M:%S%.f")
+                .map_or(true, |expiry| expiry.and_utc() <= Utc::now() + Duration::seconds(EXPIRY_MARGIN_SECS))
+        });
+        self.field("token").filter(|_| !expired)
+    }
+
+    /// Trades the refresh token for a new access token and records it in the held token
+    async fn refresh(&self) -> Result<String, FoodError> {
+        let missing = |name: &str| {
+            FoodError::Calendar(format!(
+                "The Google token has expired and has no {} to renew it. Please reconnect Google Calendar",
+                name
+            ))
+        };
+        let refresh_token = self.field("refresh_token").ok_or_else(|| missing("refresh_token"))?;
+        let client_id = self.field("client_id").ok_or_else(|| missing("client_id"))?;
+        let client_secret = self.field("client_secret").ok_or_else(|| missing("client_secret"))?;
+        let token_uri = self.field("token_uri").unwrap_or_else(|| DEFAULT_TOKEN_URI.to_string());
+        log::debug!("Refreshing Google access token");
+
+        let form = form_urlencoded::Serializer::new(String::new())
+            .append_pair("grant_type", "refresh_token")
+            .append_pair("refresh_token", &refresh_token)
+            .append_pair("client_id", &client_id)
+            .append_pair("client_secret", &client_secret)
+            .finish();
+        let request = Request::post(&token_uri)
+            .header(CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(form))
+            .map_err(|e| FoodError::Calendar(format!("Invalid Google token_uri '{}': {}", token_uri, e)))?;
+        let response = https_client()
+            .request(request)
+            .await
+            .map_err(|e| FoodError::Calendar(format!("Could not reach Google to refresh the token: {}", e)))?;
+        let status = response.status();
+        let body = hyper::body::to_bytes(response.into_body())
+            .await
+            .map_err(|e| FoodError::Calendar(format!("Could not read Google's token response: {}", e)))?;
+        let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        if !status.is_success() {
+            return Err(FoodError::Calendar(format!(
+                "Google refused to refresh the token ({}). Please reconnect Google Calendar",
+                body["error"].as_str().unwrap_or(status.as_str())
+            )));
+        }
+        let access_token = body["access_token"]
+            .as_str()
+            .ok_or_else(|| FoodError::Calendar("Google's token response has no access_token".to_string()))?
+            .to_string();
+
+        let mut token = self.token.borrow_mut();
+        token["token"] = Value::from(access_token.clone());
+        if let Some(expires_in) = body["expires_in"].as_i64() {
+            let expiry = Utc::now() + Duration::seconds(expires_in);
+            token["expiry"] = Value::from(expiry.format("%Y-%m-%dT%H:%M:%SZ").to_string());
+        }
+        self.refreshed.set(true);
+        Ok(access_token)
+    }
+
+    /// Runs `request` with a valid access token. `action` names what the request does
+    /// for error messages, e.g. "the event".
+    async fn authorized<T, F, Fut>(&self, action: &str, request: F) -> Result<T, FoodError>
+    where
+        F: Fn(CalendarHub<HttpsConnector<HttpConnector>>) -> Fut,
+        Fut: Future<Output = Result<T, GoogleError>>,
+    {
+        let access_token = match self.valid_access_token() {
+            Some(access_token) => access_token,
+            None => self.refresh().await?,
+        };
+
+        // A token without a recorded expiry may have lapsed anyway; renew it once and retry
+        match request(CalendarHub::new(https_client(), access_token)).await {
+            Err(e) if error_status(&e) == Some(StatusCode::UNAUTHORIZED) && !self.refreshed.get() => {
+                let access_token = self.refresh().await?;
+                request(CalendarHub::new(https_client(), access_token)).await
+            }
+            result => result,
+        }
+        .map_err(|e| FoodError::Calendar(format!("Google Calendar rejected {}: {}", action, e)))
+    }
+
+    async fn insert(&self, uid: &str, reminder: &ReminderEvent) -> Result<CalendarEvent, FoodError> {
+        log::debug!("Creating Google Calendar event for {}", uid);
+        let event = google_event(reminder)?;
+        let created = self
+            .authorized("the event", |hub| {
+                let event = event.clone();
+                async move { hub.events().insert(event, "primary").doit().await }
+            })
+            .await?
+            .1;
+        Ok(CalendarEvent {
+            id: created.id.unwrap_or_default(),
+            link: created.html_link,
+        })
+    }
+
+    async fn update(&self, event_id: &str, uid: &str, reminder: &ReminderEvent) -> Result<CalendarEvent, FoodError> {
+        log::debug!("Updating Google Calendar event {} for {}", event_id, uid);
+        let event = google_event(reminder)?;
+        let updated = self
+            .authorized("the event update", |hub| {
+                let event = event.clone();
+                async move { hub.events().update(event, "primary", event_id).doit().await }
+            })
+            .await?
+            .1;
+        Ok(CalendarEvent {
+            id: updated.id.unwrap_or_else(|| event_id.to_string()),
+            link: updated.html_link,
+        })
+    }
+
+    async fn delete(&self, event_id: &str) -> Result<(), FoodError> {
+        log::debug!("Deleting Google Calendar event {}", event_id);
+        self.authorized("the event removal", |hub| async move {
+            match hub.events().delete("primary", event_id).doit().await {
+                // Removed by the user already; nothing left to do
+                Err(e) if matches!(error_status(&e), Some(StatusCode::NOT_FOUND | StatusCode::GONE)) => Ok(()),
+                result => result.map(|_| ()),
+            }
+        })
+        .await
+    }
+}
+
+impl CalendarProvider for GoogleCalendar {
+    fn create_event<'a>(&'a self, uid: &'a str, event: &'a ReminderEvent) -> ProviderFuture<'a, CalendarEvent> {
+        Box::pin(self.insert(uid, event))
+    }
+
+    fn update_event<'a>(&'a self, event_id: &'a str, uid: &'a str, event: &'a ReminderEvent) -> ProviderFuture<'a, CalendarEvent> {
+        Box::pin(self.update(event_id, uid, event))
+    }
+
+    fn delete_event<'a>(&'a self, event_id: &'a str) -> ProviderFuture<'a, ()> {
+        Box::pin(self.delete(event_id))
+    }
+
+    fn refreshed_token(&self) -> Option<Value> {
+        self.refreshed.get().then(|| self.token.borrow().clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+    use chrono_tz::Tz;
+    use crate::reminder::MAX_LEAD_DAYS;
+
+    fn reminder(alarms: Vec<u32>) -> ReminderEvent {
+        let start = Tz::UTC.with_ymd_and_hms(2026, 3, 14, 9, 0, 0).unwrap();
+        ReminderEvent {
+            title: "Check your milk before it expires!".to_string(),
+            description: String::new(),
+            location: String::new(),
+            start,
+            end: start + Duration::hours(1),
+            alarms,
+        }
+    }
+
+    #[test]
+    fn alarms_become_popups_in_minutes() {
+        let event = google_event(&reminder(vec![MAX_LEAD_DAYS, 2, 0])).unwrap();
+        let minutes: Vec<Option<i32>> = event.reminders.unwrap().overrides.unwrap().iter().map(|r| r.minutes).collect();
+        assert_eq!(minutes, vec![Some(40_320), Some(2880), Some(0)]);
+    }
+
+    #[test]
+    fn rejects_alarms_further_ahead_than_google_allows() {
+        assert!(matches!(google_event(&reminder(vec![MAX_LEAD_DAYS + 1, 0])), Err(FoodError::Calendar(_))));
+    }
+}
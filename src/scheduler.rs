@@ -0,0 +1,363 @@
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::Serialize;
+use std::sync::mpsc::{Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::time::Duration as Interval;
+use crate::error::FoodError;
+use crate::handlers::CommandHandler;
+use crate::notify::{DueItem, ExpiryNotice, NotificationChannel, MAX_DIGEST_DAYS};
+use crate::reminder::MAX_LEAD_DAYS;
+use crate::runtime;
+use crate::storage::{lock_repository, Repository, SentNotification};
+
+/// How often the scheduler looks for newly due items unless told otherwise
+pub const DEFAULT_SCAN_INTERVAL: Interval = Interval::from_secs(60 * 60);
+
+/// A notice a channel delivered
+#[derive(Debug, Serialize)]
+pub struct Delivery {
+    pub channel: String,
+    pub notice: ExpiryNotice,
+}
+
+/// A notice a channel could not deliver; it is tried again on the next scan
+#[derive(Debug, Serialize)]
+pub struct FailedDelivery {
+    pub channel: String,
+    pub user_id: i32,
+    pub error: String,
+}
+
+/// What one scan sent and what failed
+#[derive(Debug, Default, Serialize)]
+pub struct ScanReport {
+    pub sent: Vec<Delivery>,
+    pub failed: Vec<FailedDelivery>,
+}
+
+/// Scans every user's food for items entering their expiry window and sends them through
+/// its channels. What was sent is recorded in the database, so a restarted scheduler picks
+/// up where it left off instead of sending everything again.
+pub struct ExpiryScheduler {
+    channels: Vec<Box<dyn NotificationChannel>>,
+}
+
+/// What keeps `due` from being sent to the user through `channel` again
+fn sent_record(user_id: i32, today: NaiveDate, due: &DueItem, channel: &str) -> SentNotification {
+    SentNotification {
+        user_id,
+        food_id: due.item.id,
+        expiry_date: today + Duration::days(due.days_left),
+        stage: due.stage,
+        channel: channel.to_string(),
+    }
+}
+
+/// A notice a channel still owes a user, with the records to keep once it is delivered
+struct PendingNotice<'a> {
+    channel: &'a dyn NotificationChannel,
+    notice: ExpiryNotice,
+    records: Vec<SentNotification>,
+}
+
+impl ExpiryScheduler {
+    pub fn new(channels: Vec<Box<dyn NotificationChannel>>) -> Self {
+        Self { channels }
+    }
+
+    /// One pass over every user's active food. An item is due once the reminder time of one of
+    /// its stages has passed in the user's time zone; only the latest stage reached is sent, so
+    /// a scheduler that was down for days sends one notice per item rather than several.
+    /// The storage lock is released while channels deliver, so a callback may use the handler.
+    pub async fn scan(
+        &self,
+        handler: &CommandHandler,
+        storage: &Mutex<Box<dyn Repository>>,
+        now: DateTime<Utc>,
+    ) -> Result<ScanReport, FoodError> {
+        let pending = self.pending(handler, lock_repository(storage).as_ref(), now)?;
+        let mut report = ScanReport::default();
+        for PendingNotice { channel, notice, records } in pending {
+            match channel.send(&notice).await {
+                Ok(()) => {
+                    let mut storage = lock_repository(storage);
+                    for record in &records {
+                        storage.record_notification(record, now)?;
+                    }
+                    log::debug!("Sent {} due items to user {} via {}", notice.items.len(), notice.user_id, channel.name());
+                    report.sent.push(Delivery { channel: channel.name().to_string(), notice });
+                }
+                Err(e) => {
+                    log::warn!("Failed to notify user {} via {}: {}", notice.user_id, channel.name(), e);
+                    report.failed.push(FailedDelivery {
+                        channel: channel.name().to_string(),
+                        user_id: notice.user_id,
+                        error: e.to_string(),
+                    });
+                }
+            }
+        }
+        Ok(report)
+    }
+
+    /// The notice each channel still owes each user, unless the channel holds it back for now
+    fn pending(&self, handler: &CommandHandler, storage: &dyn Repository, now: DateTime<Utc>) -> Result<Vec<PendingNotice<'_>>, FoodError> {
+        // No stage is further out than MAX_LEAD_DAYS; a weekly digest also lists what comes due
+        // before the next one, and a day more covers zones ahead of UTC
+        let horizon = now.date_naive() + Duration::days(i64::from(MAX_LEAD_DAYS) + MAX_DIGEST_DAYS + 1);
+        let mut pending = Vec::new();
+        for user_id in storage.users_with_food_expiring(horizon)? {
+            let preferences = handler.handle_reminder_preferences(storage, user_id)?;
+            let today = now.with_timezone(&preferences.timezone).date_naive();
+            let due: Vec<DueItem> = storage
+                .search_by_expiry(user_id, None, Some(horizon))?
+                .iter()
+                .filter_map(|stored| DueItem::reached(stored, &preferences, today, now))
+                .collect();
+
+            for channel in &self.channels {
+                let mut items = Vec::new();
+                if !channel.sends_digests() {
+                    for due in &due {
+                        if !storage.notification_sent(&sent_record(user_id, today, due, channel.name()))? {
+                            items.push(due.clone());
+                        }
+                    }
+                    if items.is_empty() {
+                        continue;
+                    }
+                }
+                let mut notice = ExpiryNotice { user_id, date: today, items, recipient: None };
+                if channel.prepare(storage, &mut notice, &preferences, now)? {
+                    let records = notice
+                        .items
+                        .iter()
+                        .map(|due| sent_record(user_id, today, due, channel.name()))
+                        .collect();
+                    pending.push(PendingNotice { channel: channel.as_ref(), notice, records });
+                }
+            }
+        }
+        Ok(pending)
+    }
+
+    /// Scans every `interval` until `stop` receives or its sender is dropped. The first scan
+    /// runs at once, which catches up on anything that came due while nothing was running.
+    pub fn run(
+        &self,
+        handler: &CommandHandler,
+        storage: &Mutex<Box<dyn Repository>>,
+        interval: Interval,
+        stop: &Receiver<()>,
+    ) {
+        log::debug!("Expiry scheduler started, scanning every {}s", interval.as_secs_f64());
+        loop {
+            match runtime().block_on(self.scan(handler, storage, Utc::now())) {
+                Ok(report) => log::debug!(
+                    "Expiry scan sent {} notices, {} failed",
+                    report.sent.len(),
+                    report.failed.len()
+                ),
+                Err(e) => log::error!("Expiry scan failed: {}", e),
+            }
+            if !matches!(stop.recv_timeout(interval), Err(RecvTimeoutError::Timeout)) {
+                log::debug!("Expiry scheduler stopped");
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{FoodStock, FoodType, StorageType, Unit};
+    use crate::notify::NotifyFuture;
+    use crate::reminder::ReminderPreferences;
+    use crate::storage::{FoodRepository, InMemoryFoodDb};
+    use chrono::TimeZone;
+    use chrono_tz::Tz;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    const ALICE: i32 = 1;
+    const BOB: i32 = 2;
+
+    /// Keeps what it is sent, or fails while `failing` is set
+    struct RecordingChannel {
+        name: &'static str,
+        failing: Arc<AtomicBool>,
+        sent: Delivered,
+    }
+
+    impl NotificationChannel for RecordingChannel {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn send<'a>(&'a self, notice: &'a ExpiryNotice) -> NotifyFuture<'a> {
+            Box::pin(async move {
+                if self.failing.load(Ordering::SeqCst) {
+                    return Err(FoodError::Notification("channel down".to_string()));
+                }
+                self.sent.lock().unwrap().push(notice.clone());
+                Ok(())
+            })
+        }
+    }
+
+    type Delivered = Arc<Mutex<Vec<ExpiryNotice>>>;
+
+    /// A channel plus the handles to break it and to read what it delivered
+    fn channel(name: &'static str) -> (Box<dyn NotificationChannel>, Arc<AtomicBool>, Delivered) {
+        let failing = Arc::new(AtomicBool::new(false));
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let channel = RecordingChannel { name, failing: Arc::clone(&failing), sent: Arc::clone(&sent) };
+        (Box::new(channel), failing, sent)
+    }
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 3, day).unwrap()
+    }
+
+    fn at(day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2026, 3, day, hour, minute, 0).unwrap()
+    }
+
+    /// A storage holding milk for `user_id` that expires on March 10th, reminded 3 days and 1 day before
+    fn storage_with_milk(user_id: i32) -> Mutex<Box<dyn Repository>> {
+        let mut db = InMemoryFoodDb::new();
+        let mut milk = FoodStock::new(
+            "milk".to_string(),
+            date(1),
+            FoodType::Dairy,
+            FoodType::Dairy.major_nutrient(),
+            StorageType::Cold,
+            date(10),
+            Unit::Litres(1.0),
+        );
+        milk.reminder_days = vec![3, 1];
+        db.add_food(user_id, milk).unwrap();
+        Mutex::new(Box::new(db))
+    }
+
+    fn scan(scheduler: &ExpiryScheduler, storage: &Mutex<Box<dyn Repository>>, now: DateTime<Utc>) -> ScanReport {
+        runtime().block_on(scheduler.scan(&CommandHandler::new(), storage, now)).unwrap()
+    }
+
+    fn stages(notices: &[ExpiryNotice]) -> Vec<(i32, u32)> {
+        notices
+            .iter()
+            .flat_map(|notice| notice.items.iter().map(move |due| (notice.user_id, due.stage)))
+            .collect()
+    }
+
+    #[test]
+    fn nothing_is_sent_before_the_first_stage() {
+        let (log, _, sent) = channel("log");
+        let scheduler = ExpiryScheduler::new(vec![log]);
+        let storage = storage_with_milk(ALICE);
+
+        let report = scan(&scheduler, &storage, at(7, 8, 59));
+        assert!(report.sent.is_empty() && report.failed.is_empty());
+        assert!(sent.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn each_stage_is_sent_once() {
+        let (log, _, sent) = channel("log");
+        let scheduler = ExpiryScheduler::new(vec![log]);
+        let storage = storage_with_milk(ALICE);
+
+        assert_eq!(scan(&scheduler, &storage, at(7, 9, 0)).sent.len(), 1);
+        assert!(scan(&scheduler, &storage, at(7, 9, 0)).sent.is_empty());
+        assert!(scan(&scheduler, &storage, at(8, 12, 0)).sent.is_empty());
+        assert_eq!(scan(&scheduler, &storage, at(9, 9, 30)).sent.len(), 1);
+        assert!(scan(&scheduler, &storage, at(9, 10, 0)).sent.is_empty());
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(stages(&sent), vec![(ALICE, 3), (ALICE, 1)]);
+        assert_eq!(sent[0].date, date(7));
+        assert_eq!(sent[0].items[0].days_left, 3);
+        assert_eq!(sent[1].items[0].days_left, 1);
+    }
+
+    #[test]
+    fn a_late_scan_sends_only_the_latest_stage_reached() {
+        let (log, _, sent) = channel("log");
+        let scheduler = ExpiryScheduler::new(vec![log]);
+        let storage = storage_with_milk(ALICE);
+
+        scan(&scheduler, &storage, at(11, 9, 0));
+        assert!(scan(&scheduler, &storage, at(12, 9, 0)).sent.is_empty());
+
+        let sent = sent.lock().unwrap();
+        assert_eq!(stages(&sent), vec![(ALICE, 1)]);
+        assert_eq!(sent[0].items[0].days_left, -1);
+    }
+
+    #[test]
+    fn each_user_hears_only_about_their_own_food() {
+        let (log, _, sent) = channel("log");
+        let scheduler = ExpiryScheduler::new(vec![log]);
+        let storage = storage_with_milk(ALICE);
+        {
+            let mut db = lock_repository(&storage);
+            let cheese = FoodStock::new(
+                "cheese".to_string(),
+                date(1),
+                FoodType::Dairy,
+                FoodType::Dairy.major_nutrient(),
+                StorageType::Cold,
+                date(20),
+                Unit::Grams(200.0),
+            );
+            db.add_food(BOB, cheese).unwrap();
+        }
+
+        scan(&scheduler, &storage, at(7, 9, 0));
+        let sent = sent.lock().unwrap();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].user_id, ALICE);
+        assert_eq!(sent[0].items[0].item.name, "milk");
+    }
+
+    #[test]
+    fn failed_deliveries_are_retried_on_that_channel_only() {
+        let (steady, _, steady_sent) = channel("steady");
+        let (flaky, flaky_failing, flaky_sent) = channel("flaky");
+        flaky_failing.store(true, Ordering::SeqCst);
+        let scheduler = ExpiryScheduler::new(vec![steady, flaky]);
+        let storage = storage_with_milk(ALICE);
+
+        let report = scan(&scheduler, &storage, at(7, 9, 0));
+        assert_eq!(report.sent.len(), 1);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].channel, "flaky");
+        assert_eq!(report.failed[0].user_id, ALICE);
+
+        flaky_failing.store(false, Ordering::SeqCst);
+        let report = scan(&scheduler, &storage, at(7, 10, 0));
+        assert_eq!(report.sent.len(), 1);
+        assert_eq!(report.sent[0].channel, "flaky");
+        assert!(report.failed.is_empty());
+
+        assert_eq!(stages(&steady_sent.lock().unwrap()), vec![(ALICE, 3)]);
+        assert_eq!(stages(&flaky_sent.lock().unwrap()), vec![(ALICE, 3)]);
+    }
+
+    #[test]
+    fn stages_come_due_in_the_users_time_zone() {
+        let (log, _, sent) = channel("log");
+        let scheduler = ExpiryScheduler::new(vec![log]);
+        let storage = storage_with_milk(ALICE);
+        lock_repository(&storage)
+            .set_reminder_preferences(ALICE, &ReminderPreferences::with_timezone(Tz::Asia__Tokyo))
+            .unwrap();
+
+        // 9:00 in Tokyo on March 7th is midnight UTC
+        assert!(scan(&scheduler, &storage, at(6, 23, 59)).sent.is_empty());
+        assert_eq!(scan(&scheduler, &storage, at(7, 0, 0)).sent.len(), 1);
+        assert_eq!(sent.lock().unwrap()[0].date, date(7));
+    }
+}
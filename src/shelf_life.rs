@@ -0,0 +1,266 @@
+use std::fs;
+use std::path::Path;
+use serde::Deserialize;
+use crate::models::{FoodType, StorageType};
+
+/// Shelf life used when not even the built-in rules match an item
+pub const DEFAULT_SHELF_LIFE_DAYS: i64 = 2;
+
+/// How many days a food keeps once stocked, for the items the rule matches.
+/// A `None` key matches anything.
+#[derive(Debug, Clone)]
+pub struct ShelfLifeRule {
+    pub food_type: Option<FoodType>,
+    pub storage_type: Option<StorageType>,
+    /// Case-insensitive fragment of the item name, e.g. "banana" or "ground beef"
+    pub name_pattern: Option<String>,
+    pub days: i64,
+}
+
+impl ShelfLifeRule {
+    fn matches(&self, name: &str, food_type: &FoodType, storage_type: &StorageType) -> bool {
+        self.food_type.as_ref().is_none_or(|t| t == food_type)
+            && self.storage_type.as_ref().is_none_or(|s| s == storage_type)
+            && self.name_pattern.as_ref().is_none_or(|p| name.to_lowercase().contains(p))
+    }
+
+    /// Higher is more specific; a name pattern beats a food type, which beats a storage type
+    fn specificity(&self) -> u8 {
+        let mut score = 0;
+        if self.name_pattern.is_some() {
+            score += 4;
+        }
+        if self.food_type.is_some() {
+            score += 2;
+        }
+        if self.storage_type.is_some() {
+            score += 1;
+        }
+        score
+    }
+}
+
+/// A rule as written in a rules file or the `shelf_life_rules` table, using the same
+/// spellings the chat flow accepts ("meat", "breakfast-cereal", "cold", "frozen")
+#[derive(Debug, Clone, Deserialize)]
+pub struct RawShelfLifeRule {
+    pub food_type: Option<String>,
+    pub storage_type: Option<String>,
+    pub name_pattern: Option<String>,
+    pub days: i64,
+}
+
+impl TryFrom<RawShelfLifeRule> for ShelfLifeRule {
+    type Error = String;
+
+    fn try_from(raw: RawShelfLifeRule) -> Result<Self, Self::Error> {
+        if raw.days < 0 {
+            return Err(format!("Shelf life cannot be negative ({} days)", raw.days));
+        }
+        Ok(Self {
+            food_type: raw.food_type.map(|t| t.trim().to_lowercase().parse()).transpose()?,
+            storage_type: raw.storage_type.map(|s| s.trim().to_lowercase().parse()).transpose()?,
+            name_pattern: raw
+                .name_pattern
+                .map(|p| p.trim().to_lowercase())
+                .filter(|p| !p.is_empty()),
+            days: raw.days,
+        })
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct RulesFile {
+    #[serde(default)]
+    rules: Vec<RawShelfLifeRule>,
+}
+
+/// Shelf-life lookup shared by every expiry estimate.
+///
+/// Custom rules are consulted first; the built-in table only answers for items no custom rule matches.
+/// Within each set the most specific matching rule wins, and earlier rules win ties.
+#[derive(Debug, Clone)]
+pub struct ShelfLifeRules {
+    overrides: Vec<ShelfLifeRule>,
+    defaults: Vec<ShelfLifeRule>,
+}
+
+impl ShelfLifeRules {
+    /// The shelf lives the agent ships with
+    pub fn builtin() -> Self {
+        use FoodType::*;
+        use StorageType::*;
+
+        let rule = |food_type: Option<FoodType>, storage_type: Option<StorageType>, days| ShelfLifeRule {
+            food_type,
+            storage_type,
+            name_pattern: None,
+            days,
+        };
+
+        Self {
+            overrides: Vec::new(),
+            defaults: vec![
+                rule(Some(Meat), Some(Cold), 7),
+                rule(Some(Meat), Some(RoomTemperature), 2),
+                rule(Some(Vegetable), Some(Cold), 5),
+                rule(Some(Vegetable), Some(RoomTemperature), 2),
+                rule(Some(Grains), None, 90),
+                rule(Some(Dairy), Some(Cold), 10),
+                rule(Some(Dairy), Some(RoomTemperature), 1),
+                rule(Some(Non_dairy), Some(Cold), 10),
+                rule(Some(Non_dairy), Some(RoomTemperature), 2),
+                rule(Some(Fruit), Some(Cold), 7),
+                rule(Some(Fruit), Some(RoomTemperature), 3),
+                rule(Some(Meat), Some(Frozen), 120),
+                rule(Some(Vegetable), Some(Frozen), 240),
+                rule(Some(Fruit), Some(Frozen), 240),
+                rule(Some(Dairy), Some(Frozen), 90),
+                rule(Some(Meat), Some(Pantry), 2),
+                rule(Some(Dairy), Some(Pantry), 1),
+                rule(Some(Vegetable), Some(Pantry), 14),
+                rule(Some(Fruit), Some(Pantry), 3),
+                rule(Some(Breakfast_cereal), Some(Pantry), 180),
+                rule(Some(Edible_oils), Some(Pantry), 365),
+                rule(None, Some(Cold), 5),
+                rule(None, Some(RoomTemperature), 2),
+                rule(None, Some(Frozen), 90),
+                rule(None, Some(Pantry), 90),
+            ],
+        }
+    }
+
+    /// Built-in rules with `rules` layered on top
+    pub fn with_overrides(rules: Vec<RawShelfLifeRule>) -> Result<Self, String> {
+        let overrides = rules
+            .into_iter()
+            .map(ShelfLifeRule::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(Self { overrides, ..Self::builtin() })
+    }
+
+    /// Loads custom rules from a `.toml` or `.json` file containing a `rules` list
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self, String> {
+        let path = path.as_ref();
+        let contents = fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read shelf-life rules from {}: {}", path.display(), e))?;
+
+        let file: RulesFile = match path.extension().and_then(|e| e.to_str()) {
+            Some("toml") => toml::from_str(&contents).map_err(|e| e.to_string())?,
+            Some("json") => serde_json::from_str(&contents).map_err(|e| e.to_string())?,
+            _ => return Err(format!("Unsupported shelf-life rules file: {}. Use .toml or .json", path.display())),
+        };
+
+        log::debug!("Loaded {} shelf-life rules from {}", file.rules.len(), path.display());
+        Self::with_overrides(file.rules)
+    }
+
+    /// Number of days the named item keeps under the given conditions
+    pub fn days_for(&self, name: &str, food_type: &FoodType, storage_type: &StorageType) -> i64 {
+        Self::best_match(&self.overrides, name, food_type, storage_type)
+            .or_else(|| Self::best_match(&self.defaults, name, food_type, storage_type))
+            .map_or(DEFAULT_SHELF_LIFE_DAYS, |rule| rule.days)
+    }
+
+    fn best_match<'a>(
+        rules: &'a [ShelfLifeRule],
+        name: &str,
+        food_type: &FoodType,
+        storage_type: &StorageType,
+    ) -> Option<&'a ShelfLifeRule> {
+        rules
+            .iter()
+            .filter(|rule| rule.matches(name, food_type, storage_type))
+            .fold(None, |best: Option<&ShelfLifeRule>, rule| match best {
+                Some(current) if current.specificity() >= rule.specificity() => Some(current),
+                _ => Some(rule),
+            })
+    }
+}
+
+impl Default for ShelfLifeRules {
+    fn default() -> Self {
+        Self::builtin()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use FoodType::*;
+    use StorageType::*;
+
+    fn raw(food_type: Option<&str>, storage_type: Option<&str>, name_pattern: Option<&str>, days: i64) -> RawShelfLifeRule {
+        RawShelfLifeRule {
+            food_type: food_type.map(str::to_string),
+            storage_type: storage_type.map(str::to_string),
+            name_pattern: name_pattern.map(str::to_string),
+            days,
+        }
+    }
+
+    #[test]
+    fn builtin_rules_match_type_and_storage() {
+        let rules = ShelfLifeRules::builtin();
+        assert_eq!(rules.days_for("chicken", &Meat, &Cold), 7);
+        assert_eq!(rules.days_for("chicken", &Meat, &Frozen), 120);
+        assert_eq!(rules.days_for("rice", &Grains, &Cold), 90);
+        assert_eq!(rules.days_for("juice", &Beverage, &Cold), 5);
+        assert_eq!(rules.days_for("juice", &Beverage, &RoomTemperature), 2);
+    }
+
+    #[test]
+    fn custom_rules_win_over_builtin_ones() {
+        let rules = ShelfLifeRules::with_overrides(vec![
+            raw(Some("dairy"), Some("cold"), None, 14),
+            raw(None, None, Some("Banana"), 4),
+        ])
+        .unwrap();
+        assert_eq!(rules.days_for("milk", &Dairy, &Cold), 14);
+        assert_eq!(rules.days_for("milk", &Dairy, &Frozen), 90);
+        assert_eq!(rules.days_for("Ripe bananas", &Fruit, &Cold), 4);
+    }
+
+    #[test]
+    fn most_specific_rule_wins_and_earlier_rules_break_ties() {
+        let rules = ShelfLifeRules::with_overrides(vec![
+            raw(None, Some("cold"), None, 3),
+            raw(Some("meat"), None, None, 5),
+            raw(Some("meat"), None, Some("ground beef"), 2),
+            raw(Some("meat"), None, None, 9),
+        ])
+        .unwrap();
+        assert_eq!(rules.days_for("ground beef", &Meat, &Cold), 2);
+        assert_eq!(rules.days_for("steak", &Meat, &Cold), 5);
+        assert_eq!(rules.days_for("lettuce", &Vegetable, &Cold), 3);
+    }
+
+    #[test]
+    fn rejects_invalid_rules() {
+        assert!(ShelfLifeRules::with_overrides(vec![raw(Some("meat"), None, None, -1)]).is_err());
+        assert!(ShelfLifeRules::with_overrides(vec![raw(Some("stew"), None, None, 3)]).is_err());
+        assert!(ShelfLifeRules::with_overrides(vec![raw(None, Some("attic"), None, 3)]).is_err());
+    }
+
+    #[test]
+    fn loads_rules_from_toml_and_json_files() {
+        let dir = std::env::temp_dir().join(format!("shelf_life_rules_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let toml_path = dir.join("rules.toml");
+        fs::write(&toml_path, "[[rules]]\nname_pattern = \"yoghurt\"\ndays = 21\n").unwrap();
+        let json_path = dir.join("rules.json");
+        fs::write(&json_path, r#"{"rules": [{"food_type": "fruit", "storage_type": "cold", "days": 12}]}"#).unwrap();
+        let text_path = dir.join("rules.txt");
+        fs::write(&text_path, "").unwrap();
+
+        let from_toml = ShelfLifeRules::from_file(&toml_path).unwrap();
+        let from_json = ShelfLifeRules::from_file(&json_path).unwrap();
+        let unsupported = ShelfLifeRules::from_file(&text_path);
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(from_toml.days_for("Greek yoghurt", &Dairy, &Cold), 21);
+        assert_eq!(from_json.days_for("apples", &Fruit, &Cold), 12);
+        assert!(unsupported.is_err());
+        assert!(ShelfLifeRules::from_file(dir.join("missing.toml")).is_err());
+    }
+}
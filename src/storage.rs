@@ -1,32 +1,304 @@
-use std::collections::HashMap;
-use chrono::{NaiveDate, Local, Duration};
-use crate::models::{FoodStock, FoodType, StorageType, Unit};
-
-use dotenvy::dotenv;
-use mysql::*;
-use mysql::prelude::*;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Local, Duration, Utc};
+use crate::error::FoodError;
+use crate::models::{FoodStock, FoodUpdate, Unit};
+use crate::notify::{DigestFrequency, EmailDigest};
+use crate::reminder::ReminderPreferences;
+use crate::shelf_life::RawShelfLifeRule;
+use serde::Serialize;
 use std::env;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration as Timeout;
+
+mod memory_db;
+mod migrations;
+mod mysql_db;
+mod sqlite_db;
+
+pub use memory_db::InMemoryFoodDb;
+pub use mysql_db::FoodDb;
+pub use sqlite_db::SqliteFoodDb;
+
+/// Owner assigned to rows that existed before `food_stock` had a `user_id` column
+pub const LEGACY_OWNER_ID: i32 = 0;
+
+/// MySQL database used when neither the config nor the URL names one
+pub const DEFAULT_DATABASE: &str = "food_registry";
+
+pub const DEFAULT_POOL_SIZE: usize = 10;
 
+/// Where the food database lives and how to connect to it
+#[derive(Debug, Clone)]
 pub struct DbConfig {
-    url: String,
-    database: String,
+    /// `None` until a URL is configured; opening the repository fails without one
+    url: Option<String>,
+    database: Option<String>,
+    pool_size: usize,
+    connect_timeout: Option<Timeout>,
+    query_timeout: Option<Timeout>,
+    legacy_owner: i32,
+    auto_migrate: bool,
 }
 
 impl DbConfig {
-    pub fn new(url: &str, database: &str) -> Self {
+    pub fn new(url: &str) -> Self {
         Self {
-            url: url.to_string(),
-            database: database.to_string()
+            url: Some(url.to_string()),
+            database: None,
+            pool_size: DEFAULT_POOL_SIZE,
+            connect_timeout: None,
+            query_timeout: None,
+            legacy_owner: LEGACY_OWNER_ID,
+            auto_migrate: true,
+        }
+    }
+
+    /// Reads the config from the environment:
+    /// `DATABASE_URL`, `DATABASE_NAME`, `DATABASE_POOL_SIZE`,
+    /// `DATABASE_CONNECT_TIMEOUT` and `DATABASE_QUERY_TIMEOUT` (seconds), `LEGACY_FOOD_OWNER_ID`
+    /// and `AUTO_MIGRATE` (`false` or `0` to only migrate on demand).
+    /// Without `DATABASE_URL` the config has no URL until [`DbConfig::with_url`] gives one.
+    pub fn from_env() -> Result<Self, FoodError> {
+        let mut config = Self::new("");
+        config.url = env::var("DATABASE_URL").ok().filter(|url| !url.trim().is_empty());
+
+        if let Ok(database) = env::var("DATABASE_NAME") {
+            config = config.with_database(&database);
+        }
+        if let Some(pool_size) = env_number::<usize>("DATABASE_POOL_SIZE")? {
+            config = config.with_pool_size(pool_size);
+        }
+        if let Some(seconds) = env_number::<f64>("DATABASE_CONNECT_TIMEOUT")? {
+            config = config.with_connect_timeout(timeout_from_secs(seconds)?);
         }
+        if let Some(seconds) = env_number::<f64>("DATABASE_QUERY_TIMEOUT")? {
+            config = config.with_query_timeout(timeout_from_secs(seconds)?);
+        }
+        if let Some(user_id) = env_number::<i32>("LEGACY_FOOD_OWNER_ID")? {
+            config = config.with_legacy_owner(user_id);
+        }
+        if let Ok(value) = env::var("AUTO_MIGRATE") {
+            config = config.with_auto_migrate(value != "false" && value != "0");
+        }
+        Ok(config)
+    }
+
+    pub fn with_url(mut self, url: &str) -> Self {
+        self.url = Some(url.to_string());
+        self
+    }
+
+    /// The configured database URL; there is deliberately no default, so a missing
+    /// `DATABASE_URL` cannot silently put the inventory in a fresh local file
+    pub fn url(&self) -> Result<&str, FoodError> {
+        self.url.as_deref().ok_or_else(|| {
+            FoodError::Validation(
+                "No database configured. Set DATABASE_URL (or pass database_url) to mysql://..., \
+                 sqlite://<path> or memory://"
+                    .to_string(),
+            )
+        })
+    }
+
+    /// Sets the MySQL database name, overriding any given in the URL
+    pub fn with_database(mut self, database: &str) -> Self {
+        self.database = Some(database.to_string());
+        self
+    }
+
+    /// Sets the most connections the MySQL pool opens at once
+    pub fn with_pool_size(mut self, pool_size: usize) -> Self {
+        self.pool_size = pool_size;
+        self
+    }
+
+    pub fn with_connect_timeout(mut self, timeout: Timeout) -> Self {
+        self.connect_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets how long a single query may take; SQLite waits this long for a locked database
+    pub fn with_query_timeout(mut self, timeout: Timeout) -> Self {
+        self.query_timeout = Some(timeout);
+        self
+    }
+
+    /// Sets the user that pre-existing, unowned food rows are migrated to
+    pub fn with_legacy_owner(mut self, user_id: i32) -> Self {
+        self.legacy_owner = user_id;
+        self
+    }
+
+    /// With auto-migrate off, the schema is left as is until [`FoodRepository::migrate`] is called
+    pub fn with_auto_migrate(mut self, auto_migrate: bool) -> Self {
+        self.auto_migrate = auto_migrate;
+        self
     }
 }
 
-pub struct FoodDb {
-    pool: Pool,
+fn env_number<T: std::str::FromStr>(name: &str) -> Result<Option<T>, FoodError> {
+    match env::var(name) {
+        Ok(value) => value
+            .trim()
+            .parse()
+            .map(Some)
+            .map_err(|_| FoodError::Validation(format!("{} must be a number, got '{}'", name, value))),
+        Err(_) => Ok(None),
+    }
 }
 
-#[derive(Debug)]
+/// Converts a timeout given in seconds, as Python and the environment pass it
+pub fn timeout_from_secs(seconds: f64) -> Result<Timeout, FoodError> {
+    Timeout::try_from_secs_f64(seconds)
+        .map_err(|_| FoodError::Validation(format!("Timeout must be a positive number of seconds, got {}", seconds)))
+}
+
+/// Status of a food item that is still in the pantry
+pub const STATUS_ACTIVE: &str = "active";
+/// Status of a food item whose quantity has been fully consumed
+pub const STATUS_USED_UP: &str = "used_up";
+
+/// Columns `advanced_search` may match a keyword against
+pub const SEARCHABLE_FIELDS: [&str; 4] = ["name", "food_type", "nutrient", "storage_type"];
+
+/// Where food items are kept. Every operation is scoped to the owning user.
+///
+/// Enum columns (food type, nutrient, storage type) hold the `Debug` names and quantities
+/// are kept in their base unit, whichever backend stores them.
+pub trait FoodRepository: Send {
+    /// Brings the schema up to date and returns the names of the migrations applied
+    fn migrate(&mut self) -> Result<Vec<String>, FoodError> {
+        Ok(Vec::new())
+    }
+
+    /// Stores a new item for the user and returns its id
+    fn add_food(&mut self, user_id: i32, food: FoodStock) -> Result<i32, FoodError>;
+
+    /// Stores several new items for the user in one transaction, so either all of them
+    /// are stored or none are. Returns their ids in the order given.
+    fn add_foods(&mut self, user_id: i32, foods: Vec<FoodStock>) -> Result<Vec<i32>, FoodError>;
+
+    /// Fetches one of the user's active food items by id
+    fn get_food(&self, user_id: i32, id: i32) -> Result<Option<StoredFood>, FoodError>;
+
+    /// Lists all of the user's active food items
+    fn get_all_food(&self, user_id: i32) -> Result<Vec<StoredFood>, FoodError>;
+
+    /// Lists the user's active items whose `field` (one of [`SEARCHABLE_FIELDS`]) contains `keyword`
+    fn advanced_search(&self, user_id: i32, keyword: &str, field: &str) -> Result<Vec<StoredFood>, FoodError>;
+
+    /// Lists the user's active food whose expiry date falls within the inclusive
+    /// `from`..=`to` range, soonest expiry first. A `None` bound leaves that side open.
+    fn search_by_expiry(
+        &self,
+        user_id: i32,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<StoredFood>, FoodError>;
+
+    /// Applies `update` to the user's active food item with the given id.
+    /// Returns `false` when the user owns no active item with that id.
+    fn update_food(&mut self, user_id: i32, id: i32, update: &FoodUpdate) -> Result<bool, FoodError>;
+
+    /// Removes the user's active food item with the given id. Used-up items stay, so their
+    /// consumption ledger is kept. Returns `false` when the user owns no active item with that id.
+    fn delete_food(&mut self, user_id: i32, id: i32) -> Result<bool, FoodError>;
+
+    /// Lowers the quantity of the user's active food item by `amount` and records the use.
+    /// An item that reaches zero is marked used up rather than deleted.
+    fn consume(&mut self, user_id: i32, id: i32, amount: &Unit) -> Result<ConsumeOutcome, FoodError>;
+
+    /// The calendar event holding the reminder for the user's food item, whether or not
+    /// the item is still active
+    fn calendar_event(&self, user_id: i32, id: i32) -> Result<Option<LinkedEvent>, FoodError>;
+
+    /// Records the calendar event holding the reminder for the user's food item, or forgets it with `None`.
+    /// Returns `false` when the user owns no item with that id.
+    fn set_calendar_event(&mut self, user_id: i32, id: i32, event: Option<&LinkedEvent>) -> Result<bool, FoodError>;
+
+    /// The digest event for the user's food expiring on `date`, if one was pushed
+    fn digest_event(&self, user_id: i32, date: NaiveDate) -> Result<Option<LinkedEvent>, FoodError>;
+
+    /// Records the digest event for the user's food expiring on `date`, or forgets it with `None`
+    fn set_digest_event(&mut self, user_id: i32, date: NaiveDate, event: Option<&LinkedEvent>) -> Result<(), FoodError>;
+
+    /// Reads the custom shelf-life rules in the order they were added
+    fn load_shelf_life_rules(&self) -> Result<Vec<RawShelfLifeRule>, FoodError> {
+        Ok(Vec::new())
+    }
+}
+
+/// What the expiry scanner needs to know about who is due and what was already sent
+pub trait NotificationStore: Send {
+    /// The users with active food expiring on or before `to`, for the expiry scanner
+    fn users_with_food_expiring(&self, to: NaiveDate) -> Result<Vec<i32>, FoodError>;
+
+    /// Whether the scanner already delivered `notification`
+    fn notification_sent(&self, notification: &SentNotification) -> Result<bool, FoodError>;
+
+    /// Records that the scanner delivered `notification` at `sent_at`, so it is not sent again after a
+    /// restart. Recording it again moves `sent_at` on, as digests that list an item again do.
+    fn record_notification(&mut self, notification: &SentNotification, sent_at: DateTime<Utc>) -> Result<(), FoodError>;
+
+    /// When `channel` last delivered anything to the user, if it ever did
+    fn last_notification(&self, user_id: i32, channel: &str) -> Result<Option<DateTime<Utc>>, FoodError>;
+}
+
+/// Per-user settings: reminder preferences and email digest subscriptions
+pub trait PreferenceStore: Send {
+    /// The user's reminder preferences, or `None` when they never set any
+    fn reminder_preferences(&self, user_id: i32) -> Result<Option<ReminderPreferences>, FoodError>;
+
+    /// Stores the user's reminder preferences in place of any earlier ones
+    fn set_reminder_preferences(&mut self, user_id: i32, preferences: &ReminderPreferences) -> Result<(), FoodError>;
+
+    /// The user's email digest subscription, if they have one
+    fn email_digest(&self, user_id: i32) -> Result<Option<EmailDigest>, FoodError>;
+
+    /// Subscribes the user to email digests in place of any earlier subscription, or unsubscribes them with `None`
+    fn set_email_digest(&mut self, user_id: i32, digest: Option<&EmailDigest>) -> Result<(), FoodError>;
+}
+
+/// A backend holding everything the agent stores: food, notification records and preferences.
+/// Implemented for every type that implements the three parts.
+pub trait Repository: FoodRepository + NotificationStore + PreferenceStore {}
+
+impl<T: FoodRepository + NotificationStore + PreferenceStore + ?Sized> Repository for T {}
+
+/// Locks a repository shared between threads. A panic mid-operation leaves nothing
+/// half-written in the database, so a poisoned lock is still used.
+pub fn lock_repository(storage: &Mutex<Box<dyn Repository>>) -> MutexGuard<'_, Box<dyn Repository>> {
+    storage.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
+/// Opens the backend the config's URL points at:
+/// `mysql://...` for MySQL, `sqlite://<path>` for an SQLite file and `memory://` for a
+/// store that lives only as long as the process
+pub fn open_repository(config: &DbConfig) -> Result<Box<dyn Repository>, FoodError> {
+    let url = config.url()?;
+    let mut repository: Box<dyn Repository> = if url.starts_with("mysql://") {
+        Box::new(FoodDb::new(config)?)
+    } else if let Some(path) = url.strip_prefix("sqlite://") {
+        Box::new(SqliteFoodDb::open(path, config.query_timeout)?)
+    } else if url == "memory://" {
+        Box::new(InMemoryFoodDb::new())
+    } else {
+        return Err(FoodError::Validation(format!(
+            "Unsupported database URL '{}'. Use mysql://, sqlite://<path> or memory://",
+            url
+        )));
+    };
+
+    if config.auto_migrate {
+        let applied = repository.migrate()?;
+        log::debug!("Applied {} schema migrations", applied.len());
+    }
+    Ok(repository)
+}
+
+/// Display-ready view of a stored food item, also the shape items take in JSON results
+#[derive(Debug, Clone, Serialize)]
 pub struct SearchResult {
+    pub id: i32,
     pub name: String,
     pub stock_date: String,
     pub food_type: String,
@@ -34,181 +306,487 @@ pub struct SearchResult {
     pub storage_type: String,
     pub expiry_date: String,
     pub quantity: String,
+    /// Quantity in its stored base unit, for callers that compute with it
+    pub quantity_value: f32,
+    pub quantity_unit: String,
+    /// Days before expiry the item is reminded on, e.g. "3,1,0"; empty follows the food type
+    pub reminder_days: String,
 }
 
 impl std::fmt::Display for SearchResult {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} ({}): {} stored {}, expires {}", 
-               self.name, self.food_type, self.quantity, 
+        write!(f, "[#{}] {} ({}): {} stored {}, expires {}",
+               self.id, self.name, self.food_type, self.quantity,
                self.storage_type.to_lowercase(), self.expiry_date)
     }
 }
 
-impl FoodDb {
-    pub fn new(config: DbConfig) -> Result<Self, mysql::Error> {
-        
-        let pool = Pool::new(config.url.as_str())?;
-        let mut conn = pool.get_conn()?;
-        
-        conn.query_drop(
-            format!("CREATE DATABASE IF NOT EXISTS {}", config.database)
-        )?;
-
-        conn.query_drop(format!("USE {}", config.database))?;
-
-        ///Create a table if it does not currently exist
-        conn.exec_drop(
-            r"CREATE TABLE IF NOT EXISTS food_stock (
-                id INT AUTO_INCREMENT PRIMARY KEY,
-                name VARCHAR(50) NOT NULL,
-                stock_date DATE NOT NULL,
-                food_type VARCHAR(50) NOT NULL,
-                nutrient VARCHAR(50) NOT NULL,
-                storage_type VARCHAR(50) NOT NULL,
-                expiry_date DATE NOT NULL,
-                quantity_value FLOAT NOT NULL,
-                quantity_unit VARCHAR(2) NOT NULL
-                )",
-            (),
-        )?;
-
-        Ok(Self {pool})
-
-    }
-    pub fn add_food(&self, food: FoodStock) -> Result<(), mysql::Error> {
-        println!("DEBUG: Starting add_food for: {}", food.name);
-        
-        let mut conn = self.pool.get_conn()?;
-        println!("DEBUG: Got database connection");
-
-        let result = conn.exec_drop(
-            r"INSERT INTO food_stock
-            (name, stock_date, food_type, nutrient, storage_type, expiry_date, quantity_value, quantity_unit)
-            VALUES (:name, :stock_date, :food_type, :nutrient, :storage_type, :expiry_date, :quantity_value, :quantity_unit)",
-            params! {
-                "name" => &food.name,
-                "stock_date" => food.stock_date.format("%Y-%m-%d").to_string(),
-                "food_type" => format!("{:?}", food.food_type),
-                "nutrient" => format!("{:?}", food.nutrient),
-                "storage_type" => format!("{:?}", food.storage_type),
-                "expiry_date" => food.expiry_date.format("%Y-%m-%d").to_string(),
-                "quantity_value" => match food.quantity {
-                    Unit::Grams(g) => g,
-                    Unit::Litres(l) => l,
-                },
-                "quantity_unit" => match food.quantity {
-                    Unit::Grams(_) => "g",
-                    Unit::Litres(_) => "L",
-                }
-            }
-        );
-        
-        match result {
-            Ok(_) => {
-                println!("DEBUG: Insert successful");
-                Ok(())
-            }
-            Err(e) => {
-                println!("DEBUG: Insert failed with error: {}", e);
-                Err(e)
-            }
-        }
+/// What happened when part of a food item was consumed
+#[derive(Debug)]
+pub enum ConsumeOutcome {
+    /// The user has no active item with the given id
+    NotFound,
+    /// The amount cannot be converted into the stored quantity's unit, e.g. ml of a food kept in grams
+    UnitMismatch { stored_unit: String },
+    /// The quantity was lowered and the use recorded in the ledger
+    Consumed(Consumption),
+}
+
+/// How much of a food item was used and what is left
+#[derive(Debug, Serialize)]
+pub struct Consumption {
+    #[serde(serialize_with = "serialize_display")]
+    pub consumed: Unit,
+    #[serde(serialize_with = "serialize_display")]
+    pub remaining: Unit,
+    pub used_up: bool,
+}
+
+fn serialize_display<S: serde::Serializer>(unit: &Unit, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+    serializer.collect_str(unit)
+}
+
+/// The effect of taking an amount out of a stored quantity, worked out before anything is written.
+/// Values are in the stored quantity's base unit.
+struct Deduction {
+    stored: Unit,
+    consumed_value: f32,
+    remaining_value: f32,
+    used_up: bool,
+}
+
+impl Deduction {
+    /// Fails with the outcome to report when `amount` cannot be taken from the stored quantity
+    fn new(stored_value: f32, stored_unit: &str, amount: &Unit) -> Result<Self, ConsumeOutcome> {
+        let stored = match Unit::from_parts(stored_value, stored_unit) {
+            Ok(unit) => unit.to_base(),
+            Err(_) => return Err(ConsumeOutcome::UnitMismatch { stored_unit: stored_unit.to_string() }),
+        };
+        let amount = amount
+            .convert_to(&stored)
+            .map_err(|_| ConsumeOutcome::UnitMismatch { stored_unit: stored.symbol().to_string() })?;
+
+        let consumed_value = amount.value().min(stored.value());
+        let remaining_value = stored.value() - consumed_value;
+        let used_up = remaining_value <= f32::EPSILON;
+        Ok(Self {
+            stored,
+            consumed_value,
+            remaining_value: if used_up { 0.0 } else { remaining_value },
+            used_up,
+        })
+    }
+
+    fn status(&self) -> &'static str {
+        if self.used_up { STATUS_USED_UP } else { STATUS_ACTIVE }
+    }
+
+    fn into_outcome(self) -> ConsumeOutcome {
+        ConsumeOutcome::Consumed(Consumption {
+            consumed: self.stored.with_value(self.consumed_value).humanize(),
+            remaining: self.stored.with_value(self.remaining_value).humanize(),
+            used_up: self.used_up,
+        })
+    }
+}
+
+/// A column value in the form the SQL backends store it
+enum ColumnValue {
+    Text(String),
+    Float(f32),
+    Null,
+}
+
+/// The `(column, value)` pairs an update writes, in the stored representation
+fn update_columns(update: &FoodUpdate) -> Vec<(&'static str, ColumnValue)> {
+    let mut columns = Vec::new();
+    if let Some(name) = &update.name {
+        columns.push(("name", ColumnValue::Text(name.clone())));
+    }
+    if let Some(stock_date) = update.stock_date {
+        columns.push(("stock_date", ColumnValue::Text(stock_date.format("%Y-%m-%d").to_string())));
+    }
+    if let Some(food_type) = &update.food_type {
+        columns.push(("food_type", ColumnValue::Text(format!("{:?}", food_type))));
+        columns.push(("nutrient", ColumnValue::Text(format!("{:?}", food_type.major_nutrient()))));
+    }
+    if let Some(storage_type) = &update.storage_type {
+        columns.push(("storage_type", ColumnValue::Text(format!("{:?}", storage_type))));
+    }
+    if let Some(expiry_date) = update.expiry_date {
+        columns.push(("expiry_date", ColumnValue::Text(expiry_date.format("%Y-%m-%d").to_string())));
+    }
+    if let Some(quantity) = &update.quantity {
+        let base = quantity.to_base();
+        columns.push(("quantity_value", ColumnValue::Float(base.value())));
+        columns.push(("quantity_unit", ColumnValue::Text(base.symbol().to_string())));
+    }
+    if let Some(reminder_days) = &update.reminder_days {
+        let value = reminder_days_column(reminder_days).map_or(ColumnValue::Null, ColumnValue::Text);
+        columns.push(("reminder_days", value));
+    }
+    columns
+}
+
+/// The stored form of an item's reminder schedule, e.g. "3,1,0"; `None` (NULL) follows the food type
+fn reminder_days_column(days: &[u32]) -> Option<String> {
+    (!days.is_empty()).then(|| days.iter().map(u32::to_string).collect::<Vec<_>>().join(","))
+}
+
+/// Reads a schedule written by [`reminder_days_column`]
+fn reminder_days_from_column(column: Option<&str>) -> Result<Vec<u32>, FoodError> {
+    column.map_or(Ok(Vec::new()), |column| column.split(',').map(parse_column).collect())
+}
+
+/// Rebuilds reminder preferences from their stored columns; the time is kept as "HH:MM"
+fn reminder_preferences_from_columns(
+    lead_days: u32,
+    time: &str,
+    timezone: &str,
+    mode: &str,
+) -> Result<ReminderPreferences, FoodError> {
+    Ok(ReminderPreferences {
+        lead_days,
+        time: NaiveTime::parse_from_str(time, "%H:%M")
+            .map_err(|_| FoodError::Storage(format!("Unreadable reminder time '{}' in reminder_preferences", time)))?,
+        timezone: timezone
+            .parse()
+            .map_err(|_| FoodError::Storage(format!("Unreadable time zone '{}' in reminder_preferences", timezone)))?,
+        mode: mode
+            .parse()
+            .map_err(|_| FoodError::Storage(format!("Unreadable reminder mode '{}' in reminder_preferences", mode)))?,
+    })
+}
+
+/// Parses a stored column with `FromStr`, reporting the raw value when it is not understood
+fn parse_column<T: std::str::FromStr>(column: &str) -> Result<T, FoodError> {
+    column
+        .parse()
+        .map_err(|_| FoodError::Storage(format!("Unreadable value '{}' in food_stock", column)))
+}
+
+/// The calendar event a food item's reminder was pushed to, so it can follow the item
+#[derive(Debug, Clone)]
+pub struct LinkedEvent {
+    /// The provider as named in the calendar settings ("google" or "caldav")
+    pub provider: String,
+    /// The provider's id for the event
+    pub event_id: String,
+}
+
+/// Reads a `sent_at` column, stored as UTC "YYYY-MM-DD HH:MM:SS"
+fn sent_at_from_column(sent_at: &str) -> Result<DateTime<Utc>, FoodError> {
+    NaiveDateTime::parse_from_str(sent_at, SENT_AT_FORMAT)
+        .map(|sent_at| sent_at.and_utc())
+        .map_err(|_| FoodError::Storage(format!("Unreadable sent_at '{}' in expiry_notifications", sent_at)))
+}
+
+/// How `sent_at` is written, matching SQL's CURRENT_TIMESTAMP
+const SENT_AT_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+/// Reads an email digest subscription from its `frequency` and `weekday` columns
+fn email_digest_from_columns(address: String, frequency: &str, weekday: &str) -> Result<EmailDigest, FoodError> {
+    let frequency = match frequency {
+        "daily" => DigestFrequency::Daily,
+        "weekly" => DigestFrequency::Weekly(
+            weekday
+                .parse()
+                .map_err(|_| FoodError::Storage(format!("Unreadable weekday '{}' in email_digests", weekday)))?,
+        ),
+        _ => return Err(FoodError::Storage(format!("Unreadable digest frequency '{}' in email_digests", frequency))),
+    };
+    Ok(EmailDigest { address, frequency })
+}
+
+/// The `weekday` column of an email digest subscription; daily digests keep Monday
+fn email_digest_weekday_column(digest: &EmailDigest) -> String {
+    match digest.frequency {
+        DigestFrequency::Daily => chrono::Weekday::Mon.to_string(),
+        DigestFrequency::Weekly(day) => day.to_string(),
     }
+}
+
+/// One reminder stage of one item, delivered through one notification channel. A new expiry
+/// date makes a new notification, so an item that is moved or corrected is announced again.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct SentNotification {
+    pub user_id: i32,
+    pub food_id: i32,
+    pub expiry_date: NaiveDate,
+    /// The reminder stage, in days before expiry
+    pub stage: u32,
+    /// The channel's name, e.g. "webhook"
+    pub channel: String,
+}
 
-    pub fn advanced_search(&self, keyword: &str, field: &str) -> Result<Vec<SearchResult>, mysql::Error> {
-        println!("DEBUG: advanced_search called with keyword='{}', field='{}'", keyword, field);
-        
-        let search_pattern = format!("%{}%", &keyword.trim());
-        println!("DEBUG: search_pattern='{}'", search_pattern);
-        
-        let mut conn = self.pool.get_conn()?;
-        let allowed_fields = ["name", "food_type", "nutrient", "storage_type"];
+/// A stored food item with the id needed to update, consume or delete it
+#[derive(Debug, Clone)]
+pub struct StoredFood {
+    pub id: i32,
+    pub food: FoodStock,
+}
 
-        if !allowed_fields.contains(&field) {
-            println!("DEBUG: Field '{}' not in allowed fields: {:?}", field, allowed_fields);
-            return Err(mysql::Error::DriverError(mysql::DriverError::MissingNamedParameter(field.to_string())))
+impl From<&StoredFood> for SearchResult {
+    fn from(stored: &StoredFood) -> Self {
+        let food = &stored.food;
+        SearchResult {
+            id: stored.id,
+            name: food.name.clone(),
+            stock_date: food.stock_date.format("%Y-%m-%d").to_string(),
+            food_type: food.food_type.to_string(),
+            nutrient: food.nutrient.to_string(),
+            storage_type: food.storage_type.to_string(),
+            expiry_date: food.expiry_date.format("%Y-%m-%d").to_string(),
+            quantity: food.quantity.humanize().to_string(),
+            quantity_value: food.quantity.to_base().value(),
+            quantity_unit: food.quantity.to_base().symbol().to_string(),
+            reminder_days: reminder_days_column(&food.reminder_days).unwrap_or_default(),
         }
+    }
+}
+
+/// Parses a date query string into a NaiveDate
+///
+/// # Arguments
+///
+/// * `query` - A string that can be "today", "tomorrow", "yesterday", or a date in "DD-MM-YYYY" format
+///
+/// # Returns
+///
+/// * `Result<NaiveDate, String>` - The parsed date or an error message
+pub fn parse_query_to_date(query: &str) -> Result<NaiveDate, String> {
+    let query = query.trim().to_lowercase();
+    match query.as_str() {
+        "today" => Ok(Local::now().naive_local().date()),
+        "tomorrow" => Ok(Local::now().naive_local().date() + Duration::days(1)),
+        "yesterday" => Ok(Local::now().naive_local().date() - Duration::days(1)),
+        _ => NaiveDate::parse_from_str(&query, "%d-%m-%Y")
+            .map_err(|_| "Invalid date format. Use 'today', 'tomorrow', 'yesterday', or DD-MM-YYYY".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{FoodType, StorageType};
+
+    const ALICE: i32 = 1;
+    const BOB: i32 = 2;
+
+    fn date(day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2026, 3, day).unwrap()
+    }
+
+    fn food(name: &str, food_type: FoodType, storage_type: StorageType, expiry_day: u32, quantity: Unit) -> FoodStock {
+        let nutrient = food_type.major_nutrient();
+        FoodStock::new(name.to_string(), date(1), food_type, nutrient, storage_type, date(expiry_day), quantity)
+    }
+
+    fn ids(foods: &[StoredFood]) -> Vec<i32> {
+        foods.iter().map(|stored| stored.id).collect()
+    }
+
+    fn sqlite() -> SqliteFoodDb {
+        let mut db = SqliteFoodDb::open(":memory:", None).unwrap();
+        db.migrate().unwrap();
+        db
+    }
+
+    /// Adds milk, rice and chicken for Alice and cheese for Bob, returning Alice's ids
+    fn stock(db: &mut dyn Repository) -> [i32; 3] {
+        let milk = db
+            .add_food(ALICE, food("milk", FoodType::Dairy, StorageType::Cold, 5, Unit::Litres(1.0)))
+            .unwrap();
+        let added = db
+            .add_foods(
+                ALICE,
+                vec![
+                    food("rice", FoodType::Grains, StorageType::Pantry, 20, Unit::Kilograms(2.0)),
+                    food("chicken", FoodType::Meat, StorageType::Frozen, 3, Unit::Grams(800.0)),
+                ],
+            )
+            .unwrap();
+        db.add_food(BOB, food("cheese", FoodType::Dairy, StorageType::Cold, 4, Unit::Grams(200.0)))
+            .unwrap();
+        [milk, added[0], added[1]]
+    }
+
+    fn check_add_and_get(db: &mut dyn Repository) {
+        let [milk, rice, chicken] = stock(db);
+
+        let stored = db.get_food(ALICE, milk).unwrap().unwrap();
+        assert_eq!(stored.food.name, "milk");
+        assert_eq!(stored.food.food_type, FoodType::Dairy);
+        assert_eq!(stored.food.storage_type, StorageType::Cold);
+        assert_eq!(stored.food.expiry_date, date(5));
+        assert_eq!(stored.food.quantity, Unit::Millilitres(1000.0));
 
-        let query = format!("SELECT name, stock_date, food_type, nutrient, storage_type, expiry_date, quantity_value, quantity_unit FROM food_stock WHERE {} LIKE :search_string", field);
-        println!("DEBUG: executing query: {}", query);
-        
-        let foods: Vec<SearchResult> = conn.exec_map(
-            query,
-            params! {
-                "search_string" => &search_pattern
-            },
-            |(name, stock_date, food_type, nutrient, storage_type, expiry_date, quantity_value, quantity_unit): (String, mysql::Value, String, String, String, mysql::Value, f32, String)| {
-                let quantity = format!("{}{}", quantity_value, quantity_unit);
-                let stock_date_str = match stock_date {
-                    mysql::Value::Date(year, month, day, _, _, _, _) => format!("{:04}-{:02}-{:02}", year, month, day),
-                    _ => "Unknown".to_string(),
-                };
-                let expiry_date_str = match expiry_date {
-                    mysql::Value::Date(year, month, day, _, _, _, _) => format!("{:04}-{:02}-{:02}", year, month, day),
-                    _ => "Unknown".to_string(),
-                };
-                SearchResult {
-                    name,
-                    stock_date: stock_date_str,
-                    food_type,
-                    nutrient,
-                    storage_type,
-                    expiry_date: expiry_date_str,
-                    quantity,
-                }
-            },
-        )?;
-        
-        println!("DEBUG: advanced_search found {} results", foods.len());
-        Ok(foods)
-    }
-
-    pub fn get_all_food(&self) -> Result<Vec<SearchResult>, mysql::Error> {
-        println!("DEBUG: Starting get_all_food");
-        let mut conn = self.pool.get_conn()?;
-        println!("DEBUG: Got connection for select");
-        
-        let result = conn.query_map(
-            r"SELECT name, stock_date, food_type, nutrient, storage_type, expiry_date, quantity_value, quantity_unit FROM food_stock",
-                |(name, stock_date, food_type, nutrient, storage_type, expiry_date, quantity_value, quantity_unit): (String, String, String, String, String, String, String, String) |{
-                let quantity = format!("{}{}", quantity_value, quantity_unit);
-                SearchResult {
-                    name,
-                    stock_date,
-                    food_type,
-                    nutrient,
-                    storage_type,
-                    expiry_date,
-                    quantity,
-                }
-            },
-        );
-        
-        match &result {
-            Ok(foods) => println!("DEBUG: Found {} food items", foods.len()),
-            Err(e) => println!("DEBUG: Select failed: {}", e),
+        let mut all = ids(&db.get_all_food(ALICE).unwrap());
+        all.sort();
+        assert_eq!(all, vec![milk, rice, chicken]);
+        assert!(db.get_food(BOB, milk).unwrap().is_none());
+        assert_eq!(db.get_all_food(BOB).unwrap().len(), 1);
+    }
+
+    fn check_search(db: &mut dyn Repository) {
+        let [milk, rice, chicken] = stock(db);
+
+        assert_eq!(ids(&db.advanced_search(ALICE, "RIC", "name").unwrap()), vec![rice]);
+        assert_eq!(ids(&db.advanced_search(ALICE, "dairy", "food_type").unwrap()), vec![milk]);
+        assert_eq!(ids(&db.advanced_search(ALICE, "frozen", "storage_type").unwrap()), vec![chicken]);
+        assert!(db.advanced_search(ALICE, "cheese", "name").unwrap().is_empty());
+        assert!(matches!(db.advanced_search(ALICE, "milk", "colour"), Err(FoodError::Validation(_))));
+
+        assert_eq!(ids(&db.search_by_expiry(ALICE, None, None).unwrap()), vec![chicken, milk, rice]);
+        assert_eq!(ids(&db.search_by_expiry(ALICE, Some(date(4)), Some(date(20))).unwrap()), vec![milk, rice]);
+        assert_eq!(ids(&db.search_by_expiry(ALICE, None, Some(date(3))).unwrap()), vec![chicken]);
+
+        assert_eq!(db.users_with_food_expiring(date(3)).unwrap(), vec![ALICE]);
+        assert_eq!(db.users_with_food_expiring(date(4)).unwrap(), vec![ALICE, BOB]);
+        assert!(db.users_with_food_expiring(date(2)).unwrap().is_empty());
+    }
+
+    fn check_update(db: &mut dyn Repository) {
+        let [milk, ..] = stock(db);
+
+        let update = FoodUpdate {
+            name: Some("oat milk".to_string()),
+            food_type: Some(FoodType::Non_dairy),
+            expiry_date: Some(date(9)),
+            quantity: Some(Unit::Millilitres(500.0)),
+            reminder_days: Some(vec![3, 1]),
+            ..FoodUpdate::default()
+        };
+        assert!(db.update_food(ALICE, milk, &update).unwrap());
+
+        let stored = db.get_food(ALICE, milk).unwrap().unwrap();
+        assert_eq!(stored.food.name, "oat milk");
+        assert_eq!(stored.food.food_type, FoodType::Non_dairy);
+        assert_eq!(stored.food.nutrient, FoodType::Non_dairy.major_nutrient());
+        assert_eq!(stored.food.storage_type, StorageType::Cold);
+        assert_eq!(stored.food.expiry_date, date(9));
+        assert_eq!(stored.food.quantity, Unit::Millilitres(500.0));
+        assert_eq!(stored.food.reminder_days, vec![3, 1]);
+
+        assert!(!db.update_food(BOB, milk, &update).unwrap());
+        assert!(!db.update_food(ALICE, 999, &update).unwrap());
+    }
+
+    fn check_delete(db: &mut dyn Repository) {
+        let [milk, rice, chicken] = stock(db);
+
+        assert!(!db.delete_food(BOB, milk).unwrap());
+        assert!(db.delete_food(ALICE, milk).unwrap());
+        assert!(db.get_food(ALICE, milk).unwrap().is_none());
+        assert!(!db.delete_food(ALICE, milk).unwrap());
+        let mut all = ids(&db.get_all_food(ALICE).unwrap());
+        all.sort();
+        assert_eq!(all, vec![rice, chicken]);
+    }
+
+    fn check_consume(db: &mut dyn Repository) {
+        let [milk, _, chicken] = stock(db);
+
+        match db.consume(ALICE, chicken, &Unit::Grams(300.0)).unwrap() {
+            ConsumeOutcome::Consumed(consumption) => {
+                assert_eq!(consumption.consumed.to_base(), Unit::Grams(300.0));
+                assert_eq!(consumption.remaining.to_base(), Unit::Grams(500.0));
+                assert!(!consumption.used_up);
+            }
+            other => panic!("unexpected outcome {:?}", other),
         }
-        
-        result
-    }
-}           
-
-    /// Parses a date query string into a NaiveDate
-    /// 
-    /// # Arguments
-    /// 
-    /// * `query` - A string that can be "today", "tomorrow", "yesterday", or a date in "DD-MM-YYYY" format
-    /// 
-    /// # Returns
-    /// 
-    /// * `Result<NaiveDate, String>` - The parsed date or an error message
-    pub fn parse_query_to_date(query: &str) -> Result<NaiveDate, String> {
-        let query = query.trim().to_lowercase();
-        match query.as_str() {
-            "today" => Ok(Local::now().naive_local().date()),
-            "tomorrow" => Ok(Local::now().naive_local().date() + Duration::days(1)),
-            "yesterday" => Ok(Local::now().naive_local().date() - Duration::days(1)),
-            _ => NaiveDate::parse_from_str(&query, "%d-%m-%Y")
-                .map_err(|_| "Invalid date format. Use 'today', 'tomorrow', 'yesterday', or DD-MM-YYYY".to_string())
+        assert_eq!(db.get_food(ALICE, chicken).unwrap().unwrap().food.quantity, Unit::Grams(500.0));
+
+        assert!(matches!(
+            db.consume(ALICE, chicken, &Unit::Millilitres(100.0)).unwrap(),
+            ConsumeOutcome::UnitMismatch { .. }
+        ));
+        assert!(matches!(db.consume(BOB, chicken, &Unit::Grams(1.0)).unwrap(), ConsumeOutcome::NotFound));
+
+        match db.consume(ALICE, milk, &Unit::Litres(2.0)).unwrap() {
+            ConsumeOutcome::Consumed(consumption) => {
+                assert_eq!(consumption.consumed.to_base(), Unit::Millilitres(1000.0));
+                assert!(consumption.used_up);
+            }
+            other => panic!("unexpected outcome {:?}", other),
         }
-    }
\ No newline at end of file
+        assert!(db.get_food(ALICE, milk).unwrap().is_none());
+        assert!(matches!(db.consume(ALICE, milk, &Unit::Litres(1.0)).unwrap(), ConsumeOutcome::NotFound));
+    }
+
+    fn check_notifications(db: &mut dyn Repository) {
+        let sent = SentNotification { user_id: ALICE, food_id: 1, expiry_date: date(5), stage: 1, channel: "email".to_string() };
+        let at = |day: u32| date(day).and_hms_opt(9, 0, 0).unwrap().and_utc();
+        assert!(!db.notification_sent(&sent).unwrap());
+        assert_eq!(db.last_notification(ALICE, "email").unwrap(), None);
+
+        db.record_notification(&sent, at(3)).unwrap();
+        assert!(db.notification_sent(&sent).unwrap());
+        assert_eq!(db.last_notification(ALICE, "email").unwrap(), Some(at(3)));
+        assert_eq!(db.last_notification(ALICE, "webhook").unwrap(), None);
+        assert_eq!(db.last_notification(BOB, "email").unwrap(), None);
+
+        db.record_notification(&sent, at(4)).unwrap();
+        assert_eq!(db.last_notification(ALICE, "email").unwrap(), Some(at(4)));
+    }
+
+    fn check_used_up_items_stay_untouched(db: &mut dyn Repository) {
+        let [milk, ..] = stock(db);
+        assert!(matches!(db.consume(ALICE, milk, &Unit::Litres(1.0)).unwrap(), ConsumeOutcome::Consumed(_)));
+
+        let update = FoodUpdate { name: Some("oat milk".to_string()), ..FoodUpdate::default() };
+        assert!(!db.update_food(ALICE, milk, &update).unwrap());
+        assert!(!db.delete_food(ALICE, milk).unwrap());
+    }
+
+    /// Runs each check against a fresh in-memory store and a fresh in-memory SQLite database
+    fn check_backends(check: fn(&mut dyn Repository)) {
+        check(&mut InMemoryFoodDb::new());
+        check(&mut sqlite());
+    }
+
+    #[test]
+    fn opening_needs_a_supported_url() {
+        let mut config = DbConfig::new("memory://");
+        assert!(open_repository(&config).is_ok());
+        config.url = None;
+        assert!(matches!(open_repository(&config), Err(FoodError::Validation(_))));
+        let config = config.with_url("postgres://localhost/food");
+        assert!(matches!(open_repository(&config), Err(FoodError::Validation(_))));
+    }
+
+    #[test]
+    fn adds_and_gets_food_per_user() {
+        check_backends(check_add_and_get);
+    }
+
+    #[test]
+    fn searches_by_field_and_expiry() {
+        check_backends(check_search);
+    }
+
+    #[test]
+    fn updates_only_the_owners_item() {
+        check_backends(check_update);
+    }
+
+    #[test]
+    fn deletes_only_the_owners_item() {
+        check_backends(check_delete);
+    }
+
+    #[test]
+    fn consumes_until_used_up() {
+        check_backends(check_consume);
+    }
+
+    #[test]
+    fn neither_updates_nor_deletes_used_up_items() {
+        check_backends(check_used_up_items_stay_untouched);
+    }
+
+    #[test]
+    fn records_notifications_and_when_they_last_went_out() {
+        check_backends(check_notifications);
+    }
+}
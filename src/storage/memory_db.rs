@@ -0,0 +1,235 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::error::FoodError;
+use crate::models::{FoodStock, FoodUpdate, Unit};
+use crate::notify::EmailDigest;
+use crate::reminder::ReminderPreferences;
+use super::{ConsumeOutcome, Deduction, FoodRepository, LinkedEvent, NotificationStore, PreferenceStore, SentNotification, StoredFood, SEARCHABLE_FIELDS, STATUS_ACTIVE};
+
+struct MemoryRow {
+    user_id: i32,
+    status: &'static str,
+    food: FoodStock,
+    calendar_event: Option<LinkedEvent>,
+}
+
+/// [`FoodRepository`] kept entirely in memory, for tests and throwaway sessions.
+/// Nothing survives the process.
+#[derive(Default)]
+pub struct InMemoryFoodDb {
+    rows: BTreeMap<i32, MemoryRow>,
+    next_id: i32,
+    reminder_preferences: HashMap<i32, ReminderPreferences>,
+    digest_events: HashMap<(i32, NaiveDate), LinkedEvent>,
+    /// When each notification was sent
+    sent_notifications: HashMap<SentNotification, DateTime<Utc>>,
+    email_digests: HashMap<i32, EmailDigest>,
+}
+
+impl InMemoryFoodDb {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    fn active(&self, user_id: i32) -> impl Iterator<Item = StoredFood> + '_ {
+        self.rows
+            .iter()
+            .filter(move |(_, row)| row.user_id == user_id && row.status == STATUS_ACTIVE)
+            .map(|(id, row)| StoredFood { id: *id, food: row.food.clone() })
+    }
+}
+
+/// The value `advanced_search` matches against, as the SQL backends store it
+fn field_value(food: &FoodStock, field: &str) -> String {
+    match field {
+        "name" => food.name.clone(),
+        "food_type" => format!("{:?}", food.food_type),
+        "nutrient" => format!("{:?}", food.nutrient),
+        _ => format!("{:?}", food.storage_type),
+    }
+}
+
+impl FoodRepository for InMemoryFoodDb {
+    fn add_food(&mut self, user_id: i32, mut food: FoodStock) -> Result<i32, FoodError> {
+        self.next_id += 1;
+        food.quantity = food.quantity.to_base();
+        self.rows.insert(self.next_id, MemoryRow { user_id, status: STATUS_ACTIVE, food, calendar_event: None });
+        Ok(self.next_id)
+    }
+
+    fn add_foods(&mut self, user_id: i32, foods: Vec<FoodStock>) -> Result<Vec<i32>, FoodError> {
+        foods.into_iter().map(|food| self.add_food(user_id, food)).collect()
+    }
+
+    fn get_food(&self, user_id: i32, id: i32) -> Result<Option<StoredFood>, FoodError> {
+        Ok(self.active(user_id).find(|stored| stored.id == id))
+    }
+
+    fn get_all_food(&self, user_id: i32) -> Result<Vec<StoredFood>, FoodError> {
+        Ok(self.active(user_id).collect())
+    }
+
+    fn advanced_search(&self, user_id: i32, keyword: &str, field: &str) -> Result<Vec<StoredFood>, FoodError> {
+        if !SEARCHABLE_FIELDS.contains(&field) {
+            return Err(FoodError::Validation(format!("Cannot search by '{}'", field)));
+        }
+        let keyword = keyword.trim().to_lowercase();
+        Ok(self
+            .active(user_id)
+            .filter(|stored| field_value(&stored.food, field).to_lowercase().contains(&keyword))
+            .collect())
+    }
+
+    fn search_by_expiry(
+        &self,
+        user_id: i32,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<StoredFood>, FoodError> {
+        let mut foods: Vec<StoredFood> = self
+            .active(user_id)
+            .filter(|stored| from.is_none_or(|from| stored.food.expiry_date >= from))
+            .filter(|stored| to.is_none_or(|to| stored.food.expiry_date <= to))
+            .collect();
+        foods.sort_by(|a, b| {
+            a.food.expiry_date.cmp(&b.food.expiry_date).then_with(|| a.food.name.cmp(&b.food.name))
+        });
+        Ok(foods)
+    }
+
+    fn update_food(&mut self, user_id: i32, id: i32, update: &FoodUpdate) -> Result<bool, FoodError> {
+        let food = match self.rows.get_mut(&id) {
+            Some(row) if row.user_id == user_id && row.status == STATUS_ACTIVE => &mut row.food,
+            _ => return Ok(false),
+        };
+        if let Some(name) = &update.name {
+            food.name = name.clone();
+        }
+        if let Some(stock_date) = update.stock_date {
+            food.stock_date = stock_date;
+        }
+        if let Some(food_type) = &update.food_type {
+            food.nutrient = food_type.major_nutrient();
+            food.food_type = food_type.clone();
+        }
+        if let Some(storage_type) = &update.storage_type {
+            food.storage_type = storage_type.clone();
+        }
+        if let Some(expiry_date) = update.expiry_date {
+            food.expiry_date = expiry_date;
+        }
+        if let Some(quantity) = &update.quantity {
+            food.quantity = quantity.to_base();
+        }
+        if let Some(reminder_days) = &update.reminder_days {
+            food.reminder_days = reminder_days.clone();
+        }
+        Ok(true)
+    }
+
+    fn delete_food(&mut self, user_id: i32, id: i32) -> Result<bool, FoodError> {
+        match self.rows.get(&id) {
+            Some(row) if row.user_id == user_id && row.status == STATUS_ACTIVE => Ok(self.rows.remove(&id).is_some()),
+            _ => Ok(false),
+        }
+    }
+
+    fn consume(&mut self, user_id: i32, id: i32, amount: &Unit) -> Result<ConsumeOutcome, FoodError> {
+        let row = match self.rows.get_mut(&id) {
+            Some(row) if row.user_id == user_id && row.status == STATUS_ACTIVE => row,
+            _ => return Ok(ConsumeOutcome::NotFound),
+        };
+        let quantity = row.food.quantity;
+        let deduction = match Deduction::new(quantity.value(), quantity.symbol(), amount) {
+            Ok(deduction) => deduction,
+            Err(outcome) => return Ok(outcome),
+        };
+
+        row.food.quantity = deduction.stored.with_value(deduction.remaining_value);
+        row.status = deduction.status();
+        Ok(deduction.into_outcome())
+    }
+
+    fn calendar_event(&self, user_id: i32, id: i32) -> Result<Option<LinkedEvent>, FoodError> {
+        Ok(self
+            .rows
+            .get(&id)
+            .filter(|row| row.user_id == user_id)
+            .and_then(|row| row.calendar_event.clone()))
+    }
+
+    fn set_calendar_event(&mut self, user_id: i32, id: i32, event: Option<&LinkedEvent>) -> Result<bool, FoodError> {
+        match self.rows.get_mut(&id) {
+            Some(row) if row.user_id == user_id => {
+                row.calendar_event = event.cloned();
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+
+    fn digest_event(&self, user_id: i32, date: NaiveDate) -> Result<Option<LinkedEvent>, FoodError> {
+        Ok(self.digest_events.get(&(user_id, date)).cloned())
+    }
+
+    fn set_digest_event(&mut self, user_id: i32, date: NaiveDate, event: Option<&LinkedEvent>) -> Result<(), FoodError> {
+        match event {
+            Some(event) => self.digest_events.insert((user_id, date), event.clone()),
+            None => self.digest_events.remove(&(user_id, date)),
+        };
+        Ok(())
+    }
+}
+
+impl NotificationStore for InMemoryFoodDb {
+    fn users_with_food_expiring(&self, to: NaiveDate) -> Result<Vec<i32>, FoodError> {
+        let users: BTreeSet<i32> = self
+            .rows
+            .values()
+            .filter(|row| row.status == STATUS_ACTIVE && row.food.expiry_date <= to)
+            .map(|row| row.user_id)
+            .collect();
+        Ok(users.into_iter().collect())
+    }
+
+    fn notification_sent(&self, notification: &SentNotification) -> Result<bool, FoodError> {
+        Ok(self.sent_notifications.contains_key(notification))
+    }
+
+    fn record_notification(&mut self, notification: &SentNotification, sent_at: DateTime<Utc>) -> Result<(), FoodError> {
+        self.sent_notifications.insert(notification.clone(), sent_at);
+        Ok(())
+    }
+
+    fn last_notification(&self, user_id: i32, channel: &str) -> Result<Option<DateTime<Utc>>, FoodError> {
+        Ok(self
+            .sent_notifications
+            .iter()
+            .filter(|(sent, _)| sent.user_id == user_id && sent.channel == channel)
+            .map(|(_, sent_at)| *sent_at)
+            .max())
+    }
+}
+
+impl PreferenceStore for InMemoryFoodDb {
+    fn reminder_preferences(&self, user_id: i32) -> Result<Option<ReminderPreferences>, FoodError> {
+        Ok(self.reminder_preferences.get(&user_id).copied())
+    }
+
+    fn set_reminder_preferences(&mut self, user_id: i32, preferences: &ReminderPreferences) -> Result<(), FoodError> {
+        self.reminder_preferences.insert(user_id, *preferences);
+        Ok(())
+    }
+
+    fn email_digest(&self, user_id: i32) -> Result<Option<EmailDigest>, FoodError> {
+        Ok(self.email_digests.get(&user_id).cloned())
+    }
+
+    fn set_email_digest(&mut self, user_id: i32, digest: Option<&EmailDigest>) -> Result<(), FoodError> {
+        match digest {
+            Some(digest) => self.email_digests.insert(user_id, digest.clone()),
+            None => self.email_digests.remove(&user_id),
+        };
+        Ok(())
+    }
+}
@@ -0,0 +1,549 @@
+//! Versioned schema migrations.
+//!
+//! Every table the agent uses, including the account and conversation tables the Python API
+//! reads, is created and evolved here. Each migration runs once, in version order, and is
+//! recorded in `schema_migrations`. The early steps are written to be safe against databases
+//! that were set up before this runner existed, so such a database converges instead of failing.
+//!
+//! Never edit a migration that has shipped; add a new one with the next version instead.
+
+use crate::error::FoodError;
+use mysql::prelude::*;
+use mysql::{params, PooledConn};
+use rusqlite::{named_params, Connection};
+
+/// What a migration does when it runs
+enum Step {
+    /// A single SQL statement
+    Sql(&'static str),
+    /// A change that needs to inspect the schema first or depends on configuration
+    Custom(fn(&mut PooledConn, &MigrationContext) -> Result<(), mysql::Error>),
+}
+
+struct Migration {
+    version: u32,
+    name: &'static str,
+    step: Step,
+}
+
+/// Settings some migrations depend on
+pub struct MigrationContext {
+    /// User that pre-existing, unowned food rows are handed to
+    pub legacy_owner: i32,
+}
+
+const MYSQL_MIGRATIONS: &[Migration] = &[
+    Migration {
+        version: 1,
+        name: "create_food_stock",
+        step: Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS food_stock (
+                id INT AUTO_INCREMENT PRIMARY KEY,
+                user_id INT NOT NULL,
+                name VARCHAR(50) NOT NULL,
+                stock_date DATE NOT NULL,
+                food_type VARCHAR(50) NOT NULL,
+                nutrient VARCHAR(50) NOT NULL,
+                storage_type VARCHAR(50) NOT NULL,
+                expiry_date DATE NOT NULL,
+                quantity_value FLOAT NOT NULL,
+                quantity_unit VARCHAR(10) NOT NULL,
+                status VARCHAR(20) NOT NULL DEFAULT 'active',
+                INDEX idx_food_stock_user (user_id)
+                )",
+        ),
+    },
+    Migration { version: 2, name: "food_stock_user_ownership", step: Step::Custom(migrate_user_ownership) },
+    Migration { version: 3, name: "food_stock_status", step: Step::Custom(add_status_column) },
+    Migration {
+        version: 4,
+        name: "create_food_consumption",
+        // Ledger of every partial or full use of a food item
+        step: Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS food_consumption (
+                id INT AUTO_INCREMENT PRIMARY KEY,
+                food_stock_id INT NOT NULL,
+                user_id INT NOT NULL,
+                amount_value FLOAT NOT NULL,
+                amount_unit VARCHAR(10) NOT NULL,
+                consumed_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (food_stock_id) REFERENCES food_stock(id) ON DELETE CASCADE,
+                INDEX idx_consumption_user (user_id, consumed_at)
+                )",
+        ),
+    },
+    Migration { version: 5, name: "quantity_base_units", step: Step::Custom(migrate_quantity_units) },
+    Migration {
+        version: 6,
+        name: "create_shelf_life_rules",
+        // Tunable shelf lives; rows here override the built-in table
+        step: Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS shelf_life_rules (
+                id INT AUTO_INCREMENT PRIMARY KEY,
+                food_type VARCHAR(50) NULL,
+                storage_type VARCHAR(50) NULL,
+                name_pattern VARCHAR(100) NULL,
+                days INT NOT NULL
+                )",
+        ),
+    },
+    Migration {
+        version: 7,
+        name: "create_users",
+        step: Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS users (
+                id INT AUTO_INCREMENT PRIMARY KEY,
+                email VARCHAR(255) UNIQUE NOT NULL,
+                username VARCHAR(100) UNIQUE NOT NULL,
+                password_hash VARCHAR(255) NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
+                )",
+        ),
+    },
+    Migration {
+        version: 8,
+        name: "create_user_conversations",
+        step: Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS user_conversations (
+                id INT AUTO_INCREMENT PRIMARY KEY,
+                user_id INT NOT NULL,
+                session_id VARCHAR(36) NOT NULL,
+                title VARCHAR(255) DEFAULT 'New Conversation',
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE,
+                INDEX idx_user_sessions (user_id, session_id),
+                INDEX idx_conversations_updated_at (updated_at),
+                UNIQUE KEY unique_user_session (user_id, session_id)
+                )",
+        ),
+    },
+    Migration {
+        version: 9,
+        name: "create_conversation_messages",
+        step: Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS conversation_messages (
+                id INT AUTO_INCREMENT PRIMARY KEY,
+                user_id INT NOT NULL,
+                session_id VARCHAR(255) NOT NULL,
+                message TEXT NOT NULL,
+                response TEXT NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES users(id),
+                INDEX idx_user_session (user_id, session_id),
+                INDEX idx_created_at (created_at)
+                )",
+        ),
+    },
+    Migration {
+        version: 10,
+        name: "create_user_google_tokens",
+        step: Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS user_google_tokens (
+                user_id INT PRIMARY KEY,
+                token_data JSON NOT NULL,
+                created_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+                )",
+        ),
+    },
+    Migration {
+        version: 11,
+        name: "create_user_calendar_settings",
+        step: Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS user_calendar_settings (
+                user_id INT PRIMARY KEY,
+                provider VARCHAR(20) NOT NULL,
+                caldav_url VARCHAR(500),
+                caldav_username VARCHAR(255),
+                caldav_password VARCHAR(255),
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP,
+                FOREIGN KEY (user_id) REFERENCES users(id) ON DELETE CASCADE
+                )",
+        ),
+    },
+    Migration {
+        version: 12,
+        name: "food_stock_calendar_event",
+        // The event each item's reminder was pushed to, so it can be moved or cancelled later
+        step: Step::Sql(
+            r"ALTER TABLE food_stock
+                ADD COLUMN calendar_provider VARCHAR(20) NULL,
+                ADD COLUMN calendar_event_id VARCHAR(1024) NULL",
+        ),
+    },
+    Migration {
+        version: 13,
+        name: "create_reminder_preferences",
+        // When each user wants reminders: days ahead of expiry, "HH:MM" local time and IANA zone
+        step: Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS reminder_preferences (
+                user_id INT PRIMARY KEY,
+                lead_days INT NOT NULL,
+                reminder_time VARCHAR(5) NOT NULL,
+                timezone VARCHAR(64) NOT NULL,
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
+                )",
+        ),
+    },
+    Migration {
+        version: 14,
+        name: "food_stock_reminder_days",
+        // Each item's own reminder schedule, e.g. "3,1,0"; NULL follows the food type
+        step: Step::Sql("ALTER TABLE food_stock ADD COLUMN reminder_days VARCHAR(64) NULL"),
+    },
+    Migration {
+        version: 15,
+        name: "reminder_mode",
+        // Whether the user's calendar gets one event per item or one digest per day
+        step: Step::Sql(
+            "ALTER TABLE reminder_preferences ADD COLUMN reminder_mode VARCHAR(10) NOT NULL DEFAULT 'item'",
+        ),
+    },
+    Migration {
+        version: 16,
+        name: "create_reminder_digests",
+        // The digest event pushed for each day the user has food expiring
+        step: Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS reminder_digests (
+                user_id INT NOT NULL,
+                expiry_date DATE NOT NULL,
+                calendar_provider VARCHAR(20) NOT NULL,
+                calendar_event_id VARCHAR(1024) NOT NULL,
+                PRIMARY KEY (user_id, expiry_date)
+                )",
+        ),
+    },
+    Migration {
+        version: 17,
+        name: "create_expiry_notifications",
+        // What the expiry scanner already sent, per item, expiry date, reminder stage and channel
+        step: Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS expiry_notifications (
+                user_id INT NOT NULL,
+                food_id INT NOT NULL,
+                expiry_date DATE NOT NULL,
+                stage INT NOT NULL,
+                channel VARCHAR(50) NOT NULL,
+                sent_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP,
+                PRIMARY KEY (food_id, expiry_date, stage, channel),
+                INDEX idx_expiry_notifications_user (user_id)
+                )",
+        ),
+    },
+    Migration {
+        version: 18,
+        name: "create_email_digests",
+        // Where and how often each subscribed user gets their expiry digest by email
+        step: Step::Sql(
+            r"CREATE TABLE IF NOT EXISTS email_digests (
+                user_id INT PRIMARY KEY,
+                address VARCHAR(255) NOT NULL,
+                frequency VARCHAR(10) NOT NULL,
+                weekday VARCHAR(3) NOT NULL DEFAULT 'Mon',
+                updated_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP ON UPDATE CURRENT_TIMESTAMP
+                )",
+        ),
+    },
+    Migration {
+        version: 19,
+        name: "encrypted_caldav_password",
+        // The API stores CalDAV passwords encrypted, which no longer fits in 255 characters
+        step: Step::Sql("ALTER TABLE user_calendar_settings MODIFY caldav_password TEXT NULL"),
+    },
+];
+
+/// SQLite versions of the food tables. Accounts and conversations stay in MySQL with the web API.
+const SQLITE_MIGRATIONS: &[(u32, &str, &str)] = &[
+    (
+        1,
+        "create_food_stock",
+        r"CREATE TABLE IF NOT EXISTS food_stock (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            user_id INTEGER NOT NULL,
+            name TEXT NOT NULL,
+            stock_date TEXT NOT NULL,
+            food_type TEXT NOT NULL,
+            nutrient TEXT NOT NULL,
+            storage_type TEXT NOT NULL,
+            expiry_date TEXT NOT NULL,
+            quantity_value REAL NOT NULL,
+            quantity_unit TEXT NOT NULL,
+            status TEXT NOT NULL DEFAULT 'active'
+        );
+        CREATE INDEX IF NOT EXISTS idx_food_stock_user ON food_stock (user_id);",
+    ),
+    (
+        2,
+        "create_food_consumption",
+        r"CREATE TABLE IF NOT EXISTS food_consumption (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            food_stock_id INTEGER NOT NULL REFERENCES food_stock (id) ON DELETE CASCADE,
+            user_id INTEGER NOT NULL,
+            amount_value REAL NOT NULL,
+            amount_unit TEXT NOT NULL,
+            consumed_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );
+        CREATE INDEX IF NOT EXISTS idx_consumption_user ON food_consumption (user_id, consumed_at);",
+    ),
+    (
+        3,
+        "create_shelf_life_rules",
+        r"CREATE TABLE IF NOT EXISTS shelf_life_rules (
+            id INTEGER PRIMARY KEY AUTOINCREMENT,
+            food_type TEXT NULL,
+            storage_type TEXT NULL,
+            name_pattern TEXT NULL,
+            days INTEGER NOT NULL
+        );",
+    ),
+    (
+        4,
+        "food_stock_calendar_event",
+        r"ALTER TABLE food_stock ADD COLUMN calendar_provider TEXT NULL;
+        ALTER TABLE food_stock ADD COLUMN calendar_event_id TEXT NULL;",
+    ),
+    (
+        5,
+        "create_reminder_preferences",
+        r"CREATE TABLE IF NOT EXISTS reminder_preferences (
+            user_id INTEGER PRIMARY KEY,
+            lead_days INTEGER NOT NULL,
+            reminder_time TEXT NOT NULL,
+            timezone TEXT NOT NULL,
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );",
+    ),
+    (
+        6,
+        "food_stock_reminder_days",
+        r"ALTER TABLE food_stock ADD COLUMN reminder_days TEXT NULL;",
+    ),
+    (
+        7,
+        "reminder_mode",
+        r"ALTER TABLE reminder_preferences ADD COLUMN reminder_mode TEXT NOT NULL DEFAULT 'item';",
+    ),
+    (
+        8,
+        "create_reminder_digests",
+        r"CREATE TABLE IF NOT EXISTS reminder_digests (
+            user_id INTEGER NOT NULL,
+            expiry_date TEXT NOT NULL,
+            calendar_provider TEXT NOT NULL,
+            calendar_event_id TEXT NOT NULL,
+            PRIMARY KEY (user_id, expiry_date)
+        );",
+    ),
+    (
+        9,
+        "create_expiry_notifications",
+        r"CREATE TABLE IF NOT EXISTS expiry_notifications (
+            user_id INTEGER NOT NULL,
+            food_id INTEGER NOT NULL,
+            expiry_date TEXT NOT NULL,
+            stage INTEGER NOT NULL,
+            channel TEXT NOT NULL,
+            sent_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP,
+            PRIMARY KEY (food_id, expiry_date, stage, channel)
+        );",
+    ),
+    (
+        10,
+        "create_email_digests",
+        r"CREATE TABLE IF NOT EXISTS email_digests (
+            user_id INTEGER PRIMARY KEY,
+            address TEXT NOT NULL,
+            frequency TEXT NOT NULL,
+            weekday TEXT NOT NULL DEFAULT 'Mon',
+            updated_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );",
+    ),
+];
+
+/// Adds the `user_id` column to tables created before food was scoped per user.
+/// Existing rows are handed to the legacy owner.
+fn migrate_user_ownership(conn: &mut PooledConn, context: &MigrationContext) -> Result<(), mysql::Error> {
+    if column_exists(conn, "food_stock", "user_id")? {
+        return Ok(());
+    }
+
+    log::debug!("Migrating food_stock rows to legacy owner {}", context.legacy_owner);
+    conn.query_drop(
+        format!(
+            "ALTER TABLE food_stock
+            ADD COLUMN user_id INT NOT NULL DEFAULT {} AFTER id,
+            ADD INDEX idx_food_stock_user (user_id)",
+            context.legacy_owner
+        )
+    )?;
+    conn.query_drop("ALTER TABLE food_stock ALTER COLUMN user_id DROP DEFAULT")?;
+    Ok(())
+}
+
+fn add_status_column(conn: &mut PooledConn, _context: &MigrationContext) -> Result<(), mysql::Error> {
+    if column_exists(conn, "food_stock", "status")? {
+        return Ok(());
+    }
+    conn.query_drop("ALTER TABLE food_stock ADD COLUMN status VARCHAR(20) NOT NULL DEFAULT 'active'")
+}
+
+/// Widens the two-character unit columns so units like "kg", "pcs" or "loaf" fit,
+/// and moves litre quantities to the millilitre base unit quantities are now stored in
+fn migrate_quantity_units(conn: &mut PooledConn, _context: &MigrationContext) -> Result<(), mysql::Error> {
+    let unit_length: Option<i64> = conn.query_first(
+        r"SELECT CHARACTER_MAXIMUM_LENGTH FROM information_schema.COLUMNS
+        WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = 'food_stock' AND COLUMN_NAME = 'quantity_unit'",
+    )?;
+    if unit_length.unwrap_or(0) >= 10 {
+        return Ok(());
+    }
+
+    conn.query_drop("ALTER TABLE food_stock MODIFY quantity_unit VARCHAR(10) NOT NULL")?;
+    conn.query_drop("ALTER TABLE food_consumption MODIFY amount_unit VARCHAR(10) NOT NULL")?;
+    conn.query_drop(
+        "UPDATE food_stock SET quantity_value = quantity_value * 1000, quantity_unit = 'ml' WHERE quantity_unit = 'L'"
+    )?;
+    conn.query_drop(
+        "UPDATE food_consumption SET amount_value = amount_value * 1000, amount_unit = 'ml' WHERE amount_unit = 'L'"
+    )?;
+    Ok(())
+}
+
+fn column_exists(conn: &mut PooledConn, table: &str, column: &str) -> Result<bool, mysql::Error> {
+    let count: Option<i64> = conn.exec_first(
+        r"SELECT COUNT(*) FROM information_schema.COLUMNS
+        WHERE TABLE_SCHEMA = DATABASE() AND TABLE_NAME = :table AND COLUMN_NAME = :column",
+        params! { "table" => table, "column" => column },
+    )?;
+    Ok(count.unwrap_or(0) > 0)
+}
+
+/// Applies every MySQL migration not yet recorded and returns the names of those applied
+pub fn migrate_mysql(conn: &mut PooledConn, context: &MigrationContext) -> Result<Vec<String>, FoodError> {
+    conn.query_drop(
+        r"CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INT PRIMARY KEY,
+            name VARCHAR(100) NOT NULL,
+            applied_at TIMESTAMP DEFAULT CURRENT_TIMESTAMP
+            )",
+    )?;
+    let applied: Vec<u32> = conn.query("SELECT version FROM schema_migrations")?;
+
+    let mut names = Vec::new();
+    for migration in MYSQL_MIGRATIONS.iter().filter(|m| !applied.contains(&m.version)) {
+        log::debug!("Applying migration {} ({})", migration.version, migration.name);
+        match migration.step {
+            Step::Sql(sql) => conn.query_drop(sql)?,
+            Step::Custom(apply) => apply(conn, context)?,
+        }
+        // MySQL commits DDL implicitly, so the record is written once the change has landed
+        conn.exec_drop(
+            "INSERT INTO schema_migrations (version, name) VALUES (:version, :name)",
+            params! { "version" => migration.version, "name" => migration.name },
+        )?;
+        names.push(migration.name.to_string());
+    }
+    Ok(names)
+}
+
+/// Applies every SQLite migration not yet recorded and returns the names of those applied.
+/// Each migration and its record are committed together.
+pub fn migrate_sqlite(conn: &mut Connection) -> Result<Vec<String>, FoodError> {
+    conn.execute_batch(
+        r"CREATE TABLE IF NOT EXISTS schema_migrations (
+            version INTEGER PRIMARY KEY,
+            name TEXT NOT NULL,
+            applied_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+        );",
+    )?;
+    let applied: Vec<u32> = conn
+        .prepare("SELECT version FROM schema_migrations")?
+        .query_map([], |row| row.get(0))?
+        .collect::<Result<_, _>>()?;
+
+    let mut names = Vec::new();
+    for (version, name, sql) in SQLITE_MIGRATIONS.iter().filter(|(v, _, _)| !applied.contains(v)) {
+        log::debug!("Applying migration {} ({})", version, name);
+        let tx = conn.transaction()?;
+        tx.execute_batch(sql)?;
+        tx.execute(
+            "INSERT INTO schema_migrations (version, name) VALUES (:version, :name)",
+            named_params! { ":version": version, ":name": name },
+        )?;
+        tx.commit()?;
+        names.push(name.to_string());
+    }
+    Ok(names)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn versions_increase(versions: &[u32]) -> bool {
+        versions.first() == Some(&1) && versions.windows(2).all(|pair| pair[1] == pair[0] + 1)
+    }
+
+    fn recorded_versions(conn: &Connection) -> Vec<u32> {
+        conn.prepare("SELECT version FROM schema_migrations ORDER BY version")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap()
+    }
+
+    fn table_exists(conn: &Connection, table: &str) -> bool {
+        conn.query_row(
+            "SELECT COUNT(*) FROM sqlite_master WHERE type = 'table' AND name = ?1",
+            [table],
+            |row| row.get::<_, i64>(0),
+        )
+        .unwrap()
+            > 0
+    }
+
+    #[test]
+    fn versions_are_numbered_in_order_without_gaps() {
+        let mysql: Vec<u32> = MYSQL_MIGRATIONS.iter().map(|m| m.version).collect();
+        let sqlite: Vec<u32> = SQLITE_MIGRATIONS.iter().map(|(version, _, _)| *version).collect();
+        assert!(versions_increase(&mysql), "MySQL versions {:?}", mysql);
+        assert!(versions_increase(&sqlite), "SQLite versions {:?}", sqlite);
+    }
+
+    #[test]
+    fn sqlite_applies_every_migration_once() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let names = migrate_sqlite(&mut conn).unwrap();
+        let expected: Vec<&str> = SQLITE_MIGRATIONS.iter().map(|(_, name, _)| *name).collect();
+        assert_eq!(names, expected);
+        assert_eq!(recorded_versions(&conn).len(), SQLITE_MIGRATIONS.len());
+        for table in ["food_stock", "food_consumption", "expiry_notifications", "email_digests"] {
+            assert!(table_exists(&conn, table), "missing table {}", table);
+        }
+
+        assert!(migrate_sqlite(&mut conn).unwrap().is_empty());
+        assert_eq!(recorded_versions(&conn).len(), SQLITE_MIGRATIONS.len());
+    }
+
+    #[test]
+    fn sqlite_resumes_after_the_last_recorded_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        let (version, name, sql) = SQLITE_MIGRATIONS[0];
+        conn.execute_batch(sql).unwrap();
+        conn.execute_batch(
+            "CREATE TABLE schema_migrations (version INTEGER PRIMARY KEY, name TEXT NOT NULL, applied_at TEXT);",
+        )
+        .unwrap();
+        conn.execute("INSERT INTO schema_migrations (version, name) VALUES (?1, ?2)", (version, name))
+            .unwrap();
+
+        let names = migrate_sqlite(&mut conn).unwrap();
+        assert_eq!(names.len(), SQLITE_MIGRATIONS.len() - 1);
+        assert!(!names.contains(&name.to_string()));
+        assert_eq!(recorded_versions(&conn), (1..=SQLITE_MIGRATIONS.len() as u32).collect::<Vec<_>>());
+    }
+}
@@ -0,0 +1,576 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::error::FoodError;
+use crate::models::{FoodStock, FoodUpdate, Unit};
+use crate::notify::EmailDigest;
+use crate::reminder::ReminderPreferences;
+use crate::shelf_life::RawShelfLifeRule;
+use std::convert::TryFrom;
+use super::migrations::{migrate_mysql, MigrationContext};
+use super::{
+    email_digest_from_columns, email_digest_weekday_column, parse_column, reminder_days_column, reminder_days_from_column,
+    reminder_preferences_from_columns, sent_at_from_column, update_columns, SENT_AT_FORMAT, ColumnValue, ConsumeOutcome, DbConfig, Deduction, FoodRepository, LinkedEvent, NotificationStore, PreferenceStore,
+    SentNotification, StoredFood, DEFAULT_DATABASE, SEARCHABLE_FIELDS, STATUS_ACTIVE,
+};
+
+use mysql::*;
+use mysql::prelude::*;
+
+/// MySQL-backed [`FoodRepository`]
+pub struct FoodDb {
+    pool: Pool,
+    legacy_owner: i32,
+}
+
+/// Column layout shared by every `SELECT` that produces a [`StoredFood`]
+type FoodRow = (i32, String, mysql::Value, String, String, String, mysql::Value, f32, String, Option<String>);
+
+const FOOD_COLUMNS: &str =
+    "id, name, stock_date, food_type, nutrient, storage_type, expiry_date, quantity_value, quantity_unit, reminder_days";
+
+/// Reads a `DATE` column from either the binary (`Value::Date`) or text (`Value::Bytes`) protocol
+fn date_from_value(value: mysql::Value) -> Result<NaiveDate, mysql::Error> {
+    let date = match &value {
+        mysql::Value::Date(year, month, day, _, _, _, _) => {
+            NaiveDate::from_ymd_opt(i32::from(*year), u32::from(*month), u32::from(*day))
+        }
+        mysql::Value::Bytes(bytes) => std::str::from_utf8(bytes)
+            .ok()
+            .and_then(|s| NaiveDate::parse_from_str(s, "%Y-%m-%d").ok()),
+        _ => None,
+    };
+    date.ok_or(mysql::Error::FromValueError(value))
+}
+
+impl TryFrom<FoodRow> for StoredFood {
+    type Error = FoodError;
+
+    fn try_from(
+        (id, name, stock_date, food_type, nutrient, storage_type, expiry_date, quantity_value, quantity_unit, reminder_days): FoodRow,
+    ) -> Result<Self, Self::Error> {
+        let quantity = Unit::from_parts(quantity_value, &quantity_unit)
+            .map_err(|_| FoodError::Storage(format!("Unreadable quantity unit '{}' in food_stock", quantity_unit)))?;
+        let mut food = FoodStock::new(
+            name,
+            date_from_value(stock_date)?,
+            parse_column(&food_type)?,
+            parse_column(&nutrient)?,
+            parse_column(&storage_type)?,
+            date_from_value(expiry_date)?,
+            quantity,
+        );
+        food.reminder_days = reminder_days_from_column(reminder_days.as_deref())?;
+        Ok(StoredFood { id, food })
+    }
+}
+
+fn stored_foods(rows: Vec<FoodRow>) -> Result<Vec<StoredFood>, FoodError> {
+    rows.into_iter().map(StoredFood::try_from).collect()
+}
+
+impl From<ColumnValue> for mysql::Value {
+    fn from(value: ColumnValue) -> Self {
+        match value {
+            ColumnValue::Text(text) => text.into(),
+            ColumnValue::Float(number) => number.into(),
+            ColumnValue::Null => mysql::Value::NULL,
+        }
+    }
+}
+
+/// Inserts one item over `conn`, which may be a plain connection or a transaction, and returns its id
+fn insert_food(conn: &mut impl Queryable, user_id: i32, food: &FoodStock) -> Result<i32, mysql::Error> {
+    let base = food.quantity.to_base();
+    let result = conn.exec_iter(
+        r"INSERT INTO food_stock
+        (user_id, name, stock_date, food_type, nutrient, storage_type, expiry_date, quantity_value, quantity_unit, reminder_days)
+        VALUES (:user_id, :name, :stock_date, :food_type, :nutrient, :storage_type, :expiry_date, :quantity_value, :quantity_unit,
+        :reminder_days)",
+        params! {
+            "user_id" => user_id,
+            "name" => &food.name,
+            "stock_date" => food.stock_date.format("%Y-%m-%d").to_string(),
+            "food_type" => format!("{:?}", food.food_type),
+            "nutrient" => format!("{:?}", food.nutrient),
+            "storage_type" => format!("{:?}", food.storage_type),
+            "expiry_date" => food.expiry_date.format("%Y-%m-%d").to_string(),
+            "quantity_value" => base.value(),
+            "quantity_unit" => base.symbol(),
+            "reminder_days" => reminder_days_column(&food.reminder_days)
+        },
+    )?;
+    Ok(result.last_insert_id().unwrap_or_default() as i32)
+}
+
+impl FoodDb {
+    /// Connects and creates the database if needed. Tables are created by [`FoodRepository::migrate`].
+    pub fn new(config: &DbConfig) -> Result<Self, FoodError> {
+        let opts = Opts::from_url(config.url()?)
+            .map_err(|e| FoodError::Validation(format!("Invalid MySQL URL: {}", e)))?;
+        let database = config
+            .database
+            .clone()
+            .or_else(|| opts.get_db_name().map(str::to_string))
+            .unwrap_or_else(|| DEFAULT_DATABASE.to_string());
+        // The name is spliced into SQL below, so only plain identifiers are accepted
+        if database.is_empty() || !database.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(FoodError::Validation(format!("Invalid database name '{}'", database)));
+        }
+        let constraints = PoolConstraints::new(1, config.pool_size)
+            .ok_or_else(|| FoodError::Validation("Pool size must be at least 1".to_string()))?;
+
+        let builder = OptsBuilder::from_opts(opts)
+            .tcp_connect_timeout(config.connect_timeout)
+            .read_timeout(config.query_timeout)
+            .write_timeout(config.query_timeout);
+
+        // The database may not exist yet, so create it over a connection that doesn't select one
+        let mut conn = Conn::new(builder.clone().db_name(None::<String>))?;
+        conn.query_drop(format!("CREATE DATABASE IF NOT EXISTS {}", database))?;
+        log::debug!("Using MySQL database {} with up to {} connections", database, config.pool_size);
+
+        let pool = Pool::new(
+            builder
+                .db_name(Some(database))
+                .pool_opts(PoolOpts::default().with_constraints(constraints)),
+        )?;
+
+        Ok(Self {pool, legacy_owner: config.legacy_owner})
+    }
+}
+
+impl FoodRepository for FoodDb {
+    fn migrate(&mut self) -> Result<Vec<String>, FoodError> {
+        let mut conn = self.pool.get_conn()?;
+        migrate_mysql(&mut conn, &MigrationContext { legacy_owner: self.legacy_owner })
+    }
+
+    fn add_food(&mut self, user_id: i32, food: FoodStock) -> Result<i32, FoodError> {
+        log::debug!("Starting add_food for: {} (user {})", food.name, user_id);
+
+        let mut conn = self.pool.get_conn()?;
+        log::debug!("Got database connection");
+
+        match insert_food(&mut conn, user_id, &food) {
+            Ok(id) => {
+                log::debug!("Insert successful, id {}", id);
+                Ok(id)
+            }
+            Err(e) => {
+                log::debug!("Insert failed with error: {}", e);
+                Err(e.into())
+            }
+        }
+    }
+
+    fn add_foods(&mut self, user_id: i32, foods: Vec<FoodStock>) -> Result<Vec<i32>, FoodError> {
+        log::debug!("Starting add_foods for {} items (user {})", foods.len(), user_id);
+
+        let mut conn = self.pool.get_conn()?;
+        // Dropping the transaction on an error rolls back the rows inserted before it
+        let mut tx = conn.start_transaction(TxOpts::default())?;
+        let ids = foods
+            .iter()
+            .map(|food| insert_food(&mut tx, user_id, food))
+            .collect::<Result<Vec<i32>, _>>()?;
+        tx.commit()?;
+        log::debug!("Inserted {} items", ids.len());
+        Ok(ids)
+    }
+
+    fn advanced_search(&self, user_id: i32, keyword: &str, field: &str) -> Result<Vec<StoredFood>, FoodError> {
+        log::debug!("advanced_search called for user {} with keyword='{}', field='{}'", user_id, keyword, field);
+
+        let search_pattern = format!("%{}%", &keyword.trim());
+        log::debug!("search_pattern='{}'", search_pattern);
+
+        let mut conn = self.pool.get_conn()?;
+
+        if !SEARCHABLE_FIELDS.contains(&field) {
+            log::debug!("Field '{}' not in allowed fields: {:?}", field, SEARCHABLE_FIELDS);
+            return Err(FoodError::Validation(format!("Cannot search by '{}'", field)));
+        }
+
+        let query = format!("SELECT {} FROM food_stock WHERE user_id = :user_id AND status = :status AND {} LIKE :search_string", FOOD_COLUMNS, field);
+        log::debug!("executing query: {}", query);
+
+        let rows: Vec<FoodRow> = conn.exec(
+            query,
+            params! {
+                "user_id" => user_id,
+                "status" => STATUS_ACTIVE,
+                "search_string" => &search_pattern
+            },
+        )?;
+        let foods = stored_foods(rows)?;
+
+        log::debug!("advanced_search found {} results", foods.len());
+        Ok(foods)
+    }
+
+    fn get_all_food(&self, user_id: i32) -> Result<Vec<StoredFood>, FoodError> {
+        log::debug!("Starting get_all_food for user {}", user_id);
+        let mut conn = self.pool.get_conn()?;
+        log::debug!("Got connection for select");
+
+        let result = conn.exec(
+            format!("SELECT {} FROM food_stock WHERE user_id = :user_id AND status = :status ORDER BY id", FOOD_COLUMNS),
+            params! {
+                "user_id" => user_id,
+                "status" => STATUS_ACTIVE
+            },
+        ).map_err(FoodError::from).and_then(stored_foods);
+
+        match &result {
+            Ok(foods) => log::debug!("Found {} food items", foods.len()),
+            Err(e) => log::debug!("Select failed: {}", e),
+        }
+
+        result
+    }
+
+    fn get_food(&self, user_id: i32, id: i32) -> Result<Option<StoredFood>, FoodError> {
+        log::debug!("get_food called for item {} (user {})", id, user_id);
+        let mut conn = self.pool.get_conn()?;
+        let row: Option<FoodRow> = conn.exec_first(
+            format!("SELECT {} FROM food_stock WHERE id = :id AND user_id = :user_id AND status = :status", FOOD_COLUMNS),
+            params! { "id" => id, "user_id" => user_id, "status" => STATUS_ACTIVE },
+        )?;
+        row.map(StoredFood::try_from).transpose()
+    }
+
+    fn update_food(&mut self, user_id: i32, id: i32, update: &FoodUpdate) -> Result<bool, FoodError> {
+        log::debug!("update_food called for item {} (user {})", id, user_id);
+        let mut conn = self.pool.get_conn()?;
+
+        let owned: Option<i32> = conn.exec_first(
+            "SELECT id FROM food_stock WHERE id = :id AND user_id = :user_id AND status = :status",
+            params! { "id" => id, "user_id" => user_id, "status" => STATUS_ACTIVE },
+        )?;
+        if owned.is_none() {
+            log::debug!("No food item {} for user {}", id, user_id);
+            return Ok(false);
+        }
+
+        let columns = update_columns(update);
+        if columns.is_empty() {
+            return Ok(true);
+        }
+
+        let assignments: Vec<String> = columns.iter().map(|(column, _)| format!("{0} = :{0}", column)).collect();
+        let mut values: Vec<(String, mysql::Value)> = vec![
+            ("id".to_string(), id.into()),
+            ("user_id".to_string(), user_id.into()),
+            ("status".to_string(), STATUS_ACTIVE.into()),
+        ];
+        values.extend(columns.into_iter().map(|(column, value)| (column.to_string(), value.into())));
+
+        let query = format!(
+            "UPDATE food_stock SET {} WHERE id = :id AND user_id = :user_id AND status = :status",
+            assignments.join(", ")
+        );
+        log::debug!("executing query: {}", query);
+        conn.exec_drop(query, Params::from(values))?;
+        Ok(true)
+    }
+
+    fn delete_food(&mut self, user_id: i32, id: i32) -> Result<bool, FoodError> {
+        log::debug!("delete_food called for item {} (user {})", id, user_id);
+        let mut conn = self.pool.get_conn()?;
+
+        conn.exec_drop(
+            "DELETE FROM food_stock WHERE id = :id AND user_id = :user_id AND status = :status",
+            params! { "id" => id, "user_id" => user_id, "status" => STATUS_ACTIVE },
+        )?;
+
+        let deleted = conn.affected_rows() > 0;
+        log::debug!("delete_food removed item: {}", deleted);
+        Ok(deleted)
+    }
+
+    fn search_by_expiry(
+        &self,
+        user_id: i32,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<StoredFood>, FoodError> {
+        log::debug!("search_by_expiry called for user {} with range {:?}..={:?}", user_id, from, to);
+        let mut conn = self.pool.get_conn()?;
+
+        let mut query = format!("SELECT {} FROM food_stock WHERE user_id = :user_id AND status = :status", FOOD_COLUMNS);
+        let mut values: Vec<(String, mysql::Value)> = vec![
+            ("user_id".to_string(), user_id.into()),
+            ("status".to_string(), STATUS_ACTIVE.into()),
+        ];
+        if let Some(from) = from {
+            query.push_str(" AND expiry_date >= :from_date");
+            values.push(("from_date".to_string(), from.format("%Y-%m-%d").to_string().into()));
+        }
+        if let Some(to) = to {
+            query.push_str(" AND expiry_date <= :to_date");
+            values.push(("to_date".to_string(), to.format("%Y-%m-%d").to_string().into()));
+        }
+        query.push_str(" ORDER BY expiry_date ASC, name ASC");
+
+        let foods = stored_foods(conn.exec(query, Params::from(values))?)?;
+        log::debug!("search_by_expiry found {} results", foods.len());
+        Ok(foods)
+    }
+
+    fn load_shelf_life_rules(&self) -> Result<Vec<RawShelfLifeRule>, FoodError> {
+        let mut conn = self.pool.get_conn()?;
+        let rules = conn.query_map(
+            "SELECT food_type, storage_type, name_pattern, days FROM shelf_life_rules ORDER BY id",
+            |(food_type, storage_type, name_pattern, days)| RawShelfLifeRule {
+                food_type,
+                storage_type,
+                name_pattern,
+                days,
+            },
+        )?;
+        log::debug!("Loaded {} shelf-life rules from the database", rules.len());
+        Ok(rules)
+    }
+
+    fn consume(&mut self, user_id: i32, id: i32, amount: &Unit) -> Result<ConsumeOutcome, FoodError> {
+        log::debug!("consume called for item {} (user {}) with {}", id, user_id, amount);
+        let mut conn = self.pool.get_conn()?;
+        let mut tx = conn.start_transaction(TxOpts::default())?;
+
+        let current: Option<(f32, String)> = tx.exec_first(
+            "SELECT quantity_value, quantity_unit FROM food_stock
+            WHERE id = :id AND user_id = :user_id AND status = :status FOR UPDATE",
+            params! { "id" => id, "user_id" => user_id, "status" => STATUS_ACTIVE },
+        )?;
+        let (stored_value, stored_unit) = match current {
+            Some(row) => row,
+            None => return Ok(ConsumeOutcome::NotFound),
+        };
+        let deduction = match Deduction::new(stored_value, &stored_unit, amount) {
+            Ok(deduction) => deduction,
+            Err(outcome) => return Ok(outcome),
+        };
+
+        tx.exec_drop(
+            r"INSERT INTO food_consumption (food_stock_id, user_id, amount_value, amount_unit)
+            VALUES (:food_stock_id, :user_id, :amount_value, :amount_unit)",
+            params! {
+                "food_stock_id" => id,
+                "user_id" => user_id,
+                "amount_value" => deduction.consumed_value,
+                "amount_unit" => deduction.stored.symbol(),
+            },
+        )?;
+        tx.exec_drop(
+            "UPDATE food_stock SET quantity_value = :quantity_value, quantity_unit = :quantity_unit, status = :status WHERE id = :id",
+            params! {
+                "quantity_value" => deduction.remaining_value,
+                "quantity_unit" => deduction.stored.symbol(),
+                "status" => deduction.status(),
+                "id" => id,
+            },
+        )?;
+        tx.commit()?;
+
+        log::debug!("consume left {}{} (used up: {})", deduction.remaining_value, deduction.stored.symbol(), deduction.used_up);
+        Ok(deduction.into_outcome())
+    }
+
+    fn calendar_event(&self, user_id: i32, id: i32) -> Result<Option<LinkedEvent>, FoodError> {
+        let mut conn = self.pool.get_conn()?;
+        let linked: Option<(Option<String>, Option<String>)> = conn.exec_first(
+            "SELECT calendar_provider, calendar_event_id FROM food_stock WHERE id = :id AND user_id = :user_id",
+            params! { "id" => id, "user_id" => user_id },
+        )?;
+        Ok(match linked {
+            Some((Some(provider), Some(event_id))) => Some(LinkedEvent { provider, event_id }),
+            _ => None,
+        })
+    }
+
+    fn set_calendar_event(&mut self, user_id: i32, id: i32, event: Option<&LinkedEvent>) -> Result<bool, FoodError> {
+        log::debug!("set_calendar_event called for item {} (user {})", id, user_id);
+        let mut conn = self.pool.get_conn()?;
+
+        // affected_rows() is 0 when the values don't change, so ownership is checked separately
+        let owned: Option<i32> = conn.exec_first(
+            "SELECT id FROM food_stock WHERE id = :id AND user_id = :user_id AND status = :status",
+            params! { "id" => id, "user_id" => user_id, "status" => STATUS_ACTIVE },
+        )?;
+        if owned.is_none() {
+            return Ok(false);
+        }
+        conn.exec_drop(
+            "UPDATE food_stock SET calendar_provider = :provider, calendar_event_id = :event_id
+            WHERE id = :id AND user_id = :user_id",
+            params! {
+                "provider" => event.map(|event| event.provider.as_str()),
+                "event_id" => event.map(|event| event.event_id.as_str()),
+                "id" => id,
+                "user_id" => user_id,
+            },
+        )?;
+        Ok(true)
+    }
+
+    fn digest_event(&self, user_id: i32, date: NaiveDate) -> Result<Option<LinkedEvent>, FoodError> {
+        let mut conn = self.pool.get_conn()?;
+        let linked: Option<(String, String)> = conn.exec_first(
+            "SELECT calendar_provider, calendar_event_id FROM reminder_digests
+            WHERE user_id = :user_id AND expiry_date = :expiry_date",
+            params! { "user_id" => user_id, "expiry_date" => date.format("%Y-%m-%d").to_string() },
+        )?;
+        Ok(linked.map(|(provider, event_id)| LinkedEvent { provider, event_id }))
+    }
+
+    fn set_digest_event(&mut self, user_id: i32, date: NaiveDate, event: Option<&LinkedEvent>) -> Result<(), FoodError> {
+        log::debug!("set_digest_event called for {} (user {})", date, user_id);
+        let mut conn = self.pool.get_conn()?;
+        let expiry_date = date.format("%Y-%m-%d").to_string();
+        match event {
+            Some(event) => conn.exec_drop(
+                r"INSERT INTO reminder_digests (user_id, expiry_date, calendar_provider, calendar_event_id)
+                VALUES (:user_id, :expiry_date, :provider, :event_id)
+                ON DUPLICATE KEY UPDATE
+                    calendar_provider = VALUES(calendar_provider),
+                    calendar_event_id = VALUES(calendar_event_id)",
+                params! {
+                    "user_id" => user_id,
+                    "expiry_date" => expiry_date,
+                    "provider" => event.provider.as_str(),
+                    "event_id" => event.event_id.as_str(),
+                },
+            )?,
+            None => conn.exec_drop(
+                "DELETE FROM reminder_digests WHERE user_id = :user_id AND expiry_date = :expiry_date",
+                params! { "user_id" => user_id, "expiry_date" => expiry_date },
+            )?,
+        }
+        Ok(())
+    }
+}
+
+impl NotificationStore for FoodDb {
+    fn users_with_food_expiring(&self, to: NaiveDate) -> Result<Vec<i32>, FoodError> {
+        let mut conn = self.pool.get_conn()?;
+        Ok(conn.exec(
+            "SELECT DISTINCT user_id FROM food_stock WHERE status = :status AND expiry_date <= :to_date ORDER BY user_id",
+            params! { "status" => STATUS_ACTIVE, "to_date" => to.format("%Y-%m-%d").to_string() },
+        )?)
+    }
+
+    fn notification_sent(&self, notification: &SentNotification) -> Result<bool, FoodError> {
+        let mut conn = self.pool.get_conn()?;
+        let sent: Option<i32> = conn.exec_first(
+            "SELECT 1 FROM expiry_notifications
+            WHERE food_id = :food_id AND expiry_date = :expiry_date AND stage = :stage AND channel = :channel",
+            params! {
+                "food_id" => notification.food_id,
+                "expiry_date" => notification.expiry_date.format("%Y-%m-%d").to_string(),
+                "stage" => notification.stage,
+                "channel" => notification.channel.as_str(),
+            },
+        )?;
+        Ok(sent.is_some())
+    }
+
+    fn record_notification(&mut self, notification: &SentNotification, sent_at: DateTime<Utc>) -> Result<(), FoodError> {
+        let mut conn = self.pool.get_conn()?;
+        conn.exec_drop(
+            r"INSERT INTO expiry_notifications (user_id, food_id, expiry_date, stage, channel, sent_at)
+            VALUES (:user_id, :food_id, :expiry_date, :stage, :channel, :sent_at)
+            ON DUPLICATE KEY UPDATE sent_at = VALUES(sent_at)",
+            params! {
+                "user_id" => notification.user_id,
+                "food_id" => notification.food_id,
+                "expiry_date" => notification.expiry_date.format("%Y-%m-%d").to_string(),
+                "stage" => notification.stage,
+                "channel" => notification.channel.as_str(),
+                "sent_at" => sent_at.format(SENT_AT_FORMAT).to_string(),
+            },
+        )?;
+        Ok(())
+    }
+
+    fn last_notification(&self, user_id: i32, channel: &str) -> Result<Option<DateTime<Utc>>, FoodError> {
+        let mut conn = self.pool.get_conn()?;
+        // sent_at is written as UTC; formatting it reads it back unchanged whatever the session time zone
+        let sent_at: Option<Option<String>> = conn.exec_first(
+            "SELECT DATE_FORMAT(MAX(sent_at), '%Y-%m-%d %H:%i:%s') FROM expiry_notifications
+            WHERE user_id = :user_id AND channel = :channel",
+            params! { "user_id" => user_id, "channel" => channel },
+        )?;
+        sent_at.flatten().as_deref().map(sent_at_from_column).transpose()
+    }
+}
+
+impl PreferenceStore for FoodDb {
+    fn reminder_preferences(&self, user_id: i32) -> Result<Option<ReminderPreferences>, FoodError> {
+        let mut conn = self.pool.get_conn()?;
+        let stored: Option<(u32, String, String, String)> = conn.exec_first(
+            "SELECT lead_days, reminder_time, timezone, reminder_mode FROM reminder_preferences WHERE user_id = :user_id",
+            params! { "user_id" => user_id },
+        )?;
+        stored
+            .map(|(lead_days, time, timezone, mode)| reminder_preferences_from_columns(lead_days, &time, &timezone, &mode))
+            .transpose()
+    }
+
+    fn set_reminder_preferences(&mut self, user_id: i32, preferences: &ReminderPreferences) -> Result<(), FoodError> {
+        log::debug!("set_reminder_preferences called for user {}", user_id);
+        let mut conn = self.pool.get_conn()?;
+        conn.exec_drop(
+            r"INSERT INTO reminder_preferences (user_id, lead_days, reminder_time, timezone, reminder_mode)
+            VALUES (:user_id, :lead_days, :reminder_time, :timezone, :reminder_mode)
+            ON DUPLICATE KEY UPDATE
+                lead_days = VALUES(lead_days),
+                reminder_time = VALUES(reminder_time),
+                timezone = VALUES(timezone),
+                reminder_mode = VALUES(reminder_mode)",
+            params! {
+                "user_id" => user_id,
+                "lead_days" => preferences.lead_days,
+                "reminder_time" => preferences.time.format("%H:%M").to_string(),
+                "timezone" => preferences.timezone.name(),
+                "reminder_mode" => preferences.mode.as_str(),
+            },
+        )?;
+        Ok(())
+    }
+
+    fn email_digest(&self, user_id: i32) -> Result<Option<EmailDigest>, FoodError> {
+        let mut conn = self.pool.get_conn()?;
+        let stored: Option<(String, String, String)> = conn.exec_first(
+            "SELECT address, frequency, weekday FROM email_digests WHERE user_id = :user_id",
+            params! { "user_id" => user_id },
+        )?;
+        stored
+            .map(|(address, frequency, weekday)| email_digest_from_columns(address, &frequency, &weekday))
+            .transpose()
+    }
+
+    fn set_email_digest(&mut self, user_id: i32, digest: Option<&EmailDigest>) -> Result<(), FoodError> {
+        log::debug!("set_email_digest called for user {}", user_id);
+        let mut conn = self.pool.get_conn()?;
+        match digest {
+            Some(digest) => conn.exec_drop(
+                r"INSERT INTO email_digests (user_id, address, frequency, weekday)
+                VALUES (:user_id, :address, :frequency, :weekday)
+                ON DUPLICATE KEY UPDATE
+                    address = VALUES(address),
+                    frequency = VALUES(frequency),
+                    weekday = VALUES(weekday)",
+                params! {
+                    "user_id" => user_id,
+                    "address" => digest.address.as_str(),
+                    "frequency" => digest.frequency.as_str(),
+                    "weekday" => email_digest_weekday_column(digest),
+                },
+            )?,
+            None => conn.exec_drop(
+                "DELETE FROM email_digests WHERE user_id = :user_id",
+                params! { "user_id" => user_id },
+            )?,
+        }
+        Ok(())
+    }
+}
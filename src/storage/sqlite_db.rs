@@ -0,0 +1,518 @@
+use chrono::{DateTime, NaiveDate, Utc};
+use crate::error::FoodError;
+use crate::models::{FoodStock, FoodUpdate, Unit};
+use crate::notify::EmailDigest;
+use crate::reminder::ReminderPreferences;
+use crate::shelf_life::RawShelfLifeRule;
+use rusqlite::types::{ToSqlOutput, Value};
+use std::time::Duration;
+use rusqlite::{named_params, params_from_iter, Connection, OptionalExtension, Row, ToSql};
+use super::migrations::migrate_sqlite;
+use super::{
+    email_digest_from_columns, email_digest_weekday_column, parse_column, reminder_days_column, reminder_days_from_column,
+    reminder_preferences_from_columns, sent_at_from_column, update_columns, SENT_AT_FORMAT, ColumnValue, ConsumeOutcome, Deduction, FoodRepository, LinkedEvent, NotificationStore, PreferenceStore,
+    SentNotification, StoredFood, SEARCHABLE_FIELDS, STATUS_ACTIVE,
+};
+
+/// Embedded SQLite [`FoodRepository`] for single-user deployments that run without a MySQL server.
+/// Dates are kept as `YYYY-MM-DD` text so they sort and compare like MySQL `DATE`s.
+pub struct SqliteFoodDb {
+    conn: Connection,
+}
+
+const FOOD_COLUMNS: &str =
+    "id, name, stock_date, food_type, nutrient, storage_type, expiry_date, quantity_value, quantity_unit, reminder_days";
+
+fn parse_date(column: &str) -> Result<NaiveDate, FoodError> {
+    NaiveDate::parse_from_str(column, "%Y-%m-%d")
+        .map_err(|_| FoodError::Storage(format!("Unreadable date '{}' in food_stock", column)))
+}
+
+/// Reads a row selected with [`FOOD_COLUMNS`]
+fn stored_food(row: &Row) -> Result<StoredFood, FoodError> {
+    let stock_date: String = row.get(2)?;
+    let food_type: String = row.get(3)?;
+    let nutrient: String = row.get(4)?;
+    let storage_type: String = row.get(5)?;
+    let expiry_date: String = row.get(6)?;
+    let quantity_value: f32 = row.get(7)?;
+    let quantity_unit: String = row.get(8)?;
+    let reminder_days: Option<String> = row.get(9)?;
+
+    let quantity = Unit::from_parts(quantity_value, &quantity_unit)
+        .map_err(|_| FoodError::Storage(format!("Unreadable quantity unit '{}' in food_stock", quantity_unit)))?;
+    let mut food = FoodStock::new(
+        row.get(1)?,
+        parse_date(&stock_date)?,
+        parse_column(&food_type)?,
+        parse_column(&nutrient)?,
+        parse_column(&storage_type)?,
+        parse_date(&expiry_date)?,
+        quantity,
+    );
+    food.reminder_days = reminder_days_from_column(reminder_days.as_deref())?;
+    Ok(StoredFood { id: row.get(0)?, food })
+}
+
+/// Inserts one item over `conn`, which may be inside a transaction, and returns its id
+fn insert_food(conn: &Connection, user_id: i32, food: &FoodStock) -> Result<i32, FoodError> {
+    let base = food.quantity.to_base();
+    conn.execute(
+        r"INSERT INTO food_stock
+        (user_id, name, stock_date, food_type, nutrient, storage_type, expiry_date, quantity_value, quantity_unit, reminder_days)
+        VALUES (:user_id, :name, :stock_date, :food_type, :nutrient, :storage_type, :expiry_date, :quantity_value, :quantity_unit,
+        :reminder_days)",
+        named_params! {
+            ":user_id": user_id,
+            ":name": food.name,
+            ":stock_date": food.stock_date.format("%Y-%m-%d").to_string(),
+            ":food_type": format!("{:?}", food.food_type),
+            ":nutrient": format!("{:?}", food.nutrient),
+            ":storage_type": format!("{:?}", food.storage_type),
+            ":expiry_date": food.expiry_date.format("%Y-%m-%d").to_string(),
+            ":quantity_value": f64::from(base.value()),
+            ":quantity_unit": base.symbol(),
+            ":reminder_days": reminder_days_column(&food.reminder_days),
+        },
+    )?;
+    Ok(conn.last_insert_rowid() as i32)
+}
+
+impl ToSql for ColumnValue {
+    fn to_sql(&self) -> rusqlite::Result<ToSqlOutput<'_>> {
+        match self {
+            ColumnValue::Text(text) => text.to_sql(),
+            ColumnValue::Float(number) => Ok(ToSqlOutput::Owned(Value::Real(f64::from(*number)))),
+            ColumnValue::Null => Ok(ToSqlOutput::Owned(Value::Null)),
+        }
+    }
+}
+
+impl SqliteFoodDb {
+    /// Opens (creating if needed) the database file at `path`; `:memory:` gives a private in-memory database.
+    /// Tables are created by [`FoodRepository::migrate`].
+    /// A `busy_timeout` makes writers wait that long for a locked database instead of failing at once.
+    pub fn open(path: &str, busy_timeout: Option<Duration>) -> Result<Self, FoodError> {
+        log::debug!("Opening SQLite food database at {}", path);
+        let conn = Connection::open(path)?;
+        if let Some(timeout) = busy_timeout {
+            conn.busy_timeout(timeout)?;
+        }
+
+        conn.execute_batch("PRAGMA foreign_keys = ON;")?;
+        Ok(Self { conn })
+    }
+
+    fn query_foods(&self, query: &str, params: &[(&str, &dyn ToSql)]) -> Result<Vec<StoredFood>, FoodError> {
+        let mut statement = self.conn.prepare(query)?;
+        let mut rows = statement.query(params)?;
+        let mut foods = Vec::new();
+        while let Some(row) = rows.next()? {
+            foods.push(stored_food(row)?);
+        }
+        Ok(foods)
+    }
+}
+
+impl FoodRepository for SqliteFoodDb {
+    fn migrate(&mut self) -> Result<Vec<String>, FoodError> {
+        migrate_sqlite(&mut self.conn)
+    }
+
+    fn add_food(&mut self, user_id: i32, food: FoodStock) -> Result<i32, FoodError> {
+        log::debug!("Starting add_food for: {} (user {})", food.name, user_id);
+        let id = insert_food(&self.conn, user_id, &food)?;
+        log::debug!("Insert successful, id {}", id);
+        Ok(id)
+    }
+
+    fn add_foods(&mut self, user_id: i32, foods: Vec<FoodStock>) -> Result<Vec<i32>, FoodError> {
+        log::debug!("Starting add_foods for {} items (user {})", foods.len(), user_id);
+        // Dropping the transaction on an error rolls back the rows inserted before it
+        let tx = self.conn.transaction()?;
+        let ids = foods
+            .iter()
+            .map(|food| insert_food(&tx, user_id, food))
+            .collect::<Result<Vec<i32>, _>>()?;
+        tx.commit()?;
+        log::debug!("Inserted {} items", ids.len());
+        Ok(ids)
+    }
+
+    fn get_food(&self, user_id: i32, id: i32) -> Result<Option<StoredFood>, FoodError> {
+        log::debug!("get_food called for item {} (user {})", id, user_id);
+        let query = format!(
+            "SELECT {} FROM food_stock WHERE id = :id AND user_id = :user_id AND status = :status",
+            FOOD_COLUMNS
+        );
+        let foods = self.query_foods(&query, named_params! { ":id": id, ":user_id": user_id, ":status": STATUS_ACTIVE })?;
+        Ok(foods.into_iter().next())
+    }
+
+    fn get_all_food(&self, user_id: i32) -> Result<Vec<StoredFood>, FoodError> {
+        log::debug!("Starting get_all_food for user {}", user_id);
+        let query = format!(
+            "SELECT {} FROM food_stock WHERE user_id = :user_id AND status = :status ORDER BY id",
+            FOOD_COLUMNS
+        );
+        let foods = self.query_foods(&query, named_params! { ":user_id": user_id, ":status": STATUS_ACTIVE })?;
+        log::debug!("Found {} food items", foods.len());
+        Ok(foods)
+    }
+
+    fn advanced_search(&self, user_id: i32, keyword: &str, field: &str) -> Result<Vec<StoredFood>, FoodError> {
+        log::debug!("advanced_search called for user {} with keyword='{}', field='{}'", user_id, keyword, field);
+        if !SEARCHABLE_FIELDS.contains(&field) {
+            return Err(FoodError::Validation(format!("Cannot search by '{}'", field)));
+        }
+
+        let query = format!(
+            "SELECT {} FROM food_stock WHERE user_id = :user_id AND status = :status AND {} LIKE :search_string ORDER BY id",
+            FOOD_COLUMNS, field
+        );
+        let search_pattern = format!("%{}%", keyword.trim());
+        let foods = self.query_foods(
+            &query,
+            named_params! { ":user_id": user_id, ":status": STATUS_ACTIVE, ":search_string": search_pattern },
+        )?;
+        log::debug!("advanced_search found {} results", foods.len());
+        Ok(foods)
+    }
+
+    fn search_by_expiry(
+        &self,
+        user_id: i32,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    ) -> Result<Vec<StoredFood>, FoodError> {
+        log::debug!("search_by_expiry called for user {} with range {:?}..={:?}", user_id, from, to);
+        // An open bound compares against NULL, which lets every date through
+        let query = format!(
+            "SELECT {} FROM food_stock WHERE user_id = :user_id AND status = :status
+            AND (:from_date IS NULL OR expiry_date >= :from_date)
+            AND (:to_date IS NULL OR expiry_date <= :to_date)
+            ORDER BY expiry_date ASC, name ASC",
+            FOOD_COLUMNS
+        );
+        let from = from.map(|date| date.format("%Y-%m-%d").to_string());
+        let to = to.map(|date| date.format("%Y-%m-%d").to_string());
+        let foods = self.query_foods(
+            &query,
+            named_params! { ":user_id": user_id, ":status": STATUS_ACTIVE, ":from_date": from, ":to_date": to },
+        )?;
+        log::debug!("search_by_expiry found {} results", foods.len());
+        Ok(foods)
+    }
+
+    fn update_food(&mut self, user_id: i32, id: i32, update: &FoodUpdate) -> Result<bool, FoodError> {
+        log::debug!("update_food called for item {} (user {})", id, user_id);
+        let owned: Option<i32> = self
+            .conn
+            .query_row(
+                "SELECT id FROM food_stock WHERE id = :id AND user_id = :user_id AND status = :status",
+                named_params! { ":id": id, ":user_id": user_id, ":status": STATUS_ACTIVE },
+                |row| row.get(0),
+            )
+            .optional()?;
+        if owned.is_none() {
+            log::debug!("No food item {} for user {}", id, user_id);
+            return Ok(false);
+        }
+
+        let columns = update_columns(update);
+        if columns.is_empty() {
+            return Ok(true);
+        }
+
+        // Positional parameters: the assignments in order, then id, user_id and status
+        let assignments: Vec<String> = columns.iter().map(|(column, _)| format!("{} = ?", column)).collect();
+        let query = format!(
+            "UPDATE food_stock SET {} WHERE id = ? AND user_id = ? AND status = ?",
+            assignments.join(", ")
+        );
+        let values: Vec<ColumnValue> = columns.into_iter().map(|(_, value)| value).collect();
+        let mut params: Vec<&dyn ToSql> = values.iter().map(|value| value as &dyn ToSql).collect();
+        params.push(&id);
+        params.push(&user_id);
+        params.push(&STATUS_ACTIVE);
+        self.conn.execute(&query, params_from_iter(params))?;
+        Ok(true)
+    }
+
+    fn delete_food(&mut self, user_id: i32, id: i32) -> Result<bool, FoodError> {
+        log::debug!("delete_food called for item {} (user {})", id, user_id);
+        let deleted = self.conn.execute(
+            "DELETE FROM food_stock WHERE id = :id AND user_id = :user_id AND status = :status",
+            named_params! { ":id": id, ":user_id": user_id, ":status": STATUS_ACTIVE },
+        )? > 0;
+        log::debug!("delete_food removed item: {}", deleted);
+        Ok(deleted)
+    }
+
+    fn consume(&mut self, user_id: i32, id: i32, amount: &Unit) -> Result<ConsumeOutcome, FoodError> {
+        log::debug!("consume called for item {} (user {}) with {}", id, user_id, amount);
+        let tx = self.conn.transaction()?;
+
+        let current: Option<(f32, String)> = tx
+            .query_row(
+                "SELECT quantity_value, quantity_unit FROM food_stock
+                WHERE id = :id AND user_id = :user_id AND status = :status",
+                named_params! { ":id": id, ":user_id": user_id, ":status": STATUS_ACTIVE },
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        let (stored_value, stored_unit) = match current {
+            Some(row) => row,
+            None => return Ok(ConsumeOutcome::NotFound),
+        };
+        let deduction = match Deduction::new(stored_value, &stored_unit, amount) {
+            Ok(deduction) => deduction,
+            Err(outcome) => return Ok(outcome),
+        };
+
+        tx.execute(
+            r"INSERT INTO food_consumption (food_stock_id, user_id, amount_value, amount_unit)
+            VALUES (:food_stock_id, :user_id, :amount_value, :amount_unit)",
+            named_params! {
+                ":food_stock_id": id,
+                ":user_id": user_id,
+                ":amount_value": f64::from(deduction.consumed_value),
+                ":amount_unit": deduction.stored.symbol(),
+            },
+        )?;
+        tx.execute(
+            "UPDATE food_stock SET quantity_value = :quantity_value, quantity_unit = :quantity_unit, status = :status WHERE id = :id",
+            named_params! {
+                ":quantity_value": f64::from(deduction.remaining_value),
+                ":quantity_unit": deduction.stored.symbol(),
+                ":status": deduction.status(),
+                ":id": id,
+            },
+        )?;
+        tx.commit()?;
+
+        Ok(deduction.into_outcome())
+    }
+
+    fn calendar_event(&self, user_id: i32, id: i32) -> Result<Option<LinkedEvent>, FoodError> {
+        let linked: Option<(Option<String>, Option<String>)> = self
+            .conn
+            .query_row(
+                "SELECT calendar_provider, calendar_event_id FROM food_stock WHERE id = :id AND user_id = :user_id",
+                named_params! { ":id": id, ":user_id": user_id },
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()?;
+        Ok(match linked {
+            Some((Some(provider), Some(event_id))) => Some(LinkedEvent { provider, event_id }),
+            _ => None,
+        })
+    }
+
+    fn set_calendar_event(&mut self, user_id: i32, id: i32, event: Option<&LinkedEvent>) -> Result<bool, FoodError> {
+        log::debug!("set_calendar_event called for item {} (user {})", id, user_id);
+        let updated = self.conn.execute(
+            "UPDATE food_stock SET calendar_provider = :provider, calendar_event_id = :event_id
+            WHERE id = :id AND user_id = :user_id",
+            named_params! {
+                ":provider": event.map(|event| event.provider.as_str()),
+                ":event_id": event.map(|event| event.event_id.as_str()),
+                ":id": id,
+                ":user_id": user_id,
+            },
+        )? > 0;
+        Ok(updated)
+    }
+
+    fn digest_event(&self, user_id: i32, date: NaiveDate) -> Result<Option<LinkedEvent>, FoodError> {
+        Ok(self
+            .conn
+            .query_row(
+                "SELECT calendar_provider, calendar_event_id FROM reminder_digests
+                WHERE user_id = :user_id AND expiry_date = :expiry_date",
+                named_params! { ":user_id": user_id, ":expiry_date": date.format("%Y-%m-%d").to_string() },
+                |row| Ok(LinkedEvent { provider: row.get(0)?, event_id: row.get(1)? }),
+            )
+            .optional()?)
+    }
+
+    fn set_digest_event(&mut self, user_id: i32, date: NaiveDate, event: Option<&LinkedEvent>) -> Result<(), FoodError> {
+        log::debug!("set_digest_event called for {} (user {})", date, user_id);
+        let expiry_date = date.format("%Y-%m-%d").to_string();
+        match event {
+            Some(event) => self.conn.execute(
+                r"INSERT INTO reminder_digests (user_id, expiry_date, calendar_provider, calendar_event_id)
+                VALUES (:user_id, :expiry_date, :provider, :event_id)
+                ON CONFLICT (user_id, expiry_date) DO UPDATE SET
+                    calendar_provider = excluded.calendar_provider,
+                    calendar_event_id = excluded.calendar_event_id",
+                named_params! {
+                    ":user_id": user_id,
+                    ":expiry_date": expiry_date,
+                    ":provider": event.provider,
+                    ":event_id": event.event_id,
+                },
+            )?,
+            None => self.conn.execute(
+                "DELETE FROM reminder_digests WHERE user_id = :user_id AND expiry_date = :expiry_date",
+                named_params! { ":user_id": user_id, ":expiry_date": expiry_date },
+            )?,
+        };
+        Ok(())
+    }
+
+    fn load_shelf_life_rules(&self) -> Result<Vec<RawShelfLifeRule>, FoodError> {
+        let mut statement = self
+            .conn
+            .prepare("SELECT food_type, storage_type, name_pattern, days FROM shelf_life_rules ORDER BY id")?;
+        let rules = statement
+            .query_map([], |row| {
+                Ok(RawShelfLifeRule {
+                    food_type: row.get(0)?,
+                    storage_type: row.get(1)?,
+                    name_pattern: row.get(2)?,
+                    days: row.get(3)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        log::debug!("Loaded {} shelf-life rules from the database", rules.len());
+        Ok(rules)
+    }
+}
+
+impl NotificationStore for SqliteFoodDb {
+    fn users_with_food_expiring(&self, to: NaiveDate) -> Result<Vec<i32>, FoodError> {
+        let mut statement = self.conn.prepare(
+            "SELECT DISTINCT user_id FROM food_stock WHERE status = :status AND expiry_date <= :to_date ORDER BY user_id",
+        )?;
+        let users = statement
+            .query_map(
+                named_params! { ":status": STATUS_ACTIVE, ":to_date": to.format("%Y-%m-%d").to_string() },
+                |row| row.get(0),
+            )?
+            .collect::<rusqlite::Result<Vec<i32>>>()?;
+        Ok(users)
+    }
+
+    fn notification_sent(&self, notification: &SentNotification) -> Result<bool, FoodError> {
+        let sent: Option<i32> = self
+            .conn
+            .query_row(
+                "SELECT 1 FROM expiry_notifications
+                WHERE food_id = :food_id AND expiry_date = :expiry_date AND stage = :stage AND channel = :channel",
+                named_params! {
+                    ":food_id": notification.food_id,
+                    ":expiry_date": notification.expiry_date.format("%Y-%m-%d").to_string(),
+                    ":stage": notification.stage,
+                    ":channel": notification.channel,
+                },
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(sent.is_some())
+    }
+
+    fn record_notification(&mut self, notification: &SentNotification, sent_at: DateTime<Utc>) -> Result<(), FoodError> {
+        self.conn.execute(
+            r"INSERT INTO expiry_notifications (user_id, food_id, expiry_date, stage, channel, sent_at)
+            VALUES (:user_id, :food_id, :expiry_date, :stage, :channel, :sent_at)
+            ON CONFLICT (food_id, expiry_date, stage, channel) DO UPDATE SET sent_at = excluded.sent_at",
+            named_params! {
+                ":user_id": notification.user_id,
+                ":food_id": notification.food_id,
+                ":expiry_date": notification.expiry_date.format("%Y-%m-%d").to_string(),
+                ":stage": notification.stage,
+                ":channel": notification.channel,
+                ":sent_at": sent_at.format(SENT_AT_FORMAT).to_string(),
+            },
+        )?;
+        Ok(())
+    }
+
+    fn last_notification(&self, user_id: i32, channel: &str) -> Result<Option<DateTime<Utc>>, FoodError> {
+        let sent_at: Option<String> = self.conn.query_row(
+            "SELECT MAX(sent_at) FROM expiry_notifications WHERE user_id = :user_id AND channel = :channel",
+            named_params! { ":user_id": user_id, ":channel": channel },
+            |row| row.get(0),
+        )?;
+        sent_at.as_deref().map(sent_at_from_column).transpose()
+    }
+}
+
+impl PreferenceStore for SqliteFoodDb {
+    fn reminder_preferences(&self, user_id: i32) -> Result<Option<ReminderPreferences>, FoodError> {
+        let stored: Option<(u32, String, String, String)> = self
+            .conn
+            .query_row(
+                "SELECT lead_days, reminder_time, timezone, reminder_mode FROM reminder_preferences WHERE user_id = :user_id",
+                named_params! { ":user_id": user_id },
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+            )
+            .optional()?;
+        stored
+            .map(|(lead_days, time, timezone, mode)| reminder_preferences_from_columns(lead_days, &time, &timezone, &mode))
+            .transpose()
+    }
+
+    fn set_reminder_preferences(&mut self, user_id: i32, preferences: &ReminderPreferences) -> Result<(), FoodError> {
+        log::debug!("set_reminder_preferences called for user {}", user_id);
+        self.conn.execute(
+            r"INSERT INTO reminder_preferences (user_id, lead_days, reminder_time, timezone, reminder_mode)
+            VALUES (:user_id, :lead_days, :reminder_time, :timezone, :reminder_mode)
+            ON CONFLICT (user_id) DO UPDATE SET
+                lead_days = excluded.lead_days,
+                reminder_time = excluded.reminder_time,
+                timezone = excluded.timezone,
+                reminder_mode = excluded.reminder_mode,
+                updated_at = CURRENT_TIMESTAMP",
+            named_params! {
+                ":user_id": user_id,
+                ":lead_days": preferences.lead_days,
+                ":reminder_time": preferences.time.format("%H:%M").to_string(),
+                ":timezone": preferences.timezone.name(),
+                ":reminder_mode": preferences.mode.as_str(),
+            },
+        )?;
+        Ok(())
+    }
+
+    fn email_digest(&self, user_id: i32) -> Result<Option<EmailDigest>, FoodError> {
+        let stored: Option<(String, String, String)> = self
+            .conn
+            .query_row(
+                "SELECT address, frequency, weekday FROM email_digests WHERE user_id = :user_id",
+                named_params! { ":user_id": user_id },
+                |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)),
+            )
+            .optional()?;
+        stored
+            .map(|(address, frequency, weekday)| email_digest_from_columns(address, &frequency, &weekday))
+            .transpose()
+    }
+
+    fn set_email_digest(&mut self, user_id: i32, digest: Option<&EmailDigest>) -> Result<(), FoodError> {
+        log::debug!("set_email_digest called for user {}", user_id);
+        match digest {
+            Some(digest) => self.conn.execute(
+                r"INSERT INTO email_digests (user_id, address, frequency, weekday)
+                VALUES (:user_id, :address, :frequency, :weekday)
+                ON CONFLICT (user_id) DO UPDATE SET
+                    address = excluded.address,
+                    frequency = excluded.frequency,
+                    weekday = excluded.weekday,
+                    updated_at = CURRENT_TIMESTAMP",
+                named_params! {
+                    ":user_id": user_id,
+                    ":address": digest.address,
+                    ":frequency": digest.frequency.as_str(),
+                    ":weekday": email_digest_weekday_column(digest),
+                },
+            )?,
+            None => self.conn.execute(
+                "DELETE FROM email_digests WHERE user_id = :user_id",
+                named_params! { ":user_id": user_id },
+            )?,
+        };
+        Ok(())
+    }
+}